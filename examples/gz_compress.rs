@@ -3,6 +3,7 @@ extern crate libdeflater;
 use std::vec::Vec;
 use libdeflater::{Compressor, CompressionLvl};
 
+#[allow(clippy::needless_borrow, clippy::slow_vector_initialization)]
 fn main() {
     let str_to_compress = "hello\n";
     let str_bytes = str_to_compress.as_bytes();
@@ -34,11 +34,12 @@ fn main() {
 
     println!("input data length = {}, expected output data length = {}", gz_data.len(), isize);
 
+    // decompress into the (uninitialized) spare capacity of the
+    // output buffer, rather than zero-filling it first
     let decompressed_data = {
         let mut decompressor = Decompressor::new();
-        let mut outbuf = Vec::new();
-        outbuf.resize(isize, 0);
-        decompressor.gzip_decompress(&gz_data, &mut outbuf).unwrap();
+        let mut outbuf = Vec::with_capacity(isize);
+        decompressor.gzip_decompress_append(&gz_data, &mut outbuf).unwrap();
         outbuf
     };
 
@@ -3,6 +3,7 @@
 #![allow(non_snake_case)]
 #![allow(dead_code)]
 #![allow(bad_style)]
+#![cfg_attr(not(test), no_std)]
 
 #[repr(C)]
 pub struct libdeflate_compressor { _unused : [ u8 ; 0 ] , }
@@ -12,15 +13,15 @@ pub struct libdeflate_decompressor { _unused : [ u8 ; 0 ] , }
 #[repr(C)]
 pub struct libdeflate_options {
     pub sizeof_options: usize,
-    pub malloc_func: Option<unsafe extern "C" fn(arg1: usize) -> *mut ::std::os::raw::c_void>,
-    pub free_func: Option<unsafe extern "C" fn(arg1: *mut ::std::os::raw::c_void)>,
+    pub malloc_func: Option<unsafe extern "C" fn(arg1: usize) -> *mut ::core::ffi::c_void>,
+    pub free_func: Option<unsafe extern "C" fn(arg1: *mut ::core::ffi::c_void)>,
 }
 
 pub const libdeflate_result_LIBDEFLATE_SUCCESS: libdeflate_result = 0;
 pub const libdeflate_result_LIBDEFLATE_BAD_DATA: libdeflate_result = 1;
 pub const libdeflate_result_LIBDEFLATE_SHORT_OUTPUT: libdeflate_result = 2;
 pub const libdeflate_result_LIBDEFLATE_INSUFFICIENT_SPACE: libdeflate_result = 3;
-pub type libdeflate_result = ::std::os::raw::c_uint;
+pub type libdeflate_result = ::core::ffi::c_uint;
 
 extern "C" {
     pub fn libdeflate_alloc_decompressor() -> *mut libdeflate_decompressor;
@@ -28,53 +29,53 @@ extern "C" {
     pub fn libdeflate_free_decompressor(decompressor: *mut libdeflate_decompressor);
 
     pub fn libdeflate_gzip_decompress(decompressor: *mut libdeflate_decompressor,
-                                      in_: *const ::std::os::raw::c_void,
+                                      in_: *const ::core::ffi::c_void,
                                       in_nbytes: usize ,
-                                      out: *mut ::std::os::raw::c_void,
+                                      out: *mut ::core::ffi::c_void,
                                       out_nbytes_avail: usize,
                                       actual_out_nbytes_ret: *mut usize) -> libdeflate_result;
 
     pub fn libdeflate_gzip_decompress_ex(decompressor: *mut libdeflate_decompressor,
-                                         in_: *const ::std::os::raw::c_void,
+                                         in_: *const ::core::ffi::c_void,
                                          in_nbytes: usize,
-                                         out: *mut ::std::os::raw::c_void,
+                                         out: *mut ::core::ffi::c_void,
                                          out_nbytes_avail: usize,
                                          actual_in_nbytes_ret: *mut usize,
                                          actual_out_nbytes_ret: *mut usize) -> libdeflate_result;
 
     pub fn libdeflate_zlib_decompress(decompressor: *mut libdeflate_decompressor,
-                                      in_: *const ::std::os::raw::c_void,
+                                      in_: *const ::core::ffi::c_void,
                                       in_nbytes: usize,
-                                      out: *mut ::std::os::raw::c_void,
+                                      out: *mut ::core::ffi::c_void,
                                       out_nbytes_avail: usize,
                                       actual_out_nbytes_ret: *mut usize) -> libdeflate_result;
 
     pub fn libdeflate_zlib_decompress_ex(decompressor: *mut libdeflate_decompressor,
-                                         in_: *const ::std::os::raw::c_void,
+                                         in_: *const ::core::ffi::c_void,
                                          in_nbytes: usize,
-                                         out: *mut ::std::os::raw::c_void,
+                                         out: *mut ::core::ffi::c_void,
                                          out_nbytes_avail: usize,
                                          actual_in_nbytes_ret: *mut usize,
                                          actual_out_nbytes_ret: *mut usize) -> libdeflate_result;
 
     pub fn libdeflate_deflate_decompress(decompressor: *mut libdeflate_decompressor,
-                                         in_: *const ::std::os::raw::c_void,
+                                         in_: *const ::core::ffi::c_void,
                                          in_nbytes: usize,
-                                         out: *mut ::std::os::raw::c_void,
+                                         out: *mut ::core::ffi::c_void,
                                          out_nbytes_avail: usize,
                                          actual_out_nbytes_ret: *mut usize) -> libdeflate_result;
     
     pub fn libdeflate_deflate_decompress_ex(decompressor: *mut libdeflate_decompressor, 
-                                            in_: *const ::std::os::raw::c_void,
+                                            in_: *const ::core::ffi::c_void,
                                             in_nbytes: usize,
-                                            out: *mut ::std::os::raw::c_void,
+                                            out: *mut ::core::ffi::c_void,
                                             out_nbytes_avail: usize,
                                             actual_in_nbytes_ret: *mut usize,
                                             actual_out_nbytes_ret: *mut usize) -> libdeflate_result;
 
-    pub fn libdeflate_alloc_compressor(compression_level: ::std::os::raw::c_int) -> *mut libdeflate_compressor;
+    pub fn libdeflate_alloc_compressor(compression_level: ::core::ffi::c_int) -> *mut libdeflate_compressor;
     
-    pub fn libdeflate_alloc_compressor_ex(compression_level: ::std::ffi::c_int,
+    pub fn libdeflate_alloc_compressor_ex(compression_level: ::core::ffi::c_int,
                                           options: *const libdeflate_options) -> *mut libdeflate_compressor;
 
     pub fn libdeflate_deflate_compress_bound(compressor: *mut libdeflate_compressor,
@@ -82,41 +83,41 @@ extern "C" {
 
 
     pub fn libdeflate_deflate_compress(compressor: *mut libdeflate_compressor,
-                                       in_: *const ::std::os::raw::c_void,
+                                       in_: *const ::core::ffi::c_void,
                                        in_nbytes: usize,
-                                       out: *mut ::std::os::raw::c_void,
+                                       out: *mut ::core::ffi::c_void,
                                        out_nbytes_avail: usize) -> usize;
 
     pub fn libdeflate_zlib_compress_bound(compressor: *mut libdeflate_compressor,
                                           in_nbytes: usize) -> usize;
 
     pub fn libdeflate_zlib_compress(compressor: *mut libdeflate_compressor,
-                                    in_: *const ::std::os::raw::c_void,
+                                    in_: *const ::core::ffi::c_void,
                                     in_nbytes: usize,
-                                    out: *mut ::std::os::raw::c_void,
+                                    out: *mut ::core::ffi::c_void,
                                     out_nbytes_avail: usize) -> usize;
 
     pub fn libdeflate_gzip_compress_bound(compressor: *mut libdeflate_compressor,
                                           in_nbytes: usize) -> usize;
 
     pub fn libdeflate_gzip_compress(compressor: *mut libdeflate_compressor,
-                                    in_: *const ::std::os::raw::c_void,
+                                    in_: *const ::core::ffi::c_void,
                                     in_nbytes: usize,
-                                    out: *mut ::std::os::raw::c_void,
+                                    out: *mut ::core::ffi::c_void,
                                     out_nbytes_avail: usize ) -> usize;
 
     pub fn libdeflate_free_compressor (compressor : * mut libdeflate_compressor);
 
     pub fn libdeflate_crc32(crc32: u32,
-                            buffer: *const ::std::os::raw::c_void,
+                            buffer: *const ::core::ffi::c_void,
                             len: usize) -> u32;
 
     pub fn libdeflate_adler32(adler32: u32,
-                              buffer: *const ::std::os::raw::c_void,
+                              buffer: *const ::core::ffi::c_void,
                               len: usize) -> u32;
 
-    pub fn libdeflate_set_memory_allocator(malloc_func: unsafe extern "C" fn(size: usize) -> *mut ::std::os::raw::c_void,
-                                           free_func: unsafe extern "C" fn(ptr: *mut ::std::os::raw::c_void));
+    pub fn libdeflate_set_memory_allocator(malloc_func: unsafe extern "C" fn(size: usize) -> *mut ::core::ffi::c_void,
+                                           free_func: unsafe extern "C" fn(ptr: *mut ::core::ffi::c_void));
 }
 
 // Basic tests for Rust-to-C bindings. These tests are just for quick
@@ -146,7 +147,7 @@ mod tests {
     fn can_make_decompressor_with_null_malloc_free() {
         unsafe {
             let options = libdeflate_options {
-                sizeof_options: std::mem::size_of::<libdeflate_options>(),
+                sizeof_options: core::mem::size_of::<libdeflate_options>(),
                 malloc_func: None,
                 free_func: None,
             };
@@ -0,0 +1,31 @@
+//! Exercises the `core`/`alloc` API of `libdeflater` from a `#![no_std]`
+//! crate. This only needs to compile.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use libdeflater::{bgzf, CompressionLvl, Compressor, DecompressionError, Decompressor};
+
+pub fn gzip_roundtrip(data: &[u8]) -> Result<Vec<u8>, DecompressionError> {
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let gz_data = compressor.gzip_compress_to_vec(data).unwrap();
+
+    let mut decompressor = Decompressor::new();
+    let mut out = Vec::with_capacity(data.len());
+    decompressor.gzip_decompress_append(&gz_data, &mut out)?;
+    Ok(out)
+}
+
+pub fn bgzf_roundtrip(data: &[u8]) -> Result<Vec<u8>, DecompressionError> {
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let bgzf_data = bgzf::compress(&mut compressor, data).unwrap();
+
+    let mut decompressor = Decompressor::new();
+    bgzf::decompress(&mut decompressor, &bgzf_data)
+}
+
+pub fn total_memory_usage() -> usize {
+    libdeflater::total_memory_usage()
+}
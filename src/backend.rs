@@ -0,0 +1,75 @@
+//! The DEFLATE implementation behind [`Compressor`], [`Decompressor`],
+//! [`Crc`], and [`Adler32`].
+//!
+//! By default, this is `libdeflate` (built from source by
+//! `libdeflate-sys`). With the `rust_backend` feature, it is a
+//! pure-Rust implementation built on `miniz_oxide` and `crc32fast`
+//! instead, which doesn't need a C toolchain. Both backends expose the
+//! same functions, which mirror `libdeflate`'s API, and `rust_backend`
+//! takes precedence if both are enabled.
+//!
+//! [`Compressor`]: ../struct.Compressor.html
+//! [`Decompressor`]: ../struct.Decompressor.html
+//! [`Crc`]: ../struct.Crc.html
+//! [`Adler32`]: ../struct.Adler32.html
+
+use core::mem::MaybeUninit;
+use crate::{CompressionLvl, DecompressOutcome, DecompressionResult};
+
+#[cfg(not(any(feature = "libdeflate", feature = "rust_backend")))]
+compile_error!("libdeflater requires a backend: enable either the `libdeflate` feature (the default) or the `rust_backend` feature");
+
+#[cfg(not(feature = "rust_backend"))]
+mod libdeflate;
+#[cfg(not(feature = "rust_backend"))]
+pub(crate) use self::libdeflate::*;
+
+#[cfg(feature = "rust_backend")]
+mod rust;
+#[cfg(feature = "rust_backend")]
+pub(crate) use self::rust::*;
+
+/// Signature shared by the backends' `*_compress` functions, which
+/// return the number of bytes written into the output buffer, or 0 if
+/// the compressed data does not fit.
+pub(crate) type RawCompressFn = unsafe fn(*mut CompressorState, &[u8], &mut [MaybeUninit<u8>]) -> usize;
+
+/// Signature shared by the backends' `*_decompress` functions. If
+/// `exact` is true, the data must decompress to exactly the length of
+/// the output buffer.
+pub(crate) type RawDecompressFn = unsafe fn(*mut DecompressorState,
+                                            &[u8],
+                                            &mut [MaybeUninit<u8>],
+                                            bool) -> DecompressionResult<DecompressOutcome>;
+
+/// Returns the XFL byte of the gzip header that `libdeflate` writes at
+/// `lvl`.
+pub(crate) fn gzip_xfl(lvl: CompressionLvl) -> u8 {
+    let lvl = i32::from(lvl);
+    if lvl < 2 {
+        4
+    } else if lvl >= 8 {
+        2
+    } else {
+        0
+    }
+}
+
+/// Returns the zlib header that `libdeflate` writes at `lvl`.
+pub(crate) fn zlib_header(lvl: CompressionLvl) -> [u8; 2] {
+    let lvl = i32::from(lvl);
+    let flevel = if lvl < 2 {
+        0
+    } else if lvl < 6 {
+        1
+    } else if lvl < 8 {
+        2
+    } else {
+        3
+    };
+    // CM = 8 (DEFLATE), CINFO = 7 (32 KiB window)
+    let cmf = 0x78u8;
+    let flg = flevel << 6;
+    let fcheck = 31 - (u16::from_be_bytes([cmf, flg]) % 31);
+    [cmf, flg | fcheck as u8]
+}
@@ -0,0 +1,182 @@
+//! The default backend: thin wrappers around `libdeflate`'s C API.
+
+use core::ffi::c_void;
+use core::mem::MaybeUninit;
+use libdeflate_sys::{libdeflate_alloc_compressor_ex,
+                     libdeflate_alloc_decompressor_ex,
+                     libdeflate_free_compressor,
+                     libdeflate_free_decompressor,
+                     libdeflate_deflate_compress_bound,
+                     libdeflate_deflate_compress,
+                     libdeflate_zlib_compress_bound,
+                     libdeflate_zlib_compress,
+                     libdeflate_gzip_compress_bound,
+                     libdeflate_gzip_compress,
+                     libdeflate_deflate_decompress_ex,
+                     libdeflate_zlib_decompress_ex,
+                     libdeflate_gzip_decompress_ex,
+                     libdeflate_result,
+                     libdeflate_result_LIBDEFLATE_SUCCESS,
+                     libdeflate_result_LIBDEFLATE_BAD_DATA,
+                     libdeflate_result_LIBDEFLATE_SHORT_OUTPUT,
+                     libdeflate_result_LIBDEFLATE_INSUFFICIENT_SPACE,
+                     libdeflate_crc32,
+                     libdeflate_adler32};
+use crate::malloc_wrapper::{self, BoxedAllocator};
+use crate::{CompressionLvl, DecompressOutcome, DecompressionError, DecompressionResult, MemoryUsage};
+
+pub(crate) use libdeflate_sys::libdeflate_compressor as CompressorState;
+pub(crate) use libdeflate_sys::libdeflate_decompressor as DecompressorState;
+
+/// Signature shared by libdeflate's `*_compress` functions.
+type CompressFn = unsafe extern "C" fn(*mut CompressorState,
+                                       *const c_void,
+                                       usize,
+                                       *mut c_void,
+                                       usize) -> usize;
+
+/// Signature shared by libdeflate's `*_decompress_ex` functions.
+type DecompressExFn = unsafe extern "C" fn(*mut DecompressorState,
+                                           *const c_void,
+                                           usize,
+                                           *mut c_void,
+                                           usize,
+                                           *mut usize,
+                                           *mut usize) -> libdeflate_result;
+
+pub(crate) unsafe fn alloc_compressor(lvl: CompressionLvl,
+                                      allocator: Option<&BoxedAllocator>,
+                                      usage: &mut MemoryUsage) -> *mut CompressorState {
+    malloc_wrapper::with_context(allocator, usage, || {
+        libdeflate_alloc_compressor_ex(i32::from(lvl), &malloc_wrapper::OPTIONS)
+    })
+}
+
+pub(crate) unsafe fn free_compressor(p: *mut CompressorState,
+                                     allocator: Option<&BoxedAllocator>,
+                                     usage: &mut MemoryUsage) {
+    malloc_wrapper::with_context(allocator, usage, || libdeflate_free_compressor(p));
+}
+
+/// Returns the number of bytes that `alloc_compressor` allocates at
+/// `lvl`.
+pub(crate) fn compressor_memory(lvl: CompressionLvl) -> usize {
+    malloc_wrapper::probe_allocation_size(|| unsafe {
+        libdeflate_alloc_compressor_ex(i32::from(lvl), &malloc_wrapper::OPTIONS);
+    })
+}
+
+pub(crate) unsafe fn alloc_decompressor(allocator: Option<&BoxedAllocator>,
+                                        usage: &mut MemoryUsage) -> *mut DecompressorState {
+    malloc_wrapper::with_context(allocator, usage, || {
+        libdeflate_alloc_decompressor_ex(&malloc_wrapper::OPTIONS)
+    })
+}
+
+pub(crate) unsafe fn free_decompressor(p: *mut DecompressorState,
+                                       allocator: Option<&BoxedAllocator>,
+                                       usage: &mut MemoryUsage) {
+    malloc_wrapper::with_context(allocator, usage, || libdeflate_free_decompressor(p));
+}
+
+pub(crate) unsafe fn deflate_compress_bound(p: *mut CompressorState, n_bytes: usize) -> usize {
+    libdeflate_deflate_compress_bound(p, n_bytes)
+}
+
+pub(crate) unsafe fn zlib_compress_bound(p: *mut CompressorState, n_bytes: usize) -> usize {
+    libdeflate_zlib_compress_bound(p, n_bytes)
+}
+
+pub(crate) unsafe fn gzip_compress_bound(p: *mut CompressorState, n_bytes: usize) -> usize {
+    libdeflate_gzip_compress_bound(p, n_bytes)
+}
+
+pub(crate) unsafe fn deflate_compress(p: *mut CompressorState, in_data: &[u8], out: &mut [MaybeUninit<u8>]) -> usize {
+    compress(libdeflate_deflate_compress, p, in_data, out)
+}
+
+pub(crate) unsafe fn zlib_compress(p: *mut CompressorState, in_data: &[u8], out: &mut [MaybeUninit<u8>]) -> usize {
+    compress(libdeflate_zlib_compress, p, in_data, out)
+}
+
+pub(crate) unsafe fn gzip_compress(p: *mut CompressorState, in_data: &[u8], out: &mut [MaybeUninit<u8>]) -> usize {
+    compress(libdeflate_gzip_compress, p, in_data, out)
+}
+
+unsafe fn compress(compress: CompressFn,
+                   p: *mut CompressorState,
+                   in_data: &[u8],
+                   out: &mut [MaybeUninit<u8>]) -> usize {
+    compress(p,
+             in_data.as_ptr() as *const c_void,
+             in_data.len(),
+             out.as_mut_ptr() as *mut c_void,
+             out.len())
+}
+
+pub(crate) unsafe fn deflate_decompress(p: *mut DecompressorState,
+                                        in_data: &[u8],
+                                        out: &mut [MaybeUninit<u8>],
+                                        exact: bool) -> DecompressionResult<DecompressOutcome> {
+    decompress_ex(libdeflate_deflate_decompress_ex, "libdeflate_deflate_decompress_ex", p, in_data, out, exact)
+}
+
+pub(crate) unsafe fn zlib_decompress(p: *mut DecompressorState,
+                                     in_data: &[u8],
+                                     out: &mut [MaybeUninit<u8>],
+                                     exact: bool) -> DecompressionResult<DecompressOutcome> {
+    decompress_ex(libdeflate_zlib_decompress_ex, "libdeflate_zlib_decompress_ex", p, in_data, out, exact)
+}
+
+pub(crate) unsafe fn gzip_decompress(p: *mut DecompressorState,
+                                     in_data: &[u8],
+                                     out: &mut [MaybeUninit<u8>],
+                                     exact: bool) -> DecompressionResult<DecompressOutcome> {
+    decompress_ex(libdeflate_gzip_decompress_ex, "libdeflate_gzip_decompress_ex", p, in_data, out, exact)
+}
+
+/// Calls one of libdeflate's `*_decompress_ex` functions.
+///
+/// If `exact` is true, the output size is not requested, which makes
+/// `libdeflate` require that the data decompresses to exactly
+/// `out.len()` bytes.
+unsafe fn decompress_ex(decompress_ex: DecompressExFn,
+                        fn_name: &str,
+                        p: *mut DecompressorState,
+                        in_data: &[u8],
+                        out: &mut [MaybeUninit<u8>],
+                        exact: bool) -> DecompressionResult<DecompressOutcome> {
+    let mut in_nbytes = 0;
+    let mut out_nbytes = out.len();
+    let out_nbytes_ptr: *mut usize = if exact { core::ptr::null_mut() } else { &mut out_nbytes };
+    let ret = decompress_ex(p,
+                            in_data.as_ptr() as *const c_void,
+                            in_data.len(),
+                            out.as_mut_ptr() as *mut c_void,
+                            out.len(),
+                            &mut in_nbytes,
+                            out_nbytes_ptr);
+    check_result(ret, fn_name)?;
+    Ok(DecompressOutcome { consumed: in_nbytes, produced: out_nbytes })
+}
+
+/// Converts the result of a libdeflate decompression function into a
+/// `DecompressionResult`.
+#[allow(non_upper_case_globals)]
+fn check_result(ret: libdeflate_result, fn_name: &str) -> DecompressionResult<()> {
+    match ret {
+        libdeflate_result_LIBDEFLATE_SUCCESS => Ok(()),
+        libdeflate_result_LIBDEFLATE_BAD_DATA => Err(DecompressionError::BadData),
+        libdeflate_result_LIBDEFLATE_SHORT_OUTPUT => Err(DecompressionError::ShortOutput),
+        libdeflate_result_LIBDEFLATE_INSUFFICIENT_SPACE => Err(DecompressionError::InsufficientSpace),
+        _ => panic!("{} returned an unknown error type: this is an internal bug that **must** be fixed", fn_name),
+    }
+}
+
+pub(crate) fn crc32(crc: u32, data: &[u8]) -> u32 {
+    unsafe { libdeflate_crc32(crc, data.as_ptr() as *const c_void, data.len()) }
+}
+
+pub(crate) fn adler32(adler: u32, data: &[u8]) -> u32 {
+    unsafe { libdeflate_adler32(adler, data.as_ptr() as *const c_void, data.len()) }
+}
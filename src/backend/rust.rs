@@ -0,0 +1,245 @@
+//! The pure-Rust backend (the `rust_backend` feature), built on
+//! `miniz_oxide` (DEFLATE), `crc32fast` (CRC32), and `miniz_oxide`'s
+//! Adler32.
+//!
+//! `miniz_oxide` doesn't write zlib or gzip headers the way
+//! `libdeflate` does, so only raw DEFLATE data is handed to it and the
+//! zlib and gzip wrappers are written and checked here, with the same
+//! headers and error semantics as `libdeflate`.
+//!
+//! The compressor/decompressor state is allocated with
+//! `malloc_wrapper::allocate`, so that custom allocators and memory
+//! accounting apply to it. `miniz_oxide` allocates a compressor's
+//! internal buffers itself, with the global allocator, so those are
+//! not counted.
+//!
+//! `miniz_oxide` only writes into initialized buffers, so
+//! possibly-uninitialized output buffers are zero-filled first.
+
+use core::alloc::Layout;
+use core::mem::MaybeUninit;
+use miniz_oxide::deflate::core::{compress, CompressionStrategy, CompressorOxide, TDEFLFlush, TDEFLStatus};
+use miniz_oxide::inflate::core::inflate_flags::TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF;
+use miniz_oxide::inflate::core::{decompress, DecompressorOxide};
+use miniz_oxide::inflate::TINFLStatus;
+use miniz_oxide::DataFormat;
+use crate::backend::{gzip_xfl, zlib_header};
+use crate::malloc_wrapper::{self, BoxedAllocator};
+use crate::{assume_init, check_zlib_header, gzip, zeroed, CompressionLvl, DecompressOutcome, DecompressionError, DecompressionResult, MemoryUsage, ZLIB_FOOTER_SIZE, ZLIB_HEADER_SIZE};
+
+/// Highest compression level supported by `miniz_oxide`. Higher
+/// `libdeflate` levels are clamped to it.
+const MAX_MINIZ_LVL: i32 = 10;
+
+pub(crate) struct CompressorState {
+    inner: CompressorOxide,
+    lvl: CompressionLvl,
+}
+
+pub(crate) struct DecompressorState {
+    inner: DecompressorOxide,
+}
+
+/// Allocates a `T` in the context of `allocator` and `usage`, and
+/// initializes it with `init`. Returns null if the allocation fails.
+unsafe fn alloc_state<T>(allocator: Option<&BoxedAllocator>,
+                         usage: &mut MemoryUsage,
+                         init: impl FnOnce() -> T) -> *mut T {
+    let p = malloc_wrapper::with_context(allocator, usage, || {
+        malloc_wrapper::allocate(Layout::new::<T>())
+    }) as *mut T;
+    if !p.is_null() {
+        p.write(init());
+    }
+    p
+}
+
+/// Drops and frees a `T` that was allocated by `alloc_state`.
+unsafe fn free_state<T>(p: *mut T,
+                        allocator: Option<&BoxedAllocator>,
+                        usage: &mut MemoryUsage) {
+    core::ptr::drop_in_place(p);
+    malloc_wrapper::with_context(allocator, usage, || {
+        malloc_wrapper::deallocate(p as *mut u8, Layout::new::<T>())
+    });
+}
+
+pub(crate) unsafe fn alloc_compressor(lvl: CompressionLvl,
+                                      allocator: Option<&BoxedAllocator>,
+                                      usage: &mut MemoryUsage) -> *mut CompressorState {
+    alloc_state(allocator, usage, || {
+        let miniz_lvl = core::cmp::min(i32::from(lvl), MAX_MINIZ_LVL) as u8;
+        let inner = CompressorOxide::with_params(DataFormat::Raw, miniz_lvl, CompressionStrategy::Default, 15);
+        CompressorState { inner, lvl }
+    })
+}
+
+pub(crate) unsafe fn free_compressor(p: *mut CompressorState,
+                                     allocator: Option<&BoxedAllocator>,
+                                     usage: &mut MemoryUsage) {
+    free_state(p, allocator, usage)
+}
+
+/// Returns the number of bytes that `alloc_compressor` allocates at
+/// `lvl`. This doesn't include the buffers that `miniz_oxide` boxes
+/// with the global allocator, which make up most of its memory.
+pub(crate) fn compressor_memory(_lvl: CompressionLvl) -> usize {
+    Layout::new::<CompressorState>().size()
+}
+
+pub(crate) unsafe fn alloc_decompressor(allocator: Option<&BoxedAllocator>,
+                                        usage: &mut MemoryUsage) -> *mut DecompressorState {
+    alloc_state(allocator, usage, || DecompressorState { inner: DecompressorOxide::new() })
+}
+
+pub(crate) unsafe fn free_decompressor(p: *mut DecompressorState,
+                                       allocator: Option<&BoxedAllocator>,
+                                       usage: &mut MemoryUsage) {
+    free_state(p, allocator, usage)
+}
+
+/// `miniz_oxide`'s bound on the size of compressed data (from
+/// `mz_compressBound`), which also covers its short static-Huffman
+/// blocks.
+pub(crate) unsafe fn deflate_compress_bound(_p: *mut CompressorState, n_bytes: usize) -> usize {
+    let max_blocks = n_bytes / (31 * 1024) + 1;
+    let stored = n_bytes.saturating_add(max_blocks * 5);
+    let expanded = n_bytes.saturating_add(n_bytes / 10);
+    core::cmp::max(stored, expanded).saturating_add(128)
+}
+
+pub(crate) unsafe fn zlib_compress_bound(p: *mut CompressorState, n_bytes: usize) -> usize {
+    deflate_compress_bound(p, n_bytes).saturating_add(ZLIB_HEADER_SIZE + ZLIB_FOOTER_SIZE)
+}
+
+pub(crate) unsafe fn gzip_compress_bound(p: *mut CompressorState, n_bytes: usize) -> usize {
+    deflate_compress_bound(p, n_bytes).saturating_add(gzip::MIN_HEADER_SIZE + gzip::FOOTER_SIZE)
+}
+
+pub(crate) unsafe fn deflate_compress(p: *mut CompressorState, in_data: &[u8], out: &mut [MaybeUninit<u8>]) -> usize {
+    let state = &mut *p;
+    state.inner.reset();
+    match compress(&mut state.inner, in_data, zeroed(out), TDEFLFlush::Finish) {
+        (TDEFLStatus::Done, _, written) => written,
+        _ => 0,
+    }
+}
+
+pub(crate) unsafe fn zlib_compress(p: *mut CompressorState, in_data: &[u8], out: &mut [MaybeUninit<u8>]) -> usize {
+    if out.len() < ZLIB_HEADER_SIZE + ZLIB_FOOTER_SIZE {
+        return 0;
+    }
+    let deflate_end = out.len() - ZLIB_FOOTER_SIZE;
+    let deflate_sz = deflate_compress(p, in_data, &mut out[ZLIB_HEADER_SIZE..deflate_end]);
+    if deflate_sz == 0 {
+        return 0;
+    }
+
+    let footer_start = ZLIB_HEADER_SIZE + deflate_sz;
+    write(&mut out[..ZLIB_HEADER_SIZE], &zlib_header((*p).lvl));
+    write(&mut out[footer_start..], &adler32(1, in_data).to_be_bytes());
+    footer_start + ZLIB_FOOTER_SIZE
+}
+
+pub(crate) unsafe fn gzip_compress(p: *mut CompressorState, in_data: &[u8], out: &mut [MaybeUninit<u8>]) -> usize {
+    if out.len() < gzip::MIN_HEADER_SIZE + gzip::FOOTER_SIZE {
+        return 0;
+    }
+    let deflate_end = out.len() - gzip::FOOTER_SIZE;
+    let deflate_sz = deflate_compress(p, in_data, &mut out[gzip::MIN_HEADER_SIZE..deflate_end]);
+    if deflate_sz == 0 {
+        return 0;
+    }
+
+    // ID1, ID2, CM, FLG, MTIME, XFL, OS
+    let header = [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, gzip_xfl((*p).lvl), gzip::OS_UNKNOWN];
+    let footer_start = gzip::MIN_HEADER_SIZE + deflate_sz;
+    write(&mut out[..gzip::MIN_HEADER_SIZE], &header);
+    write(&mut out[footer_start..], &crc32(0, in_data).to_le_bytes());
+    write(&mut out[footer_start + 4..], &(in_data.len() as u32).to_le_bytes());
+    footer_start + gzip::FOOTER_SIZE
+}
+
+pub(crate) unsafe fn deflate_decompress(p: *mut DecompressorState,
+                                        in_data: &[u8],
+                                        out: &mut [MaybeUninit<u8>],
+                                        exact: bool) -> DecompressionResult<DecompressOutcome> {
+    let outcome = inflate(&mut *p, in_data, out)?;
+    check_exact(outcome, out.len(), exact)?;
+    Ok(outcome)
+}
+
+pub(crate) unsafe fn zlib_decompress(p: *mut DecompressorState,
+                                     in_data: &[u8],
+                                     out: &mut [MaybeUninit<u8>],
+                                     exact: bool) -> DecompressionResult<DecompressOutcome> {
+    check_zlib_header(in_data).map_err(|_| DecompressionError::BadData)?;
+    let outcome = inflate(&mut *p, &in_data[ZLIB_HEADER_SIZE..], out)?;
+    check_exact(outcome, out.len(), exact)?;
+
+    let footer_start = ZLIB_HEADER_SIZE + outcome.consumed;
+    let footer = in_data.get(footer_start..footer_start + ZLIB_FOOTER_SIZE)
+        .ok_or(DecompressionError::BadData)?;
+    let expected = u32::from_be_bytes([footer[0], footer[1], footer[2], footer[3]]);
+    if expected != adler32(1, assume_init(&out[..outcome.produced])) {
+        return Err(DecompressionError::BadData);
+    }
+
+    Ok(DecompressOutcome { consumed: footer_start + ZLIB_FOOTER_SIZE, produced: outcome.produced })
+}
+
+pub(crate) unsafe fn gzip_decompress(p: *mut DecompressorState,
+                                     in_data: &[u8],
+                                     out: &mut [MaybeUninit<u8>],
+                                     exact: bool) -> DecompressionResult<DecompressOutcome> {
+    let header_len = gzip::header_len(in_data).map_err(|_| DecompressionError::BadData)?;
+    let outcome = inflate(&mut *p, &in_data[header_len..], out)?;
+    check_exact(outcome, out.len(), exact)?;
+
+    let footer_start = header_len + outcome.consumed;
+    let footer = in_data.get(footer_start..footer_start + gzip::FOOTER_SIZE)
+        .ok_or(DecompressionError::BadData)?;
+    gzip::check_footer(footer, assume_init(&out[..outcome.produced]))
+        .map_err(|_| DecompressionError::BadData)?;
+
+    Ok(DecompressOutcome { consumed: footer_start + gzip::FOOTER_SIZE, produced: outcome.produced })
+}
+
+/// Decompresses the raw DEFLATE stream at the start of `in_data`.
+fn inflate(state: &mut DecompressorState,
+           in_data: &[u8],
+           out: &mut [MaybeUninit<u8>]) -> DecompressionResult<DecompressOutcome> {
+    state.inner.init();
+    match decompress(&mut state.inner, in_data, zeroed(out), 0, TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF) {
+        (TINFLStatus::Done, consumed, produced) => Ok(DecompressOutcome { consumed, produced }),
+        (TINFLStatus::HasMoreOutput, _, _) => Err(DecompressionError::InsufficientSpace),
+        _ => Err(DecompressionError::BadData),
+    }
+}
+
+/// Returns `DecompressionError::ShortOutput` if `exact` is true and
+/// the data did not fill the output buffer, like `libdeflate` does.
+fn check_exact(outcome: DecompressOutcome, out_len: usize, exact: bool) -> DecompressionResult<()> {
+    if exact && outcome.produced != out_len {
+        Err(DecompressionError::ShortOutput)
+    } else {
+        Ok(())
+    }
+}
+
+/// Copies `data` to the start of `out`, which must be long enough.
+fn write(out: &mut [MaybeUninit<u8>], data: &[u8]) {
+    for (dst, src) in out.iter_mut().zip(data) {
+        *dst = MaybeUninit::new(*src);
+    }
+}
+
+pub(crate) fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new_with_initial(crc);
+    hasher.update(data);
+    hasher.finalize()
+}
+
+pub(crate) fn adler32(adler: u32, data: &[u8]) -> u32 {
+    miniz_oxide::mz_adler32_oxide(adler, data)
+}
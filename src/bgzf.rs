@@ -0,0 +1,532 @@
+//! Support for [BGZF](https://samtools.github.io/hts-specs/SAMv1.pdf)
+//! (blocked gzip) data, as used by BAM, tabix, `bgzip`, etc.
+//!
+//! BGZF data is a series of gzip members ("blocks") that are each at
+//! most [`MAX_BLOCK_SIZE`] bytes long. Every block carries a `BC`
+//! FEXTRA subfield that records the total size of the block (BSIZE),
+//! so that blocks can be located without decompressing them. BGZF
+//! data is terminated by an empty block ([`EOF_MARKER`]).
+//!
+//! Because each block is a complete gzip member, BGZF data is also
+//! valid multi-member gzip data and can be read by any gunzip.
+//!
+//! # Random access
+//!
+//! A byte of uncompressed BGZF data can be addressed with a
+//! [`VirtualOffset`], which combines the offset of its block in the
+//! compressed data with its offset within the block's uncompressed
+//! data, and read with [`read_at`]. A [`GziIndex`] (the `.gzi` format
+//! written by `bgzip -i`) maps plain uncompressed offsets to blocks,
+//! so that [`read_range`] and [`read_range_from`] can fetch an
+//! arbitrary range of uncompressed data by decompressing only the
+//! blocks that cover it.
+//!
+//! [`MAX_BLOCK_SIZE`]: constant.MAX_BLOCK_SIZE.html
+//! [`EOF_MARKER`]: constant.EOF_MARKER.html
+//! [`VirtualOffset`]: struct.VirtualOffset.html
+//! [`GziIndex`]: struct.GziIndex.html
+//! [`read_at`]: fn.read_at.html
+//! [`read_range`]: fn.read_range.html
+//! [`read_range_from`]: fn.read_range_from.html
+
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use crate::gzip::{self, GzipHeader, FOOTER_SIZE};
+use crate::{CompressionError, CompressionResult, Compressor, Crc, DecompressOutcome, DecompressionError, DecompressionResult, Decompressor};
+
+/// The maximum size, in bytes, of a single BGZF block (header,
+/// compressed data, and trailer).
+pub const MAX_BLOCK_SIZE: usize = 0x10000;
+
+/// The maximum number of uncompressed bytes stored in each block
+/// written by [`compress`](fn.compress.html). This is the same value
+/// that htslib uses, and guarantees that even incompressible data
+/// fits in [`MAX_BLOCK_SIZE`](constant.MAX_BLOCK_SIZE.html).
+pub const MAX_BLOCK_DATA_SIZE: usize = 0xff00;
+
+/// The empty block that marks the end of BGZF data.
+pub const EOF_MARKER: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00,
+];
+
+/// The size of the header of each BGZF block.
+const HEADER_SIZE: usize = 18;
+
+/// The FEXTRA subfield ID that holds a block's BSIZE.
+const BSIZE_SUBFIELD_ID: [u8; 2] = *b"BC";
+
+/// Compresses `data` (at most
+/// [`MAX_BLOCK_DATA_SIZE`](constant.MAX_BLOCK_DATA_SIZE.html) bytes)
+/// into a single BGZF block, writing the block into `out`. Returns the
+/// number of bytes written into `out`.
+///
+/// An `out` buffer of [`MAX_BLOCK_SIZE`](constant.MAX_BLOCK_SIZE.html)
+/// bytes is always large enough.
+///
+/// # Panics
+///
+/// Panics if `data` is longer than `MAX_BLOCK_DATA_SIZE`.
+pub fn compress_block(compressor: &mut Compressor,
+                      data: &[u8],
+                      out: &mut [u8]) -> CompressionResult<usize> {
+    assert!(data.len() <= MAX_BLOCK_DATA_SIZE, "data is too large to fit in a single BGZF block");
+
+    let out_len = core::cmp::min(out.len(), MAX_BLOCK_SIZE);
+    if out_len < HEADER_SIZE + FOOTER_SIZE {
+        return Err(CompressionError::InsufficientSpace);
+    }
+
+    let deflate_sz = compressor.deflate_compress(data, &mut out[HEADER_SIZE..out_len - FOOTER_SIZE])?;
+    let footer_start = HEADER_SIZE + deflate_sz;
+    let block_size = footer_start + FOOTER_SIZE;
+
+    out[..HEADER_SIZE].copy_from_slice(&EOF_MARKER[..HEADER_SIZE]);
+    out[16..18].copy_from_slice(&((block_size - 1) as u16).to_le_bytes());
+
+    let mut crc = Crc::new();
+    crc.update(data);
+    out[footer_start..footer_start + 4].copy_from_slice(&crc.sum().to_le_bytes());
+    out[footer_start + 4..block_size].copy_from_slice(&(data.len() as u32).to_le_bytes());
+
+    Ok(block_size)
+}
+
+/// Compresses `data` into BGZF blocks of at most
+/// [`MAX_BLOCK_DATA_SIZE`](constant.MAX_BLOCK_DATA_SIZE.html)
+/// uncompressed bytes each, followed by the
+/// [`EOF_MARKER`](constant.EOF_MARKER.html) block.
+pub fn compress(compressor: &mut Compressor, data: &[u8]) -> CompressionResult<Vec<u8>> {
+    let n_blocks = data.len().div_ceil(MAX_BLOCK_DATA_SIZE);
+    let mut out = Vec::with_capacity(n_blocks * HEADER_SIZE + data.len() + EOF_MARKER.len());
+    let mut block = vec![0; MAX_BLOCK_SIZE];
+
+    for chunk in data.chunks(MAX_BLOCK_DATA_SIZE) {
+        let sz = compress_block(compressor, chunk, &mut block)?;
+        out.extend_from_slice(&block[..sz]);
+    }
+    out.extend_from_slice(&EOF_MARKER);
+
+    Ok(out)
+}
+
+/// Returns the total size (BSIZE + 1) of the BGZF block at the start
+/// of `data`, read from the block's `BC` FEXTRA subfield. Only the
+/// block's header needs to be present in `data`.
+///
+/// Returns an error if `data` does not start with a valid gzip header
+/// (see [`GzipHeader::parse`](../gzip/struct.GzipHeader.html#method.parse)),
+/// or `DecompressionError::BadData` if the header is not a valid BGZF
+/// block header.
+pub fn block_size(data: &[u8]) -> DecompressionResult<usize> {
+    let (header, header_end) = GzipHeader::parse(data)?;
+    block_size_from_header(&header, header_end)
+}
+
+fn block_size_from_header(header: &GzipHeader, header_end: usize) -> DecompressionResult<usize> {
+    let bsize = match header.extra_subfield(BSIZE_SUBFIELD_ID) {
+        Some(&[lo, hi]) => u16::from_le_bytes([lo, hi]) as usize + 1,
+        _ => return Err(DecompressionError::BadData),
+    };
+    if bsize < header_end + FOOTER_SIZE {
+        return Err(DecompressionError::BadData);
+    }
+    Ok(bsize)
+}
+
+/// Returns the uncompressed size of the BGZF block at the start of
+/// `data`, read from the block's ISIZE trailer.
+///
+/// Returns `DecompressionError::Truncated` if `data` does not contain
+/// the whole block, or `DecompressionError::BadData` if the ISIZE is
+/// larger than any valid block can hold.
+pub fn block_data_size(data: &[u8]) -> DecompressionResult<usize> {
+    let bsize = block_size(data)?;
+    if data.len() < bsize {
+        return Err(DecompressionError::Truncated);
+    }
+    let isize_bytes = &data[bsize - 4..bsize];
+    let isize = u32::from_le_bytes([isize_bytes[0], isize_bytes[1], isize_bytes[2], isize_bytes[3]]) as usize;
+    if isize > MAX_BLOCK_SIZE {
+        return Err(DecompressionError::BadData);
+    }
+    Ok(isize)
+}
+
+/// Decompresses the BGZF block at the start of `data` and writes the
+/// decompressed data into `out`.
+///
+/// The block's BSIZE field, CRC32, and ISIZE are validated. Returns a
+/// [`DecompressOutcome`](../struct.DecompressOutcome.html) holding
+/// the size of the block (the number of bytes consumed from `data`)
+/// and the number of decompressed bytes written into `out`, or an
+/// error (see [`DecompressionError`](../enum.DecompressionError.html)
+/// for error cases).
+pub fn decompress_block(decompressor: &mut Decompressor,
+                        data: &[u8],
+                        out: &mut [u8]) -> DecompressionResult<DecompressOutcome> {
+    let (header, header_end) = GzipHeader::parse(data)?;
+    let bsize = block_size_from_header(&header, header_end)?;
+    if data.len() < bsize {
+        return Err(DecompressionError::Truncated);
+    }
+
+    let footer_start = bsize - FOOTER_SIZE;
+    let produced = decompressor.deflate_decompress(&data[header_end..footer_start], out)?;
+    gzip::check_footer(&data[footer_start..bsize], &out[..produced])?;
+
+    Ok(DecompressOutcome { consumed: bsize, produced })
+}
+
+/// Decompresses `data` (a buffer containing BGZF blocks) block by
+/// block into a newly-allocated `Vec`.
+///
+/// Each block is validated (see
+/// [`decompress_block`](fn.decompress_block.html)), and `data` must
+/// end with the [`EOF_MARKER`](constant.EOF_MARKER.html) block.
+/// Returns `DecompressionError::Truncated` if it does not.
+pub fn decompress(decompressor: &mut Decompressor, data: &[u8]) -> DecompressionResult<Vec<u8>> {
+    if !data.ends_with(&EOF_MARKER) {
+        return Err(DecompressionError::Truncated);
+    }
+
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let block = &data[pos..];
+        let out_start = out.len();
+        out.resize(out_start + block_data_size(block)?, 0);
+        let outcome = decompress_block(decompressor, block, &mut out[out_start..])?;
+        pos += outcome.consumed;
+    }
+
+    Ok(out)
+}
+
+/// A BGZF virtual file offset, which addresses a byte of uncompressed
+/// data as the offset of the compressed block that contains it
+/// (upper 48 bits) and the offset of the byte within the block's
+/// uncompressed data (lower 16 bits).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VirtualOffset(u64);
+
+impl VirtualOffset {
+    /// Returns a virtual offset that addresses byte `within_block` of
+    /// the uncompressed data of the block that starts at
+    /// `block_offset` in the compressed data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_offset` does not fit in 48 bits.
+    pub const fn new(block_offset: u64, within_block: u16) -> VirtualOffset {
+        assert!(block_offset < (1 << 48), "BGZF block offsets must fit in 48 bits");
+        VirtualOffset((block_offset << 16) | within_block as u64)
+    }
+
+    /// Returns the offset of the block in the compressed data.
+    pub const fn block_offset(self) -> u64 {
+        self.0 >> 16
+    }
+
+    /// Returns the offset within the block's uncompressed data.
+    pub const fn within_block(self) -> u16 {
+        self.0 as u16
+    }
+}
+
+impl From<u64> for VirtualOffset {
+    fn from(raw: u64) -> Self {
+        VirtualOffset(raw)
+    }
+}
+
+impl From<VirtualOffset> for u64 {
+    fn from(offset: VirtualOffset) -> Self {
+        offset.0
+    }
+}
+
+/// A single entry of a [`GziIndex`](struct.GziIndex.html): the
+/// offsets at which a block starts in the compressed and uncompressed
+/// data.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct GziEntry {
+    /// The offset of the start of the block in the compressed data.
+    pub compressed_offset: u64,
+
+    /// The offset of the block's first byte in the uncompressed data.
+    pub uncompressed_offset: u64,
+}
+
+/// An index of the blocks in BGZF data, in the `.gzi` format written
+/// by `bgzip -i`.
+///
+/// The index maps uncompressed offsets to compressed block offsets,
+/// so that an arbitrary range of uncompressed data can be read by
+/// decompressing only the blocks that cover it (see
+/// [`read_range`](fn.read_range.html)). Like `bgzip`, the index does
+/// not store an entry for the first block, which always starts at
+/// offset 0 in both the compressed and uncompressed data.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GziIndex {
+    entries: Vec<GziEntry>,
+}
+
+impl GziIndex {
+    /// Builds an index of the blocks in `data` (a buffer containing
+    /// BGZF blocks). Only block headers and trailers are read: no
+    /// data is decompressed.
+    ///
+    /// Returns `DecompressionError::BadData` if `data` contains an
+    /// invalid or truncated block.
+    pub fn build(data: &[u8]) -> DecompressionResult<GziIndex> {
+        let mut entries = Vec::new();
+        let mut compressed_offset = 0;
+        let mut uncompressed_offset = 0;
+
+        while compressed_offset < data.len() {
+            let block = &data[compressed_offset..];
+            let bsize = block_size(block)?;
+            let data_size = block_data_size(block)?;
+
+            // like `bgzip`, don't index the EOF marker (or any other
+            // empty block)
+            if compressed_offset != 0 && data_size != 0 {
+                entries.push(GziEntry {
+                    compressed_offset: compressed_offset as u64,
+                    uncompressed_offset: uncompressed_offset as u64,
+                });
+            }
+            compressed_offset += bsize;
+            uncompressed_offset += data_size;
+        }
+
+        Ok(GziIndex { entries })
+    }
+
+    /// Returns the index's entries in ascending order, excluding the
+    /// implicit entry for the first block.
+    pub fn entries(&self) -> &[GziEntry] {
+        &self.entries
+    }
+
+    /// Returns the virtual offset of the byte at `uncompressed_offset`
+    /// in the uncompressed data, or `None` if `uncompressed_offset` is
+    /// too far past the start of the last indexed block to be
+    /// addressed by a virtual offset (i.e. it is past the end of the
+    /// data).
+    pub fn virtual_offset(&self, uncompressed_offset: u64) -> Option<VirtualOffset> {
+        let entry = self.covering_entry(uncompressed_offset);
+        let within_block = uncompressed_offset - entry.uncompressed_offset;
+        if within_block > u16::MAX as u64 {
+            return None;
+        }
+        Some(VirtualOffset::new(entry.compressed_offset, within_block as u16))
+    }
+
+    /// Returns the entry of the last block that starts at or before
+    /// `uncompressed_offset`.
+    fn covering_entry(&self, uncompressed_offset: u64) -> GziEntry {
+        match self.entries.partition_point(|e| e.uncompressed_offset <= uncompressed_offset) {
+            0 => GziEntry { compressed_offset: 0, uncompressed_offset: 0 },
+            n => self.entries[n - 1],
+        }
+    }
+
+    /// Parses an index from `data` (the contents of a `.gzi` file).
+    ///
+    /// Returns `DecompressionError::BadData` if `data` is not a valid
+    /// `.gzi` index.
+    pub fn from_bytes(data: &[u8]) -> DecompressionResult<GziIndex> {
+        let read_u64 = |pos: usize| -> DecompressionResult<u64> {
+            let bytes = data.get(pos..pos + 8).ok_or(DecompressionError::BadData)?;
+            let mut buf = [0; 8];
+            buf.copy_from_slice(bytes);
+            Ok(u64::from_le_bytes(buf))
+        };
+
+        let n_entries = read_u64(0)?;
+        let expected_len = n_entries.checked_mul(16).and_then(|n| n.checked_add(8));
+        if expected_len != Some(data.len() as u64) {
+            return Err(DecompressionError::BadData);
+        }
+
+        let mut entries = Vec::with_capacity(n_entries as usize);
+        for i in 0..(n_entries as usize) {
+            entries.push(GziEntry {
+                compressed_offset: read_u64(8 + 16 * i)?,
+                uncompressed_offset: read_u64(16 + 16 * i)?,
+            });
+        }
+
+        Ok(GziIndex { entries })
+    }
+
+    /// Returns the index in the `.gzi` format: the number of entries,
+    /// followed by each entry's compressed and uncompressed offsets,
+    /// all as little-endian `u64`s.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(8 + 16 * self.entries.len());
+        out.extend_from_slice(&(self.entries.len() as u64).to_le_bytes());
+        for entry in &self.entries {
+            out.extend_from_slice(&entry.compressed_offset.to_le_bytes());
+            out.extend_from_slice(&entry.uncompressed_offset.to_le_bytes());
+        }
+        out
+    }
+
+    /// Reads an index in the `.gzi` format from `reader`.
+    #[cfg(feature = "std")]
+    pub fn read<R: Read>(mut reader: R) -> io::Result<GziIndex> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        GziIndex::from_bytes(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Writes the index in the `.gzi` format to `writer`.
+    #[cfg(feature = "std")]
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&self.to_bytes())
+    }
+}
+
+/// Decompresses up to `len` bytes of uncompressed data, starting at
+/// the virtual offset `start`, from `data` (a buffer containing BGZF
+/// blocks). Only the blocks that cover the requested range are
+/// decompressed.
+///
+/// Returns fewer than `len` bytes if the end of the data is reached,
+/// or an error (see
+/// [`DecompressionError`](../enum.DecompressionError.html)) if a block
+/// is invalid.
+pub fn read_at(decompressor: &mut Decompressor,
+               data: &[u8],
+               start: VirtualOffset,
+               len: usize) -> DecompressionResult<Vec<u8>> {
+    read_blocks(decompressor, data, start.block_offset(), start.within_block() as u64, len)
+}
+
+/// Decompresses up to `len` bytes of uncompressed data, starting at
+/// byte `start` of the uncompressed data, from `data` (a buffer
+/// containing BGZF blocks). `index` is used to locate the first
+/// covering block, and only the blocks that cover the requested
+/// range are decompressed.
+///
+/// Returns fewer than `len` bytes if the end of the data is reached,
+/// or an error (see
+/// [`DecompressionError`](../enum.DecompressionError.html)) if a block
+/// is invalid.
+pub fn read_range(decompressor: &mut Decompressor,
+                  data: &[u8],
+                  index: &GziIndex,
+                  start: u64,
+                  len: usize) -> DecompressionResult<Vec<u8>> {
+    let entry = index.covering_entry(start);
+    read_blocks(decompressor, data, entry.compressed_offset, start - entry.uncompressed_offset, len)
+}
+
+/// Like [`read_at`](fn.read_at.html), but reads the BGZF blocks from
+/// `reader`, seeking directly to the first covering block.
+#[cfg(feature = "std")]
+pub fn read_at_from<R: Read + Seek>(decompressor: &mut Decompressor,
+                                    reader: &mut R,
+                                    start: VirtualOffset,
+                                    len: usize) -> io::Result<Vec<u8>> {
+    read_blocks_from(decompressor, reader, start.block_offset(), start.within_block() as u64, len)
+}
+
+/// Like [`read_range`](fn.read_range.html), but reads the BGZF blocks
+/// from `reader`, seeking directly to the first covering block.
+#[cfg(feature = "std")]
+pub fn read_range_from<R: Read + Seek>(decompressor: &mut Decompressor,
+                                       reader: &mut R,
+                                       index: &GziIndex,
+                                       start: u64,
+                                       len: usize) -> io::Result<Vec<u8>> {
+    let entry = index.covering_entry(start);
+    read_blocks_from(decompressor, reader, entry.compressed_offset, start - entry.uncompressed_offset, len)
+}
+
+/// Decompresses the blocks in `data`, starting with the block at
+/// `block_offset`, until `len` bytes of uncompressed data (after
+/// skipping the first `skip` bytes) have been collected.
+fn read_blocks(decompressor: &mut Decompressor,
+               data: &[u8],
+               block_offset: u64,
+               mut skip: u64,
+               len: usize) -> DecompressionResult<Vec<u8>> {
+    let mut out = Vec::with_capacity(len);
+    let mut block_buf = vec![0; MAX_BLOCK_SIZE];
+    let mut pos = block_offset as usize;
+
+    while out.len() < len && pos < data.len() {
+        let outcome = decompress_block(decompressor, &data[pos..], &mut block_buf)?;
+        append_block_data(&mut out, &block_buf[..outcome.produced], &mut skip, len);
+        pos += outcome.consumed;
+    }
+
+    Ok(out)
+}
+
+/// Like `read_blocks`, but reads the blocks from `reader`.
+#[cfg(feature = "std")]
+fn read_blocks_from<R: Read + Seek>(decompressor: &mut Decompressor,
+                                    reader: &mut R,
+                                    block_offset: u64,
+                                    mut skip: u64,
+                                    len: usize) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(len);
+    let mut block = Vec::with_capacity(MAX_BLOCK_SIZE);
+    let mut block_buf = vec![0; MAX_BLOCK_SIZE];
+
+    reader.seek(SeekFrom::Start(block_offset))?;
+    while out.len() < len && read_block(reader, &mut block)? {
+        let outcome = decompress_block(decompressor, &block, &mut block_buf)?;
+        append_block_data(&mut out, &block_buf[..outcome.produced], &mut skip, len);
+    }
+
+    Ok(out)
+}
+
+/// Appends the data of a decompressed block to `out`, after skipping
+/// up to `skip` bytes of it, without growing `out` beyond `len` bytes.
+/// `skip` is reduced by the number of skipped bytes.
+fn append_block_data(out: &mut Vec<u8>, block_data: &[u8], skip: &mut u64, len: usize) {
+    let skipped = core::cmp::min(*skip, block_data.len() as u64) as usize;
+    *skip -= skipped as u64;
+
+    let wanted = &block_data[skipped..];
+    let n = core::cmp::min(wanted.len(), len - out.len());
+    out.extend_from_slice(&wanted[..n]);
+}
+
+/// Reads the next whole BGZF block from `reader` into `block`.
+/// Returns `false` if `reader` is at EOF.
+#[cfg(feature = "std")]
+fn read_block<R: Read>(reader: &mut R, block: &mut Vec<u8>) -> io::Result<bool> {
+    // the fixed gzip header fields plus XLEN
+    block.resize(12, 0);
+    let mut n_read = 0;
+    while n_read < block.len() {
+        match reader.read(&mut block[n_read..])? {
+            0 if n_read == 0 => return Ok(false),
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => n_read += n,
+        }
+    }
+
+    let xlen = u16::from_le_bytes([block[10], block[11]]) as usize;
+    block.resize(12 + xlen, 0);
+    reader.read_exact(&mut block[12..])?;
+
+    let bsize = block_size(block)?;
+    let header_len = block.len();
+    block.resize(bsize, 0);
+    reader.read_exact(&mut block[header_len..])?;
+
+    Ok(true)
+}
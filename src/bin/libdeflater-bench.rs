@@ -0,0 +1,382 @@
+//! `libdeflater-bench`: measures every compression level on a
+//! directory of sample files and recommends a level (requires the
+//! `cli` feature).
+//!
+//! For each format and `CompressionLvl`, it reports the compression
+//! ratio, compression and decompression throughput, and compressor
+//! memory, as CSV or JSON. Unlike the criterion benchmarks in
+//! `benches/`, it is meant to be run by users on their own data.
+//!
+//! The compressor memory is `Compressor::memory_usage`, which is
+//! approximate with the `rust_backend` feature.
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant};
+use libdeflater::{CompressionLvl, Compressor, Decompressor, Format};
+
+const USAGE: &str = "\
+Usage: libdeflater-bench [OPTION]... DIR
+Measure every compression level on the files in DIR (recursively) and
+print the results as CSV or JSON.
+
+  -f, --formats=LIST           comma-separated formats to measure: gzip,
+                               zlib, deflate (default: all three)
+  -o, --output=csv|json        output format (default: csv)
+  -n, --iterations=N           compress and decompress each file N times
+                               (default: 3)
+      --target-throughput=MBS  recommend the best-compressing level whose
+                               compression throughput is at least MBS MB/s
+      --target-ratio=R         recommend the fastest level whose ratio
+                               (uncompressed/compressed) is at least R
+  -h, --help                   print this help and exit
+  -V, --version                print the version and exit
+
+With both targets, the best-compressing level that meets both is
+recommended. With CSV output, recommendations are printed to stderr.
+";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Output {
+    Csv,
+    Json,
+}
+
+struct Options {
+    formats: Vec<Format>,
+    output: Output,
+    iterations: u32,
+    target_throughput: Option<f64>,
+    target_ratio: Option<f64>,
+    dir: PathBuf,
+}
+
+/// Measurements of one format at one level, summed over all files.
+struct Measurement {
+    format: Format,
+    lvl: CompressionLvl,
+    input_bytes: u64,
+    compressed_bytes: u64,
+    compress_time: Duration,
+    decompress_time: Duration,
+    compressor_memory: usize,
+}
+
+impl Measurement {
+    /// Uncompressed size divided by compressed size.
+    fn ratio(&self) -> f64 {
+        self.input_bytes as f64 / std::cmp::max(self.compressed_bytes, 1) as f64
+    }
+
+    /// Compression throughput in MB/s (of uncompressed data).
+    fn compress_throughput(&self, iterations: u32) -> f64 {
+        throughput(self.input_bytes * u64::from(iterations), self.compress_time)
+    }
+
+    /// Decompression throughput in MB/s (of uncompressed data).
+    fn decompress_throughput(&self, iterations: u32) -> f64 {
+        throughput(self.input_bytes * u64::from(iterations), self.decompress_time)
+    }
+}
+
+fn throughput(bytes: u64, time: Duration) -> f64 {
+    bytes as f64 / 1e6 / time.as_secs_f64().max(1e-9)
+}
+
+fn main() {
+    let opts = match parse_args(std::env::args_os().skip(1)) {
+        Ok(opts) => opts,
+        Err(msg) => {
+            eprintln!("libdeflater-bench: {}", msg);
+            eprintln!("Try `libdeflater-bench --help' for more information.");
+            process::exit(1);
+        },
+    };
+
+    let samples = match read_samples(&opts.dir) {
+        Ok(samples) if samples.is_empty() => {
+            eprintln!("libdeflater-bench: {}: no files to measure", opts.dir.display());
+            process::exit(1);
+        },
+        Ok(samples) => samples,
+        Err(msg) => {
+            eprintln!("libdeflater-bench: {}", msg);
+            process::exit(1);
+        },
+    };
+
+    let mut measurements = Vec::new();
+    for &format in &opts.formats {
+        for lvl in CompressionLvl::iter() {
+            match measure(format, lvl, &samples, opts.iterations) {
+                Ok(m) => measurements.push(m),
+                Err(msg) => {
+                    eprintln!("libdeflater-bench: {}", msg);
+                    process::exit(1);
+                },
+            }
+        }
+    }
+
+    let recommendations: Vec<&Measurement> = opts.formats.iter()
+        .filter_map(|format| recommend(&opts, measurements.iter().filter(|m| m.format == *format)))
+        .collect();
+
+    match opts.output {
+        Output::Csv => {
+            print_csv(&opts, &measurements);
+            if opts.target_throughput.is_some() || opts.target_ratio.is_some() {
+                print_recommendations(&opts, &recommendations);
+            }
+        },
+        Output::Json => print_json(&opts, samples.len(), &measurements, &recommendations),
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = OsString>) -> Result<Options, String> {
+    let mut formats = None;
+    let mut output = Output::Csv;
+    let mut iterations = 3;
+    let mut target_throughput = None;
+    let mut target_ratio = None;
+    let mut dir = None;
+
+    while let Some(arg) = args.next() {
+        let arg_str = match arg.to_str() {
+            Some(s) if s.starts_with('-') => s.to_owned(),
+            _ => {
+                if dir.replace(PathBuf::from(arg)).is_some() {
+                    return Err(String::from("only one directory can be measured"));
+                }
+                continue;
+            },
+        };
+
+        // options take their value either after `=` or as the next
+        // argument
+        let (name, inline_value) = match arg_str.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_owned())),
+            None => (arg_str.as_str(), None),
+        };
+        let mut value = || -> Result<String, String> {
+            match &inline_value {
+                Some(value) => Ok(value.clone()),
+                None => args.next()
+                    .and_then(|value| value.into_string().ok())
+                    .ok_or_else(|| format!("option '{}' requires an argument", name)),
+            }
+        };
+
+        match name {
+            "-f" | "--formats" => formats = Some(parse_formats(&value()?)?),
+            "-o" | "--output" => {
+                output = match value()?.as_str() {
+                    "csv" => Output::Csv,
+                    "json" => Output::Json,
+                    other => return Err(format!("invalid output format '{}'", other)),
+                }
+            },
+            "-n" | "--iterations" => {
+                let n = value()?;
+                iterations = n.parse().ok().filter(|n| *n >= 1)
+                    .ok_or_else(|| format!("invalid number of iterations '{}'", n))?;
+            },
+            "--target-throughput" => target_throughput = Some(parse_positive(&value()?)?),
+            "--target-ratio" => target_ratio = Some(parse_positive(&value()?)?),
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                process::exit(0);
+            },
+            "-V" | "--version" => {
+                println!("libdeflater-bench {}", env!("CARGO_PKG_VERSION"));
+                process::exit(0);
+            },
+            _ => return Err(format!("unrecognized option '{}'", name)),
+        }
+    }
+
+    Ok(Options {
+        formats: formats.unwrap_or_else(|| vec![Format::Gzip, Format::Zlib, Format::Deflate]),
+        output,
+        iterations,
+        target_throughput,
+        target_ratio,
+        dir: dir.ok_or("missing directory operand")?,
+    })
+}
+
+fn parse_formats(list: &str) -> Result<Vec<Format>, String> {
+    list.split(',')
+        .map(|name| match name {
+            "gzip" => Ok(Format::Gzip),
+            "zlib" => Ok(Format::Zlib),
+            "deflate" => Ok(Format::Deflate),
+            _ => Err(format!("unknown format '{}'", name)),
+        })
+        .collect()
+}
+
+fn parse_positive(value: &str) -> Result<f64, String> {
+    value.parse::<f64>().ok()
+        .filter(|v| v.is_finite() && *v > 0.0)
+        .ok_or_else(|| format!("invalid target '{}'", value))
+}
+
+fn format_name(format: Format) -> &'static str {
+    match format {
+        Format::Gzip => "gzip",
+        Format::Zlib => "zlib",
+        Format::Deflate => "deflate",
+    }
+}
+
+/// Reads every file under `dir`, in path order.
+fn read_samples(dir: &Path) -> Result<Vec<Vec<u8>>, String> {
+    let mut paths = Vec::new();
+    collect_files(dir, &mut paths).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    paths.sort();
+    paths.iter()
+        .map(|path| fs::read(path).map_err(|e| format!("{}: {}", path.display(), e)))
+        .collect()
+}
+
+fn collect_files(dir: &Path, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, paths)?;
+        } else if path.is_file() {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Compresses and decompresses each sample `iterations` times with
+/// `format` at `lvl`, checking that the data roundtrips.
+fn measure(format: Format, lvl: CompressionLvl, samples: &[Vec<u8>], iterations: u32) -> Result<Measurement, String> {
+    let mut compressor = Compressor::new(lvl);
+    let mut decompressor = Decompressor::new();
+    let mut m = Measurement {
+        format,
+        lvl,
+        input_bytes: 0,
+        compressed_bytes: 0,
+        compress_time: Duration::ZERO,
+        decompress_time: Duration::ZERO,
+        compressor_memory: compressor.memory_usage().peak,
+    };
+
+    for sample in samples {
+        let mut compressed = vec![0; compressor.compress_bound(format, sample.len())];
+        let mut decompressed = vec![0; sample.len()];
+        let mut compressed_len = 0;
+
+        for _ in 0..iterations {
+            let start = Instant::now();
+            compressed_len = compressor.compress(format, sample, &mut compressed)
+                .map_err(|e| format!("{} level {}: {}", format_name(format), i32::from(lvl), e))?;
+            m.compress_time += start.elapsed();
+
+            let start = Instant::now();
+            let decompressed_len = decompressor.decompress(format, &compressed[..compressed_len], &mut decompressed)
+                .map_err(|e| format!("{} level {}: {}", format_name(format), i32::from(lvl), e))?;
+            m.decompress_time += start.elapsed();
+
+            if decompressed[..decompressed_len] != sample[..] {
+                return Err(format!("{} level {}: data did not roundtrip", format_name(format), i32::from(lvl)));
+            }
+        }
+
+        m.input_bytes += sample.len() as u64;
+        m.compressed_bytes += compressed_len as u64;
+    }
+    Ok(m)
+}
+
+/// Returns the level of one format that best meets the targets in
+/// `opts`, or `None` if there are no targets or no level meets them.
+fn recommend<'a>(opts: &Options, measurements: impl Iterator<Item = &'a Measurement>) -> Option<&'a Measurement> {
+    if opts.target_throughput.is_none() && opts.target_ratio.is_none() {
+        return None;
+    }
+    let candidates = measurements.filter(|m| {
+        opts.target_throughput.map_or(true, |t| m.compress_throughput(opts.iterations) >= t)
+            && opts.target_ratio.map_or(true, |r| m.ratio() >= r)
+    });
+
+    if opts.target_throughput.is_some() {
+        // best ratio, preferring the faster (lower) level on ties
+        candidates.fold(None, |best: Option<&Measurement>, m| match best {
+            Some(b) if b.ratio() >= m.ratio() => Some(b),
+            _ => Some(m),
+        })
+    } else {
+        candidates.max_by(|a, b| a.compress_throughput(opts.iterations).total_cmp(&b.compress_throughput(opts.iterations)))
+    }
+}
+
+fn print_csv(opts: &Options, measurements: &[Measurement]) {
+    println!("format,level,input_bytes,compressed_bytes,ratio,compress_mb_s,decompress_mb_s,compressor_memory");
+    for m in measurements {
+        println!("{},{},{},{},{:.4},{:.2},{:.2},{}",
+                 format_name(m.format),
+                 i32::from(m.lvl),
+                 m.input_bytes,
+                 m.compressed_bytes,
+                 m.ratio(),
+                 m.compress_throughput(opts.iterations),
+                 m.decompress_throughput(opts.iterations),
+                 m.compressor_memory);
+    }
+}
+
+fn print_recommendations(opts: &Options, recommendations: &[&Measurement]) {
+    for &format in &opts.formats {
+        match recommendations.iter().find(|m| m.format == format) {
+            Some(m) => eprintln!("recommended {} level: {} (ratio {:.4}, {:.2} MB/s compression)",
+                                 format_name(format),
+                                 i32::from(m.lvl),
+                                 m.ratio(),
+                                 m.compress_throughput(opts.iterations)),
+            None => eprintln!("recommended {} level: none (no level meets the target)", format_name(format)),
+        }
+    }
+}
+
+fn print_json(opts: &Options, n_files: usize, measurements: &[Measurement], recommendations: &[&Measurement]) {
+    let json_measurement = |m: &Measurement| {
+        format!("{{\"format\": \"{}\", \"level\": {}, \"input_bytes\": {}, \"compressed_bytes\": {}, \"ratio\": {:.4}, \
+                 \"compress_mb_s\": {:.2}, \"decompress_mb_s\": {:.2}, \"compressor_memory\": {}}}",
+                format_name(m.format),
+                i32::from(m.lvl),
+                m.input_bytes,
+                m.compressed_bytes,
+                m.ratio(),
+                m.compress_throughput(opts.iterations),
+                m.decompress_throughput(opts.iterations),
+                m.compressor_memory)
+    };
+    let json_target = |target: Option<f64>| target.map_or(String::from("null"), |t| t.to_string());
+
+    println!("{{");
+    println!("  \"files\": {},", n_files);
+    println!("  \"iterations\": {},", opts.iterations);
+    println!("  \"target_throughput\": {},", json_target(opts.target_throughput));
+    println!("  \"target_ratio\": {},", json_target(opts.target_ratio));
+    println!("  \"results\": [");
+    for (i, m) in measurements.iter().enumerate() {
+        let sep = if i + 1 < measurements.len() { "," } else { "" };
+        println!("    {}{}", json_measurement(m), sep);
+    }
+    println!("  ],");
+    println!("  \"recommendations\": [");
+    for (i, m) in recommendations.iter().enumerate() {
+        let sep = if i + 1 < recommendations.len() { "," } else { "" };
+        println!("    {}{}", json_measurement(m), sep);
+    }
+    println!("  ]");
+    println!("}}");
+}
@@ -0,0 +1,435 @@
+//! `libdeflater`: a gzip-compatible command-line tool built on
+//! `Compressor` and `Decompressor` (requires the `cli` feature).
+//!
+//! It accepts the common `gzip`/`gunzip` flags, and, like `libdeflate`'s
+//! own `gzip` program, (de)compresses each file in a single buffer
+//! rather than streaming it.
+
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, OpenOptions};
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use libdeflater::gzip::GzipReader;
+use libdeflater::{CompressionLvl, Compressor, DecompressionError, Decompressor, Format};
+
+const USAGE: &str = "\
+Usage: libdeflater [OPTION]... [FILE]...
+Compress or decompress FILEs (by default, compress FILEs in-place as gzip).
+With no FILE, or when FILE is -, read standard input.
+
+  -c, --stdout       write to standard output, keep original files
+  -d, --decompress   decompress
+  -f, --force        overwrite existing output files, and (de)compress
+                     to/from a terminal
+  -h, --help         print this help and exit
+  -k, --keep         keep (don't delete) input files
+  -l, --list         list compressed and uncompressed sizes
+  -S, --suffix=SUF   use suffix SUF on compressed files
+  -t, --test         test compressed file integrity
+  -V, --version      print the version and exit
+  -1, --fast         compress faster
+  -12, --best        compress better (levels 0 to 12, default 6;
+                     0 stores the data uncompressed)
+      --zlib         use the zlib format (default suffix .zz)
+      --raw          use the raw DEFLATE format (default suffix .deflate)
+";
+
+/// Exit status when a warning (but no error) was reported, as in gzip.
+const WARNING_STATUS: i32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Compress,
+    Decompress,
+    Test,
+    List,
+}
+
+struct Options {
+    mode: Mode,
+    stdout: bool,
+    keep: bool,
+    force: bool,
+    lvl: CompressionLvl,
+    format: Format,
+    suffix: Option<OsString>,
+    files: Vec<OsString>,
+}
+
+impl Options {
+    fn suffix(&self) -> &OsStr {
+        match &self.suffix {
+            Some(suffix) => suffix,
+            None => OsStr::new(match self.format {
+                Format::Gzip => ".gz",
+                Format::Zlib => ".zz",
+                Format::Deflate => ".deflate",
+            }),
+        }
+    }
+}
+
+/// Why a file was not processed.
+enum Failure {
+    /// Something went wrong; exits with status 1.
+    Error(String),
+    /// The file was skipped (e.g. it has an unknown suffix); exits
+    /// with status 2 unless there was also an error.
+    Warning(String),
+}
+
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Failure {
+        Failure::Error(e.to_string())
+    }
+}
+
+impl From<DecompressionError> for Failure {
+    fn from(e: DecompressionError) -> Failure {
+        Failure::Error(e.to_string())
+    }
+}
+
+fn main() {
+    let opts = match parse_args(std::env::args_os().skip(1)) {
+        Ok(opts) => opts,
+        Err(msg) => {
+            eprintln!("libdeflater: {}", msg);
+            eprintln!("Try `libdeflater --help' for more information.");
+            process::exit(1);
+        },
+    };
+
+    let files = if opts.files.is_empty() { vec![OsString::from("-")] } else { opts.files.clone() };
+    // only compression needs a compressor, which can be large at the
+    // higher levels, so it is allocated the first time it is used
+    let mut compressor = None;
+    let mut decompressor = Decompressor::new();
+    let mut totals = (0, 0);
+    let mut status = 0;
+
+    if opts.mode == Mode::List {
+        println!("{:>19} {:>19}  ratio uncompressed_name", "compressed", "uncompressed");
+    }
+
+    for file in &files {
+        let result = if file == "-" {
+            process_stdin(&opts, &mut compressor, &mut decompressor, &mut totals)
+        } else {
+            process_file(&opts, Path::new(file), &mut compressor, &mut decompressor, &mut totals)
+        };
+        match result {
+            Ok(()) => {},
+            Err(Failure::Error(msg)) => {
+                eprintln!("libdeflater: {}: {}", file.to_string_lossy(), msg);
+                status = 1;
+            },
+            Err(Failure::Warning(msg)) => {
+                eprintln!("libdeflater: {}: {}", file.to_string_lossy(), msg);
+                if status == 0 {
+                    status = WARNING_STATUS;
+                }
+            },
+        }
+    }
+
+    if opts.mode == Mode::List && files.len() > 1 {
+        print_list_row(totals.0, totals.1, "(totals)");
+    }
+    process::exit(status);
+}
+
+fn parse_args(mut args: impl Iterator<Item = OsString>) -> Result<Options, String> {
+    let mut opts = Options {
+        mode: Mode::Compress,
+        stdout: false,
+        keep: false,
+        force: false,
+        lvl: CompressionLvl::default(),
+        format: Format::Gzip,
+        suffix: None,
+        files: Vec::new(),
+    };
+
+    while let Some(arg) = args.next() {
+        let arg_str = match arg.to_str() {
+            Some(s) if s.starts_with('-') && s != "-" => s,
+            _ => {
+                opts.files.push(arg);
+                continue;
+            },
+        };
+
+        if arg_str == "--" {
+            opts.files.extend(args.by_ref());
+            break;
+        } else if let Some(long) = arg_str.strip_prefix("--") {
+            let (name, value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (long, None),
+            };
+            match name {
+                "stdout" | "to-stdout" => opts.stdout = true,
+                "decompress" | "uncompress" => opts.mode = Mode::Decompress,
+                "force" => opts.force = true,
+                "help" => {
+                    print!("{}", USAGE);
+                    process::exit(0);
+                },
+                "keep" => opts.keep = true,
+                "list" => opts.mode = Mode::List,
+                "suffix" => {
+                    let suffix = match value {
+                        Some(value) => OsString::from(value),
+                        None => args.next().ok_or("option '--suffix' requires an argument")?,
+                    };
+                    opts.suffix = Some(suffix);
+                },
+                "test" => opts.mode = Mode::Test,
+                "version" => {
+                    println!("libdeflater {}", env!("CARGO_PKG_VERSION"));
+                    process::exit(0);
+                },
+                "fast" => opts.lvl = CompressionLvl::new(1).unwrap(),
+                "best" => opts.lvl = CompressionLvl::best(),
+                "zlib" => opts.format = Format::Zlib,
+                "raw" => opts.format = Format::Deflate,
+                _ => return Err(format!("unrecognized option '--{}'", name)),
+            }
+        } else {
+            let flags = &arg_str[1..];
+            let mut chars = flags.char_indices();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    'c' => opts.stdout = true,
+                    'd' => opts.mode = Mode::Decompress,
+                    'f' => opts.force = true,
+                    'h' => {
+                        print!("{}", USAGE);
+                        process::exit(0);
+                    },
+                    'k' => opts.keep = true,
+                    'l' => opts.mode = Mode::List,
+                    't' => opts.mode = Mode::Test,
+                    'V' => {
+                        println!("libdeflater {}", env!("CARGO_PKG_VERSION"));
+                        process::exit(0);
+                    },
+                    'S' => {
+                        // the suffix is the rest of this argument, or the next one
+                        let rest = &flags[i + 1..];
+                        let suffix = if rest.is_empty() {
+                            args.next().ok_or("option requires an argument -- 'S'")?
+                        } else {
+                            OsString::from(rest)
+                        };
+                        opts.suffix = Some(suffix);
+                        break;
+                    },
+                    '0'..='9' => {
+                        // levels may have two digits (e.g. -12)
+                        let digits: String = flags[i..].chars().take_while(char::is_ascii_digit).collect();
+                        let lvl = digits.parse::<i32>().ok()
+                            .and_then(|lvl| CompressionLvl::new(lvl).ok())
+                            .ok_or_else(|| format!("invalid compression level -- '{}'", digits))?;
+                        opts.lvl = lvl;
+                        for _ in 1..digits.len() {
+                            chars.next();
+                        }
+                    },
+                    _ => return Err(format!("invalid option -- '{}'", c)),
+                }
+            }
+        }
+    }
+
+    if opts.suffix().is_empty() {
+        return Err(String::from("invalid suffix ''"));
+    }
+    Ok(opts)
+}
+
+fn process_stdin(opts: &Options,
+                 compressor: &mut Option<Compressor>,
+                 decompressor: &mut Decompressor,
+                 totals: &mut (u64, u64)) -> Result<(), Failure> {
+    if opts.mode == Mode::Compress && !opts.force && io::stdout().is_terminal() {
+        return Err(Failure::Error(String::from("compressed data not written to a terminal. Use -f to force compression.")));
+    }
+    if opts.mode != Mode::Compress && !opts.force && io::stdin().is_terminal() {
+        return Err(Failure::Error(String::from("compressed data not read from a terminal. Use -f to force decompression.")));
+    }
+
+    let mut data = Vec::new();
+    io::stdin().lock().read_to_end(&mut data)?;
+
+    match opts.mode {
+        Mode::Compress => write_stdout(&compress(compressor, opts, &data)?),
+        Mode::Decompress => write_stdout(&decompress(decompressor, opts.format, &data)?),
+        Mode::Test => decompress(decompressor, opts.format, &data).map(|_| ()),
+        Mode::List => list(decompressor, opts.format, &data, "stdout", totals),
+    }
+}
+
+fn process_file(opts: &Options,
+                path: &Path,
+                compressor: &mut Option<Compressor>,
+                decompressor: &mut Decompressor,
+                totals: &mut (u64, u64)) -> Result<(), Failure> {
+    let metadata = fs::metadata(path)?;
+    if metadata.is_dir() {
+        return Err(Failure::Warning(String::from("is a directory -- ignored")));
+    }
+
+    let suffix = opts.suffix();
+    let stripped = strip_suffix(path, suffix);
+
+    let out_path = match opts.mode {
+        Mode::Compress if !opts.stdout => {
+            if stripped.is_some() {
+                return Err(Failure::Warning(format!("already has {} suffix -- unchanged", suffix.to_string_lossy())));
+            }
+            let mut out_path = path.as_os_str().to_owned();
+            out_path.push(suffix);
+            Some(PathBuf::from(out_path))
+        },
+        Mode::Decompress if !opts.stdout => match &stripped {
+            Some(out_path) => Some(out_path.clone()),
+            None => return Err(Failure::Warning(String::from("unknown suffix -- ignored"))),
+        },
+        _ => None,
+    };
+
+    if opts.mode == Mode::Compress && out_path.is_none() && !opts.force && io::stdout().is_terminal() {
+        return Err(Failure::Error(String::from("compressed data not written to a terminal. Use -f to force compression.")));
+    }
+    if let Some(out_path) = &out_path {
+        if !opts.force && fs::symlink_metadata(out_path).is_ok() {
+            return Err(Failure::Error(format!("{} already exists; not overwritten", out_path.display())));
+        }
+    }
+
+    let data = fs::read(path)?;
+    let out_data = match opts.mode {
+        Mode::Compress => compress(compressor, opts, &data)?,
+        Mode::Decompress => decompress(decompressor, opts.format, &data)?,
+        Mode::Test => {
+            decompress(decompressor, opts.format, &data)?;
+            return Ok(());
+        },
+        Mode::List => {
+            let name = stripped.unwrap_or_else(|| path.to_owned());
+            return list(decompressor, opts.format, &data, &name.to_string_lossy(), totals);
+        },
+    };
+
+    match out_path {
+        Some(out_path) => {
+            write_file(&out_path, &out_data, opts.force)?;
+            // like gzip, the output keeps the input's permissions
+            // (best effort)
+            let _ = fs::set_permissions(&out_path, metadata.permissions());
+            if !opts.keep {
+                fs::remove_file(path)?;
+            }
+            Ok(())
+        },
+        None => write_stdout(&out_data),
+    }
+}
+
+/// Returns `path` without `suffix`, or `None` if `path` doesn't end
+/// with `suffix` (or is just `suffix`). The file name is compared as
+/// bytes, so names that aren't valid Unicode are handled too.
+#[cfg(unix)]
+fn strip_suffix(path: &Path, suffix: &OsStr) -> Option<PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let stem = path.file_name()?.as_bytes().strip_suffix(suffix.as_bytes())?;
+    if stem.is_empty() {
+        return None;
+    }
+    Some(path.with_file_name(OsStr::from_bytes(stem)))
+}
+
+/// Returns `path` without `suffix`, or `None` if `path` doesn't end
+/// with `suffix` (or is just `suffix`). File names and suffixes that
+/// aren't valid Unicode never match.
+#[cfg(not(unix))]
+fn strip_suffix(path: &Path, suffix: &OsStr) -> Option<PathBuf> {
+    let stem = path.file_name()?.to_str()?.strip_suffix(suffix.to_str()?)?;
+    if stem.is_empty() {
+        return None;
+    }
+    Some(path.with_file_name(stem))
+}
+
+/// Compresses `data`, allocating `compressor` if this is the first
+/// time it is needed.
+fn compress(compressor: &mut Option<Compressor>, opts: &Options, data: &[u8]) -> io::Result<Vec<u8>> {
+    let compressor = compressor.get_or_insert_with(|| Compressor::new(opts.lvl));
+    compressor.compress_to_vec(&opts.format, data).map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+}
+
+/// Decompresses `data`, which may hold several gzip members back to
+/// back.
+fn decompress(decompressor: &mut Decompressor, format: Format, data: &[u8]) -> Result<Vec<u8>, Failure> {
+    if data.is_empty() {
+        return Err(DecompressionError::Truncated.into());
+    }
+    match format {
+        Format::Gzip => {
+            let mut out = Vec::new();
+            GzipReader::with_max_member_size(data, usize::MAX).read_to_end(&mut out)?;
+            Ok(out)
+        },
+        _ => Ok(decompressor.decompress_to_vec(&format, data, usize::MAX)?),
+    }
+}
+
+fn list(decompressor: &mut Decompressor,
+        format: Format,
+        data: &[u8],
+        name: &str,
+        totals: &mut (u64, u64)) -> Result<(), Failure> {
+    let decompressed_len = decompress(decompressor, format, data)?.len() as u64;
+    totals.0 += data.len() as u64;
+    totals.1 += decompressed_len;
+    print_list_row(data.len() as u64, decompressed_len, name);
+    Ok(())
+}
+
+fn print_list_row(compressed_len: u64, decompressed_len: u64, name: &str) {
+    let ratio = if decompressed_len == 0 {
+        0.0
+    } else {
+        100.0 * (1.0 - compressed_len as f64 / decompressed_len as f64)
+    };
+    println!("{:>19} {:>19} {:>5.1}% {}", compressed_len, decompressed_len, ratio, name);
+}
+
+/// Writes `data` to a new file at `path` (or overwrites it if `force`
+/// is true), removing the partially-written file on failure.
+fn write_file(path: &Path, data: &[u8], force: bool) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    let mut f = options.open(path)?;
+    let result = f.write_all(data);
+    if result.is_err() {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+fn write_stdout(data: &[u8]) -> Result<(), Failure> {
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(data)?;
+    stdout.flush()?;
+    Ok(())
+}
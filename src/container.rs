@@ -0,0 +1,396 @@
+//! Builds container formats (gzip, zlib, or in-house formats) around
+//! raw DEFLATE data in Rust.
+//!
+//! [`Compressor::gzip_compress`] and [`Compressor::zlib_compress`]
+//! leave the framing to `libdeflate`. This module instead writes the
+//! header and trailer itself, around the raw DEFLATE data produced by
+//! [`Compressor::deflate_compress`] and a [`Checksum`] (e.g. [`Crc`]
+//! or [`Adler32`]) of the uncompressed data. A container is described
+//! by a [`Framing`], which supplies the header and trailer when
+//! compressing and validates them when decompressing.
+//!
+//! [`GzipFraming`] and [`ZlibFraming`] produce exactly the same bytes
+//! as `gzip_compress` and `zlib_compress`, and are mostly useful as a
+//! reference, or to write a custom [`GzipHeader`]. In-house containers
+//! (magic bytes, version, length prefix, checksum trailer, etc.) can
+//! be defined by implementing [`Framing`]:
+//!
+//! ```
+//! use libdeflater::container::Framing;
+//! use libdeflater::{CompressionLvl, Compressor, Crc, DecompressionError, DecompressionResult, Decompressor};
+//!
+//! /// `LDF1`, then the uncompressed length (u32 LE), then DEFLATE
+//! /// data, then the CRC32 of the uncompressed data (u32 LE).
+//! struct Ldf1;
+//!
+//! impl Framing for Ldf1 {
+//!     type Checksum = Crc;
+//!
+//!     fn header_len(&self) -> usize { 8 }
+//!
+//!     fn write_header(&self, _lvl: CompressionLvl, raw_len: usize, out: &mut [u8]) {
+//!         out[..4].copy_from_slice(b"LDF1");
+//!         out[4..].copy_from_slice(&(raw_len as u32).to_le_bytes());
+//!     }
+//!
+//!     fn trailer_len(&self) -> usize { 4 }
+//!
+//!     fn write_trailer(&self, checksum: u32, _raw_len: usize, out: &mut [u8]) {
+//!         out.copy_from_slice(&checksum.to_le_bytes());
+//!     }
+//!
+//!     fn read_header(&self, in_data: &[u8]) -> DecompressionResult<usize> {
+//!         match in_data.get(..8) {
+//!             Some(header) if &header[..4] == b"LDF1" => Ok(8),
+//!             Some(_) => Err(DecompressionError::BadMagic),
+//!             None => Err(DecompressionError::Truncated),
+//!         }
+//!     }
+//!
+//!     fn check_trailer(&self, header: &[u8], trailer: &[u8], checksum: u32, raw_len: usize) -> DecompressionResult<()> {
+//!         let expected = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+//!         if expected != checksum {
+//!             return Err(DecompressionError::CrcMismatch { expected, actual: checksum });
+//!         }
+//!         let expected = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+//!         if expected != raw_len as u32 {
+//!             return Err(DecompressionError::SizeMismatch { expected, actual: raw_len as u32 });
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//!
+//! // every `Framing` is also a `Codec`
+//! let compressed = Compressor::default().compress_to_vec(&Ldf1, b"hello, world!").unwrap();
+//! assert_eq!(&compressed[..4], b"LDF1");
+//! let data = Decompressor::new().decompress_to_vec(&Ldf1, &compressed, 1 << 20).unwrap();
+//! assert_eq!(data, b"hello, world!");
+//! ```
+//!
+//! [`Compressor::gzip_compress`]: ../struct.Compressor.html#method.gzip_compress
+//! [`Compressor::zlib_compress`]: ../struct.Compressor.html#method.zlib_compress
+//! [`Compressor::deflate_compress`]: ../struct.Compressor.html#method.deflate_compress
+//! [`Checksum`]: trait.Checksum.html
+//! [`Crc`]: ../struct.Crc.html
+//! [`Adler32`]: ../struct.Adler32.html
+//! [`Framing`]: trait.Framing.html
+//! [`GzipFraming`]: struct.GzipFraming.html
+//! [`ZlibFraming`]: struct.ZlibFraming.html
+//! [`GzipHeader`]: ../gzip/struct.GzipHeader.html
+
+use core::mem::MaybeUninit;
+use crate::gzip::{self, GzipHeader};
+use crate::{as_uninit_mut, assume_init, backend, check_zlib_header, sealed, zeroed, Adler32, Codec, CompressionError,
+            CompressionLvl, CompressionResult, Compressor, Crc, DecompressOutcome, DecompressionError,
+            DecompressionResult, Decompressor, ZLIB_FOOTER_SIZE, ZLIB_HEADER_SIZE};
+
+/// A rolling checksum of uncompressed data, which a
+/// [`Framing`](trait.Framing.html) records in its trailer.
+pub trait Checksum: Default {
+    /// Updates the checksum with the bytes in `data`.
+    fn update(&mut self, data: &[u8]);
+
+    /// Returns the current checksum.
+    fn sum(&self) -> u32;
+}
+
+impl Checksum for Crc {
+    fn update(&mut self, data: &[u8]) {
+        Crc::update(self, data)
+    }
+
+    fn sum(&self) -> u32 {
+        Crc::sum(self)
+    }
+}
+
+impl Checksum for Adler32 {
+    fn update(&mut self, data: &[u8]) {
+        Adler32::update(self, data)
+    }
+
+    fn sum(&self) -> u32 {
+        Adler32::sum(self)
+    }
+}
+
+/// The header and trailer of a container format that wraps a single
+/// raw DEFLATE stream.
+///
+/// Compressed data is laid out as `header_len` bytes of header, the
+/// DEFLATE data, and then `trailer_len` bytes of trailer. Every
+/// `Framing` is also a [`Codec`](../trait.Codec.html), so it can be
+/// used with [`Compressor::compress_to_vec`], the pools, etc.
+///
+/// [`Compressor::compress_to_vec`]: ../struct.Compressor.html#method.compress_to_vec
+pub trait Framing {
+    /// The checksum of the uncompressed data that is passed to
+    /// `write_trailer` and `check_trailer`.
+    type Checksum: Checksum;
+
+    /// Returns the number of bytes written by `write_header`.
+    fn header_len(&self) -> usize;
+
+    /// Writes the header for `raw_len` bytes of uncompressed data,
+    /// compressed at `lvl`, into `out` (which is `header_len` bytes
+    /// long).
+    fn write_header(&self, lvl: CompressionLvl, raw_len: usize, out: &mut [u8]);
+
+    /// Returns the number of bytes written by `write_trailer`.
+    fn trailer_len(&self) -> usize;
+
+    /// Writes the trailer for `raw_len` bytes of uncompressed data
+    /// with the supplied `checksum` into `out` (which is
+    /// `trailer_len` bytes long).
+    fn write_trailer(&self, checksum: u32, raw_len: usize, out: &mut [u8]);
+
+    /// Validates the header at the start of `in_data` and returns its
+    /// length (which may differ from `header_len`, e.g. for gzip
+    /// headers with optional fields).
+    fn read_header(&self, in_data: &[u8]) -> DecompressionResult<usize>;
+
+    /// Checks the `trailer` (`trailer_len` bytes) that follows the
+    /// DEFLATE data, given the `header` that was validated by
+    /// `read_header`, and the `checksum` and length of the
+    /// decompressed data.
+    fn check_trailer(&self,
+                     header: &[u8],
+                     trailer: &[u8],
+                     checksum: u32,
+                     raw_len: usize) -> DecompressionResult<()>;
+}
+
+/// Returns the maximum number of bytes required to encode `n_bytes`
+/// with `framing` (see
+/// [`Compressor::deflate_compress_bound`](../struct.Compressor.html#method.deflate_compress_bound)).
+pub fn compress_bound<F: Framing + ?Sized>(framing: &F,
+                                           compressor: &mut Compressor,
+                                           n_bytes: usize) -> usize {
+    framing.header_len()
+        .saturating_add(compressor.deflate_compress_bound(n_bytes))
+        .saturating_add(framing.trailer_len())
+}
+
+/// Compresses `in_raw_data` with `compressor` and wraps it with
+/// `framing`, writing the data into `out`. Returns the number of bytes
+/// written into `out`.
+pub fn compress<F: Framing + ?Sized>(framing: &F,
+                                     compressor: &mut Compressor,
+                                     in_raw_data: &[u8],
+                                     out: &mut [u8]) -> CompressionResult<usize> {
+    compress_uninit(framing, compressor, in_raw_data, unsafe { as_uninit_mut(out) })
+}
+
+/// Like [`compress`](fn.compress.html), but writes into a
+/// possibly-uninitialized buffer.
+pub fn compress_uninit<F: Framing + ?Sized>(framing: &F,
+                                            compressor: &mut Compressor,
+                                            in_raw_data: &[u8],
+                                            out: &mut [MaybeUninit<u8>]) -> CompressionResult<usize> {
+    let header_end = framing.header_len();
+    let trailer_len = framing.trailer_len();
+    if out.len() < header_end + trailer_len {
+        return Err(CompressionError::InsufficientSpace);
+    }
+
+    let deflate_end = out.len() - trailer_len;
+    let deflate_sz = compressor.deflate_compress_uninit(in_raw_data, &mut out[header_end..deflate_end])?;
+    let trailer_start = header_end + deflate_sz;
+    let trailer_end = trailer_start + trailer_len;
+
+    let mut checksum = F::Checksum::default();
+    checksum.update(in_raw_data);
+    framing.write_header(compressor.lvl(), in_raw_data.len(), zeroed(&mut out[..header_end]));
+    framing.write_trailer(checksum.sum(), in_raw_data.len(), zeroed(&mut out[trailer_start..trailer_end]));
+
+    Ok(trailer_end)
+}
+
+/// Decompresses the container at the start of `in_data`, which is
+/// wrapped with `framing`, writing the decompressed data into `out`.
+///
+/// Returns how many bytes of `in_data` the container occupies
+/// (including its trailer) and how many bytes were written into
+/// `out`. Any data after the container is ignored.
+pub fn decompress<F: Framing + ?Sized>(framing: &F,
+                                       decompressor: &mut Decompressor,
+                                       in_data: &[u8],
+                                       out: &mut [u8]) -> DecompressionResult<DecompressOutcome> {
+    decompress_uninit(framing, decompressor, in_data, unsafe { as_uninit_mut(out) })
+}
+
+/// Like [`decompress`](fn.decompress.html), but writes into a
+/// possibly-uninitialized buffer.
+pub fn decompress_uninit<F: Framing + ?Sized>(framing: &F,
+                                              decompressor: &mut Decompressor,
+                                              in_data: &[u8],
+                                              out: &mut [MaybeUninit<u8>]) -> DecompressionResult<DecompressOutcome> {
+    let header_end = framing.read_header(in_data)?;
+    let outcome = decompressor.deflate_decompress_impl(&in_data[header_end..], out, false)?;
+    let trailer_start = header_end + outcome.consumed;
+    let trailer_end = trailer_start + framing.trailer_len();
+    let trailer = in_data.get(trailer_start..trailer_end).ok_or(DecompressionError::Truncated)?;
+
+    let data = unsafe { assume_init(&out[..outcome.produced]) };
+    let mut checksum = F::Checksum::default();
+    checksum.update(data);
+    framing.check_trailer(&in_data[..header_end], trailer, checksum.sum(), data.len())?;
+
+    Ok(DecompressOutcome { consumed: trailer_end, produced: outcome.produced })
+}
+
+impl<F: Framing> Codec for F {
+    fn compress_bound(&self, compressor: &mut Compressor, n_bytes: usize) -> usize {
+        compress_bound(self, compressor, n_bytes)
+    }
+
+    fn compress(&self,
+                compressor: &mut Compressor,
+                in_raw_data: &[u8],
+                out: &mut [u8]) -> CompressionResult<usize> {
+        compress(self, compressor, in_raw_data, out)
+    }
+
+    fn decompress(&self,
+                  decompressor: &mut Decompressor,
+                  in_data: &[u8],
+                  out: &mut [u8]) -> DecompressionResult<usize> {
+        decompress(self, decompressor, in_data, out).map(|outcome| outcome.produced)
+    }
+
+    fn compress_uninit(&self,
+                       compressor: &mut Compressor,
+                       in_raw_data: &[u8],
+                       out: &mut [MaybeUninit<u8>],
+                       _: sealed::Token) -> CompressionResult<usize> {
+        compress_uninit(self, compressor, in_raw_data, out)
+    }
+
+    fn decompress_uninit(&self,
+                         decompressor: &mut Decompressor,
+                         in_data: &[u8],
+                         out: &mut [MaybeUninit<u8>],
+                         _: sealed::Token) -> DecompressionResult<usize> {
+        decompress_uninit(self, decompressor, in_data, out).map(|outcome| outcome.produced)
+    }
+}
+
+/// [`gzip`](https://tools.ietf.org/html/rfc1952) framing.
+///
+/// By default, this writes the same minimal header as
+/// [`Compressor::gzip_compress`](../struct.Compressor.html#method.gzip_compress)
+/// (so the output is identical), but it can be given a custom
+/// [`GzipHeader`](../gzip/struct.GzipHeader.html) instead. Any valid
+/// gzip header is accepted when decompressing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GzipFraming {
+    header: Option<GzipHeader>,
+}
+
+impl GzipFraming {
+    /// Returns a `GzipFraming` that writes `libdeflate`'s header.
+    pub fn new() -> GzipFraming {
+        GzipFraming { header: None }
+    }
+
+    /// Returns a `GzipFraming` that writes the supplied `header`.
+    pub fn with_header(header: GzipHeader) -> GzipFraming {
+        GzipFraming { header: Some(header) }
+    }
+
+    /// Returns the custom header that this framing writes, if any.
+    pub fn header(&self) -> Option<&GzipHeader> {
+        self.header.as_ref()
+    }
+}
+
+impl Framing for GzipFraming {
+    type Checksum = Crc;
+
+    fn header_len(&self) -> usize {
+        self.header.as_ref().map_or(gzip::MIN_HEADER_SIZE, GzipHeader::encoded_len)
+    }
+
+    fn write_header(&self, lvl: CompressionLvl, _raw_len: usize, out: &mut [u8]) {
+        let header_bytes = match &self.header {
+            Some(header) => header.to_bytes(),
+            None => GzipHeader { xfl: backend::gzip_xfl(lvl), ..GzipHeader::default() }.to_bytes(),
+        };
+        out.copy_from_slice(&header_bytes);
+    }
+
+    fn trailer_len(&self) -> usize {
+        gzip::FOOTER_SIZE
+    }
+
+    fn write_trailer(&self, checksum: u32, raw_len: usize, out: &mut [u8]) {
+        out[..4].copy_from_slice(&checksum.to_le_bytes());
+        out[4..].copy_from_slice(&(raw_len as u32).to_le_bytes());
+    }
+
+    fn read_header(&self, in_data: &[u8]) -> DecompressionResult<usize> {
+        gzip::header_len(in_data)
+    }
+
+    fn check_trailer(&self,
+                     _header: &[u8],
+                     trailer: &[u8],
+                     checksum: u32,
+                     raw_len: usize) -> DecompressionResult<()> {
+        let expected = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        if expected != checksum {
+            return Err(DecompressionError::CrcMismatch { expected, actual: checksum });
+        }
+
+        let expected = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+        let actual = raw_len as u32;
+        if expected != actual {
+            return Err(DecompressionError::SizeMismatch { expected, actual });
+        }
+        Ok(())
+    }
+}
+
+/// [`zlib`](https://tools.ietf.org/html/rfc1950) framing, which writes
+/// the same header as
+/// [`Compressor::zlib_compress`](../struct.Compressor.html#method.zlib_compress)
+/// (so the output is identical).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ZlibFraming;
+
+impl Framing for ZlibFraming {
+    type Checksum = Adler32;
+
+    fn header_len(&self) -> usize {
+        ZLIB_HEADER_SIZE
+    }
+
+    fn write_header(&self, lvl: CompressionLvl, _raw_len: usize, out: &mut [u8]) {
+        out.copy_from_slice(&backend::zlib_header(lvl));
+    }
+
+    fn trailer_len(&self) -> usize {
+        ZLIB_FOOTER_SIZE
+    }
+
+    fn write_trailer(&self, checksum: u32, _raw_len: usize, out: &mut [u8]) {
+        out.copy_from_slice(&checksum.to_be_bytes());
+    }
+
+    fn read_header(&self, in_data: &[u8]) -> DecompressionResult<usize> {
+        check_zlib_header(in_data)?;
+        Ok(ZLIB_HEADER_SIZE)
+    }
+
+    fn check_trailer(&self,
+                     _header: &[u8],
+                     trailer: &[u8],
+                     checksum: u32,
+                     _raw_len: usize) -> DecompressionResult<()> {
+        let expected = u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        if expected != checksum {
+            return Err(DecompressionError::Adler32Mismatch { expected, actual: checksum });
+        }
+        Ok(())
+    }
+}
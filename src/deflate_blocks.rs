@@ -0,0 +1,267 @@
+//! A minimal DEFLATE ([RFC1951](https://tools.ietf.org/html/rfc1951))
+//! parser that walks the blocks of a DEFLATE stream without producing
+//! any decompressed data.
+//!
+//! `libdeflate` always ends its output with a final block (one with
+//! BFINAL set) and does not report where that block starts.
+//! Concatenating independently compressed streams (see the `parallel`
+//! module) requires clearing that block's BFINAL bit and knowing
+//! exactly where, to the bit, the stream ends.
+
+/// Order in which code length code lengths are stored in a dynamic
+/// block header.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Number of extra bits that follow each length symbol (257..=285).
+const LENGTH_EXTRA_BITS: [u32; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// Number of extra bits that follow each distance symbol (0..=29).
+const DISTANCE_EXTRA_BITS: [u32; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+const MAX_CODE_LEN: usize = 15;
+
+/// Returns the bit offsets of the start of the final block of the
+/// DEFLATE stream at the start of `data` (i.e. the offset of its
+/// BFINAL bit) and of the first bit after the final block. Bit
+/// offsets count from the least significant bit of `data[0]`.
+///
+/// Returns `None` if `data` does not start with a valid DEFLATE
+/// stream.
+pub(crate) fn final_block_bounds(data: &[u8]) -> Option<(usize, usize)> {
+    let mut bits = BitReader::new(data);
+
+    loop {
+        let block_start = bits.position();
+        let bfinal = bits.take(1)?;
+        let btype = bits.take(2)?;
+
+        match btype {
+            0 => skip_stored_block(&mut bits)?,
+            1 => {
+                let (litlen, distance) = fixed_codes();
+                skip_huffman_block(&mut bits, &litlen, &distance)?
+            },
+            2 => {
+                let (litlen, distance) = read_dynamic_codes(&mut bits)?;
+                skip_huffman_block(&mut bits, &litlen, &distance)?
+            },
+            _ => return None,
+        }
+
+        if bfinal == 1 {
+            return Some((block_start, bits.position()));
+        }
+    }
+}
+
+fn skip_stored_block(bits: &mut BitReader<'_>) -> Option<()> {
+    bits.align_to_byte();
+    let len = bits.take(16)?;
+    let nlen = bits.take(16)?;
+    if len != !nlen & 0xffff {
+        return None;
+    }
+    bits.skip_bytes(len as usize)
+}
+
+fn skip_huffman_block(bits: &mut BitReader<'_>, litlen: &Huffman, distance: &Huffman) -> Option<()> {
+    loop {
+        match litlen.decode(bits)? as usize {
+            0..=255 => {},
+            256 => return Some(()),
+            sym @ 257..=285 => {
+                bits.take(LENGTH_EXTRA_BITS[sym - 257])?;
+                let dist_sym = distance.decode(bits)? as usize;
+                bits.take(*DISTANCE_EXTRA_BITS.get(dist_sym)?)?;
+            },
+            _ => return None,
+        }
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut litlen_lens = [0; 288];
+    litlen_lens[..144].iter_mut().for_each(|l| *l = 8);
+    litlen_lens[144..256].iter_mut().for_each(|l| *l = 9);
+    litlen_lens[256..280].iter_mut().for_each(|l| *l = 7);
+    litlen_lens[280..].iter_mut().for_each(|l| *l = 8);
+    let distance_lens = [5; 32];
+
+    (Huffman::new(&litlen_lens).unwrap(), Huffman::new(&distance_lens).unwrap())
+}
+
+fn read_dynamic_codes(bits: &mut BitReader<'_>) -> Option<(Huffman, Huffman)> {
+    let n_litlen = bits.take(5)? as usize + 257;
+    let n_distance = bits.take(5)? as usize + 1;
+    let n_code_length = bits.take(4)? as usize + 4;
+
+    let mut code_length_lens = [0; 19];
+    for &i in &CODE_LENGTH_ORDER[..n_code_length] {
+        code_length_lens[i] = bits.take(3)? as u8;
+    }
+    let code_length = Huffman::new(&code_length_lens)?;
+
+    let mut lens = [0; 286 + 32];
+    let n_lens = n_litlen + n_distance;
+    let mut i = 0;
+    while i < n_lens {
+        let (len, repeat) = match code_length.decode(bits)? {
+            sym @ 0..=15 => (sym as u8, 1),
+            16 => (*lens.get(i.checked_sub(1)?)?, 3 + bits.take(2)? as usize),
+            17 => (0, 3 + bits.take(3)? as usize),
+            18 => (0, 11 + bits.take(7)? as usize),
+            _ => return None,
+        };
+        if i + repeat > n_lens {
+            return None;
+        }
+        lens[i..i + repeat].iter_mut().for_each(|l| *l = len);
+        i += repeat;
+    }
+
+    // the end-of-block symbol must be encodable
+    if lens[256] == 0 {
+        return None;
+    }
+
+    Some((Huffman::new(&lens[..n_litlen])?, Huffman::new(&lens[n_litlen..n_lens])?))
+}
+
+/// A table-based canonical Huffman decoder. Each table entry holds
+/// `(symbol << 4) | code length`, and a code length of 0 marks an
+/// unused code.
+struct Huffman {
+    table: Vec<u16>,
+    table_bits: u32,
+}
+
+impl Huffman {
+    /// Builds a decoder from the code length of each symbol. Returns
+    /// `None` if the lengths are over-subscribed. Incomplete codes are
+    /// allowed (e.g. a distance code with a single symbol).
+    fn new(lens: &[u8]) -> Option<Huffman> {
+        let mut count = [0u16; MAX_CODE_LEN + 1];
+        for &len in lens {
+            count[len as usize] += 1;
+        }
+        count[0] = 0;
+
+        let mut next_code = [0u32; MAX_CODE_LEN + 1];
+        let mut code = 0u32;
+        for len in 1..=MAX_CODE_LEN {
+            code = (code + count[len - 1] as u32) << 1;
+            next_code[len] = code;
+            if code + count[len] as u32 > (1 << len) {
+                return None;
+            }
+        }
+
+        let table_bits = core::cmp::max(1, lens.iter().copied().max().unwrap_or(0) as u32);
+        let table_size = 1usize << table_bits;
+        let mut table = vec![0u16; table_size];
+
+        for (sym, &len) in lens.iter().enumerate() {
+            if len == 0 {
+                continue;
+            }
+            let len = len as u32;
+            let code = next_code[len as usize];
+            next_code[len as usize] += 1;
+
+            // Huffman codes are packed starting from their most
+            // significant bit, so reverse them for LSB-first lookup
+            let reversed = code.reverse_bits() >> (32 - len);
+            let entry = ((sym as u16) << 4) | len as u16;
+            for i in (reversed as usize..table_size).step_by(1 << len) {
+                table[i] = entry;
+            }
+        }
+
+        Some(Huffman { table, table_bits })
+    }
+
+    fn decode(&self, bits: &mut BitReader<'_>) -> Option<u16> {
+        if bits.count < self.table_bits {
+            bits.refill();
+        }
+        let entry = self.table[(bits.buf & ((1 << self.table_bits) - 1)) as usize];
+        let len = (entry & 0xf) as u32;
+        if len == 0 || len > bits.count {
+            return None;
+        }
+        bits.consume(len);
+        Some(entry >> 4)
+    }
+}
+
+/// Reads bits from a byte slice, least significant bit first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    next_byte: usize,
+    buf: u64,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader { data, next_byte: 0, buf: 0, count: 0 }
+    }
+
+    /// Returns the offset, in bits, of the next unread bit.
+    fn position(&self) -> usize {
+        self.next_byte * 8 - self.count as usize
+    }
+
+    fn refill(&mut self) {
+        while self.count <= 56 {
+            match self.data.get(self.next_byte) {
+                Some(&b) => {
+                    self.buf |= (b as u64) << self.count;
+                    self.count += 8;
+                    self.next_byte += 1;
+                },
+                None => return,
+            }
+        }
+    }
+
+    fn consume(&mut self, n: u32) {
+        self.buf >>= n;
+        self.count -= n;
+    }
+
+    /// Reads `n` (at most 32) bits.
+    fn take(&mut self, n: u32) -> Option<u32> {
+        if self.count < n {
+            self.refill();
+            if self.count < n {
+                return None;
+            }
+        }
+        let val = (self.buf & ((1u64 << n) - 1)) as u32;
+        self.consume(n);
+        Some(val)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.consume(self.count % 8);
+    }
+
+    /// Skips `n` whole bytes. Must be called when byte-aligned.
+    fn skip_bytes(&mut self, n: usize) -> Option<()> {
+        let next_byte = self.position() / 8 + n;
+        if next_byte > self.data.len() {
+            return None;
+        }
+        self.next_byte = next_byte;
+        self.buf = 0;
+        self.count = 0;
+        Some(())
+    }
+}
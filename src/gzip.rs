@@ -0,0 +1,383 @@
+//! Support for the full [`gzip`](https://tools.ietf.org/html/rfc1952)
+//! member header, and for streaming gzip data through `std::io`.
+//!
+//! `libdeflate` always writes a fixed, minimal gzip header when
+//! compressing and skips over whatever header was present when
+//! decompressing. [`GzipHeader`] models every header field (FTEXT,
+//! MTIME, XFL, OS, FEXTRA, FNAME, FCOMMENT, and FHCRC) so that it can
+//! be parsed from existing gzip data with [`GzipHeader::parse`], or
+//! written when compressing with
+//! [`Compressor::gzip_compress_with_header`].
+//!
+//! [`GzipWriter`] compresses data of unbounded length, written through
+//! `std::io::Write`, into a series of gzip members while only
+//! buffering a fixed-size block at a time, and [`GzipReader`] reads
+//! such data back through `std::io::Read` one member at a time. Both
+//! require the `std` feature.
+//!
+//! [`GzipHeader`]: struct.GzipHeader.html
+//! [`GzipWriter`]: struct.GzipWriter.html
+//! [`GzipReader`]: struct.GzipReader.html
+//! [`GzipHeader::parse`]: struct.GzipHeader.html#method.parse
+//! [`Compressor::gzip_compress_with_header`]: ../struct.Compressor.html#method.gzip_compress_with_header
+
+use alloc::vec::Vec;
+use crate::{crc32, Crc, DecompressionError};
+
+#[cfg(feature = "std")]
+mod reader;
+#[cfg(feature = "std")]
+mod writer;
+
+#[cfg(feature = "std")]
+pub use self::reader::{GzipReader, DEFAULT_MAX_MEMBER_SIZE};
+#[cfg(feature = "std")]
+pub use self::writer::{GzipWriter, DEFAULT_BLOCK_SIZE};
+
+const ID1: u8 = 0x1f;
+const ID2: u8 = 0x8b;
+const CM_DEFLATE: u8 = 8;
+
+const FTEXT: u8 = 0x01;
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+const FRESERVED: u8 = 0xe0;
+
+/// Size of the fixed part of a gzip header (ID1 through OS).
+pub(crate) const MIN_HEADER_SIZE: usize = 10;
+
+/// Size of a gzip trailer (CRC32 and ISIZE).
+pub(crate) const FOOTER_SIZE: usize = 8;
+
+/// Value of the OS header field when the operating system is not
+/// known. This is what `libdeflate` writes.
+pub const OS_UNKNOWN: u8 = 255;
+
+/// Value of the OS header field for Unix systems. This is what GNU
+/// gzip writes on Unix systems.
+pub const OS_UNIX: u8 = 3;
+
+/// The header of a single [`gzip`](https://tools.ietf.org/html/rfc1952)
+/// member.
+///
+/// The default header is equivalent to the minimal header written by
+/// `libdeflate` at the default compression level (no optional fields,
+/// MTIME of 0, and an unknown OS).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GzipHeader {
+    /// FTEXT: the uncompressed data is probably ASCII text.
+    pub text: bool,
+
+    /// MTIME: modification time of the original file, in seconds
+    /// since the Unix epoch. 0 means that no timestamp is available,
+    /// which is useful for reproducible builds.
+    pub mtime: u32,
+
+    /// XFL: extra flags. 2 indicates that the slowest (best)
+    /// compression was used, 4 that the fastest compression was
+    /// used.
+    pub xfl: u8,
+
+    /// OS: the filesystem on which compression took place (e.g.
+    /// [`OS_UNIX`](constant.OS_UNIX.html) or
+    /// [`OS_UNKNOWN`](constant.OS_UNKNOWN.html)).
+    pub os: u8,
+
+    /// FEXTRA: the raw extra field, which is a sequence of subfields
+    /// (see [`extra_subfields`](#method.extra_subfields)). Must not be
+    /// longer than 65535 bytes.
+    pub extra: Option<Vec<u8>>,
+
+    /// FNAME: the original file name (ISO 8859-1), without its
+    /// terminating zero byte. Must not contain zero bytes.
+    pub filename: Option<Vec<u8>>,
+
+    /// FCOMMENT: a file comment (ISO 8859-1), without its terminating
+    /// zero byte. Must not contain zero bytes.
+    pub comment: Option<Vec<u8>>,
+
+    /// FHCRC: whether the header is protected by a CRC16 (the two
+    /// least significant bytes of the CRC32 of the header).
+    pub header_crc: bool,
+}
+
+impl Default for GzipHeader {
+    fn default() -> Self {
+        GzipHeader {
+            text: false,
+            mtime: 0,
+            xfl: 0,
+            os: OS_UNKNOWN,
+            extra: None,
+            filename: None,
+            comment: None,
+            header_crc: false,
+        }
+    }
+}
+
+impl GzipHeader {
+    /// Parses the gzip header at the start of `data`.
+    ///
+    /// Returns the parsed header and the offset of the first byte
+    /// after the header (i.e. the start of the member's DEFLATE data),
+    /// or an error describing why `data` does not start with a valid
+    /// gzip header (e.g. `DecompressionError::BadMagic`, or
+    /// `DecompressionError::Truncated` if `data` ends before the end
+    /// of the header). If the header has an FHCRC field, it is
+    /// verified.
+    pub fn parse(data: &[u8]) -> Result<(GzipHeader, usize), DecompressionError> {
+        let raw = RawHeader::parse(data)?;
+        let header = GzipHeader {
+            text: raw.flg & FTEXT != 0,
+            mtime: raw.mtime,
+            xfl: raw.xfl,
+            os: raw.os,
+            extra: raw.extra.map(|b| b.to_vec()),
+            filename: raw.filename.map(|b| b.to_vec()),
+            comment: raw.comment.map(|b| b.to_vec()),
+            header_crc: raw.flg & FHCRC != 0,
+        };
+        Ok((header, raw.len))
+    }
+
+    /// Returns the number of bytes that this header occupies when
+    /// written with [`to_bytes`](#method.to_bytes).
+    pub fn encoded_len(&self) -> usize {
+        let mut len = MIN_HEADER_SIZE;
+        if let Some(extra) = &self.extra {
+            len += 2 + extra.len();
+        }
+        if let Some(filename) = &self.filename {
+            len += filename.len() + 1;
+        }
+        if let Some(comment) = &self.comment {
+            len += comment.len() + 1;
+        }
+        if self.header_crc {
+            len += 2;
+        }
+        len
+    }
+
+    /// Returns this header encoded as gzip header bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `extra` is longer than 65535 bytes, or if `filename`
+    /// or `comment` contain a zero byte.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut flg = 0;
+        if self.text {
+            flg |= FTEXT;
+        }
+        if self.header_crc {
+            flg |= FHCRC;
+        }
+        if self.extra.is_some() {
+            flg |= FEXTRA;
+        }
+        if self.filename.is_some() {
+            flg |= FNAME;
+        }
+        if self.comment.is_some() {
+            flg |= FCOMMENT;
+        }
+
+        let mut out = Vec::with_capacity(self.encoded_len());
+        out.extend_from_slice(&[ID1, ID2, CM_DEFLATE, flg]);
+        out.extend_from_slice(&self.mtime.to_le_bytes());
+        out.push(self.xfl);
+        out.push(self.os);
+
+        if let Some(extra) = &self.extra {
+            assert!(extra.len() <= u16::MAX as usize, "gzip FEXTRA field is longer than 65535 bytes");
+            out.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+            out.extend_from_slice(extra);
+        }
+        if let Some(filename) = &self.filename {
+            assert!(!filename.contains(&0), "gzip FNAME field contains a zero byte");
+            out.extend_from_slice(filename);
+            out.push(0);
+        }
+        if let Some(comment) = &self.comment {
+            assert!(!comment.contains(&0), "gzip FCOMMENT field contains a zero byte");
+            out.extend_from_slice(comment);
+            out.push(0);
+        }
+        if self.header_crc {
+            let mut crc = Crc::new();
+            crc.update(&out);
+            out.extend_from_slice(&(crc.sum() as u16).to_le_bytes());
+        }
+
+        out
+    }
+
+    /// Returns an iterator over the `(subfield ID, subfield data)`
+    /// pairs in the FEXTRA field. Iteration stops early if the field
+    /// is malformed.
+    pub fn extra_subfields(&self) -> ExtraSubfields<'_> {
+        ExtraSubfields {
+            remaining: self.extra.as_deref().unwrap_or(&[]),
+        }
+    }
+
+    /// Returns the data of the first FEXTRA subfield with the given
+    /// two-byte subfield ID, if any.
+    pub fn extra_subfield(&self, id: [u8; 2]) -> Option<&[u8]> {
+        self.extra_subfields()
+            .find(|(subfield_id, _)| *subfield_id == id)
+            .map(|(_, data)| data)
+    }
+
+    /// Appends a subfield with the given two-byte subfield ID to the
+    /// FEXTRA field, creating the field if necessary.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` is longer than 65535 bytes.
+    pub fn push_extra_subfield(&mut self, id: [u8; 2], data: &[u8]) {
+        assert!(data.len() <= u16::MAX as usize, "gzip FEXTRA subfield is longer than 65535 bytes");
+        let extra = self.extra.get_or_insert_with(Vec::new);
+        extra.extend_from_slice(&id);
+        extra.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        extra.extend_from_slice(data);
+    }
+}
+
+/// An iterator over the subfields of a gzip FEXTRA field. Created
+/// by [`GzipHeader::extra_subfields`](struct.GzipHeader.html#method.extra_subfields).
+pub struct ExtraSubfields<'a> {
+    remaining: &'a [u8],
+}
+
+impl<'a> Iterator for ExtraSubfields<'a> {
+    type Item = ([u8; 2], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining.len() < 4 {
+            return None;
+        }
+        let id = [self.remaining[0], self.remaining[1]];
+        let len = u16::from_le_bytes([self.remaining[2], self.remaining[3]]) as usize;
+        let data = self.remaining.get(4..4 + len)?;
+        self.remaining = &self.remaining[4 + len..];
+        Some((id, data))
+    }
+}
+
+/// The fields of a gzip header, borrowed from the data that they were
+/// parsed from.
+struct RawHeader<'a> {
+    flg: u8,
+    mtime: u32,
+    xfl: u8,
+    os: u8,
+    extra: Option<&'a [u8]>,
+    filename: Option<&'a [u8]>,
+    comment: Option<&'a [u8]>,
+    len: usize,
+}
+
+impl<'a> RawHeader<'a> {
+    fn parse(data: &'a [u8]) -> Result<RawHeader<'a>, DecompressionError> {
+        // check each fixed field as soon as it is available, so that
+        // (e.g.) non-gzip data is reported as such even if it is short
+        if data.first().is_some_and(|b| *b != ID1) || data.get(1).is_some_and(|b| *b != ID2) {
+            return Err(DecompressionError::BadMagic);
+        }
+        if let Some(&cm) = data.get(2) {
+            if cm != CM_DEFLATE {
+                return Err(DecompressionError::UnsupportedMethod(cm));
+            }
+        }
+        if let Some(&flg) = data.get(3) {
+            if flg & FRESERVED != 0 {
+                return Err(DecompressionError::ReservedFlags(flg));
+            }
+        }
+        if data.len() < MIN_HEADER_SIZE {
+            return Err(DecompressionError::Truncated);
+        }
+
+        let flg = data[3];
+        let mut header = RawHeader {
+            flg,
+            mtime: u32::from_le_bytes([data[4], data[5], data[6], data[7]]),
+            xfl: data[8],
+            os: data[9],
+            extra: None,
+            filename: None,
+            comment: None,
+            len: 0,
+        };
+        let mut pos = MIN_HEADER_SIZE;
+
+        if flg & FEXTRA != 0 {
+            let xlen_bytes = data.get(pos..pos + 2).ok_or(DecompressionError::Truncated)?;
+            let xlen = u16::from_le_bytes([xlen_bytes[0], xlen_bytes[1]]) as usize;
+            pos += 2;
+            header.extra = Some(data.get(pos..pos + xlen).ok_or(DecompressionError::Truncated)?);
+            pos += xlen;
+        }
+
+        if flg & FNAME != 0 {
+            let (filename, len) = read_zero_terminated(&data[pos..])?;
+            header.filename = Some(filename);
+            pos += len;
+        }
+
+        if flg & FCOMMENT != 0 {
+            let (comment, len) = read_zero_terminated(&data[pos..])?;
+            header.comment = Some(comment);
+            pos += len;
+        }
+
+        if flg & FHCRC != 0 {
+            let crc16_bytes = data.get(pos..pos + 2).ok_or(DecompressionError::Truncated)?;
+            let expected = u16::from_le_bytes([crc16_bytes[0], crc16_bytes[1]]);
+            let mut crc = Crc::new();
+            crc.update(&data[..pos]);
+            if crc.sum() as u16 != expected {
+                return Err(DecompressionError::HeaderChecksumMismatch);
+            }
+            pos += 2;
+        }
+
+        header.len = pos;
+        Ok(header)
+    }
+}
+
+/// Validates the gzip header at the start of `data` and returns its
+/// length, without allocating.
+pub(crate) fn header_len(data: &[u8]) -> Result<usize, DecompressionError> {
+    RawHeader::parse(data).map(|raw| raw.len)
+}
+
+/// Checks a gzip trailer (`footer`, which must be `FOOTER_SIZE` bytes
+/// long) against the decompressed data of its member.
+pub(crate) fn check_footer(footer: &[u8], decompressed: &[u8]) -> Result<(), DecompressionError> {
+    let expected = u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]);
+    let actual = crc32(decompressed);
+    if expected != actual {
+        return Err(DecompressionError::CrcMismatch { expected, actual });
+    }
+
+    let expected = u32::from_le_bytes([footer[4], footer[5], footer[6], footer[7]]);
+    let actual = decompressed.len() as u32;
+    if expected != actual {
+        return Err(DecompressionError::SizeMismatch { expected, actual });
+    }
+    Ok(())
+}
+
+/// Returns the bytes before the first zero byte in `data` and the
+/// number of bytes occupied by them, including the zero byte.
+fn read_zero_terminated(data: &[u8]) -> Result<(&[u8], usize), DecompressionError> {
+    let len = data.iter().position(|b| *b == 0).ok_or(DecompressionError::Truncated)?;
+    Ok((&data[..len], len + 1))
+}
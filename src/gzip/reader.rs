@@ -0,0 +1,199 @@
+use std::io::{self, BufRead, Read};
+use crate::gzip::{GzipHeader, FOOTER_SIZE};
+use crate::{deflate_blocks, DecompressionError, Decompressor};
+
+/// Default limit on the size of a single gzip member read by a
+/// [`GzipReader`](struct.GzipReader.html) (1 GiB).
+pub const DEFAULT_MAX_MEMBER_SIZE: usize = 1 << 30;
+
+/// Minimum number of bytes requested from the inner reader at a time.
+const MIN_READ_SIZE: usize = 64 * 1024;
+
+/// A reader that decompresses (possibly multi-member) gzip data from
+/// an inner reader one member at a time.
+///
+/// Each member is buffered in full, decompressed in one go with a
+/// [`Decompressor`] into a buffer sized from the member's ISIZE
+/// trailer, and then served through `std::io::Read` and
+/// `std::io::BufRead`. Memory usage is therefore bounded by the size
+/// of the largest member rather than the size of the whole stream,
+/// which suits data written in blocks (e.g. by `pigz -i`, `bgzip`, or
+/// a [`GzipWriter`]).
+///
+/// Errors are reported as `io::Error`s that wrap a
+/// [`DecompressionError`](../enum.DecompressionError.html). A member
+/// whose compressed or decompressed size exceeds the configured
+/// maximum member size causes an `io::ErrorKind::InvalidData` error
+/// that wraps `DecompressionError::OutputLimitExceeded`, and a stream
+/// that ends part-way through a member causes an
+/// `io::ErrorKind::UnexpectedEof` error that wraps
+/// `DecompressionError::Truncated`.
+///
+/// ```
+/// use std::io::{Read, Write};
+/// use libdeflater::CompressionLvl;
+/// use libdeflater::gzip::{GzipReader, GzipWriter};
+///
+/// let mut writer = GzipWriter::new(Vec::new(), CompressionLvl::default());
+/// writer.write_all(b"hello, world!").unwrap();
+/// let gz_data = writer.finish().unwrap();
+///
+/// let mut reader = GzipReader::new(&gz_data[..]);
+/// let mut data = String::new();
+/// reader.read_to_string(&mut data).unwrap();
+/// assert_eq!(data, "hello, world!");
+/// ```
+///
+/// [`Decompressor`]: ../struct.Decompressor.html
+/// [`GzipWriter`]: struct.GzipWriter.html
+pub struct GzipReader<R: Read> {
+    inner: R,
+    decompressor: Decompressor,
+    max_member_size: usize,
+    input: Vec<u8>,
+    eof: bool,
+    output: Vec<u8>,
+    output_pos: usize,
+}
+
+impl<R: Read> GzipReader<R> {
+    /// Returns a new `GzipReader` that reads gzip data from `inner`
+    /// and rejects members larger than
+    /// [`DEFAULT_MAX_MEMBER_SIZE`](constant.DEFAULT_MAX_MEMBER_SIZE.html).
+    pub fn new(inner: R) -> GzipReader<R> {
+        Self::with_max_member_size(inner, DEFAULT_MAX_MEMBER_SIZE)
+    }
+
+    /// Returns a new `GzipReader` that reads gzip data from `inner`
+    /// and rejects members whose compressed or decompressed size is
+    /// larger than `max_member_size` bytes.
+    pub fn with_max_member_size(inner: R, max_member_size: usize) -> GzipReader<R> {
+        GzipReader {
+            inner,
+            decompressor: Decompressor::new(),
+            max_member_size,
+            input: Vec::new(),
+            eof: false,
+            output: Vec::new(),
+            output_pos: 0,
+        }
+    }
+
+    /// Returns a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner reader.
+    ///
+    /// Reading from the inner reader directly is likely to corrupt
+    /// the decompressed output.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Returns the inner reader. Any data that was read from it but
+    /// not yet decompressed is lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Decompresses the next member into `output`. Returns `false` if
+    /// there are no more members.
+    fn read_member(&mut self) -> io::Result<bool> {
+        let member_len = loop {
+            if self.input.is_empty() && self.eof {
+                return Ok(false);
+            }
+            if let Some(len) = self.member_len()? {
+                break len;
+            }
+            if self.eof {
+                return Err(DecompressionError::Truncated.into());
+            }
+            if self.input.len() > self.max_member_size {
+                return Err(DecompressionError::OutputLimitExceeded.into());
+            }
+            self.fill_input()?;
+        };
+
+        self.output = self.decompressor
+            .gzip_decompress_to_vec(&self.input[..member_len], self.max_member_size)?;
+        self.output_pos = 0;
+        self.input.drain(..member_len);
+        Ok(true)
+    }
+
+    /// Returns the length of the member at the start of `input`, or
+    /// `None` if more input is needed to find where it ends.
+    fn member_len(&self) -> io::Result<Option<usize>> {
+        let data = &self.input;
+
+        // header errors other than truncation are reported straight
+        // away, rather than after buffering the rest of the stream
+        let (header, header_len) = match GzipHeader::parse(data) {
+            Ok(parsed) => parsed,
+            Err(DecompressionError::Truncated) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        // BGZF blocks record their own size, so there's no need to
+        // walk the DEFLATE data
+        let len = match header.extra_subfield(*b"BC") {
+            Some(&[lo, hi]) => u16::from_le_bytes([lo, hi]) as usize + 1,
+            _ => match deflate_blocks::final_block_bounds(&data[header_len..]) {
+                Some((_, end)) => header_len + end.div_ceil(8) + FOOTER_SIZE,
+                None => return Ok(None),
+            },
+        };
+
+        if len > self.max_member_size {
+            return Err(DecompressionError::OutputLimitExceeded.into());
+        }
+        Ok(if len <= data.len() { Some(len) } else { None })
+    }
+
+    /// Reads at least as much data again as is currently buffered
+    /// from the inner reader, stopping early at EOF, but never buffers
+    /// more than one byte beyond the maximum member size.
+    fn fill_input(&mut self) -> io::Result<()> {
+        let start = self.input.len();
+        let target = std::cmp::min(std::cmp::max(2 * start, MIN_READ_SIZE),
+                                   self.max_member_size.saturating_add(1));
+        self.input.reserve_exact(target - start);
+
+        // `read_to_end` reads into the reserved capacity without
+        // zero-filling it first, and keeps whatever it read if it fails
+        (&mut self.inner).take((target - start) as u64).read_to_end(&mut self.input)?;
+        if self.input.len() < target {
+            self.eof = true;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for GzipReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = std::cmp::min(available.len(), buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read> BufRead for GzipReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // loop, because members may be empty
+        while self.output_pos == self.output.len() {
+            if !self.read_member()? {
+                break;
+            }
+        }
+        Ok(&self.output[self.output_pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.output_pos = std::cmp::min(self.output_pos + amt, self.output.len());
+    }
+}
@@ -0,0 +1,196 @@
+use std::io::{self, Write};
+use crate::{bgzf, CompressionLvl, Compressor};
+
+/// Default number of uncompressed bytes in each member written by a
+/// [`GzipWriter`](struct.GzipWriter.html) (1 MiB).
+pub const DEFAULT_BLOCK_SIZE: usize = 1 << 20;
+
+/// A writer that gzip-compresses everything written to it with bounded
+/// memory usage.
+///
+/// Written data is buffered until a block of `block_size` bytes is
+/// available, which is then compressed into a complete, independent
+/// gzip member and written to the inner writer. The output is
+/// therefore multi-member gzip data, which any gunzip (and
+/// [`Decompressor::gzip_decompress_multi`]) can read. Peak memory
+/// usage is roughly twice the block size.
+///
+/// A `GzipWriter` created with [`bgzf`](#method.bgzf) writes BGZF
+/// blocks instead, followed by the BGZF EOF marker.
+///
+/// [`finish`](#method.finish) must be called to write the remaining
+/// buffered data and to observe any errors. Dropping a `GzipWriter`
+/// finishes it but ignores errors.
+///
+/// ```
+/// use std::io::Write;
+/// use libdeflater::CompressionLvl;
+/// use libdeflater::gzip::GzipWriter;
+///
+/// let mut writer = GzipWriter::new(Vec::new(), CompressionLvl::default());
+/// writer.write_all(b"hello, world!").unwrap();
+/// let gz_data = writer.finish().unwrap();
+/// ```
+///
+/// [`Decompressor::gzip_decompress_multi`]: ../struct.Decompressor.html#method.gzip_decompress_multi
+pub struct GzipWriter<W: Write> {
+    inner: Option<W>,
+    compressor: Compressor,
+    block_size: usize,
+    bgzf: bool,
+    buf: Vec<u8>,
+    out: Vec<u8>,
+    /// The length of the compressed member in `out` that is being
+    /// written, or 0 if there is none.
+    out_len: usize,
+    /// The number of bytes of that member that have been written.
+    out_pos: usize,
+    wrote_member: bool,
+}
+
+impl<W: Write> GzipWriter<W> {
+    /// Returns a new `GzipWriter` that writes gzip members of up to
+    /// [`DEFAULT_BLOCK_SIZE`](constant.DEFAULT_BLOCK_SIZE.html)
+    /// uncompressed bytes to `inner`.
+    pub fn new(inner: W, lvl: CompressionLvl) -> GzipWriter<W> {
+        Self::with_block_size(inner, lvl, DEFAULT_BLOCK_SIZE)
+    }
+
+    /// Returns a new `GzipWriter` that writes gzip members of up to
+    /// `block_size` uncompressed bytes to `inner`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_size` is 0.
+    pub fn with_block_size(inner: W, lvl: CompressionLvl, block_size: usize) -> GzipWriter<W> {
+        assert!(block_size > 0, "a GzipWriter's block size must be greater than 0");
+        GzipWriter {
+            inner: Some(inner),
+            compressor: Compressor::new(lvl),
+            block_size,
+            bgzf: false,
+            buf: Vec::new(),
+            out: Vec::new(),
+            out_len: 0,
+            out_pos: 0,
+            wrote_member: false,
+        }
+    }
+
+    /// Returns a new `GzipWriter` that writes
+    /// [BGZF](../bgzf/index.html) blocks to `inner`, and terminates
+    /// them with the BGZF EOF marker when finished.
+    pub fn bgzf(inner: W, lvl: CompressionLvl) -> GzipWriter<W> {
+        let mut writer = Self::with_block_size(inner, lvl, bgzf::MAX_BLOCK_DATA_SIZE);
+        writer.bgzf = true;
+        writer
+    }
+
+    /// Returns a reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().unwrap()
+    }
+
+    /// Returns a mutable reference to the inner writer.
+    ///
+    /// Writing to the inner writer directly is likely to corrupt the
+    /// gzip output.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.as_mut().unwrap()
+    }
+
+    /// Compresses and writes any buffered data, writes the BGZF EOF
+    /// marker (for BGZF writers) or an empty member (if nothing was
+    /// written, so that the output is valid gzip data), flushes the
+    /// inner writer, and returns it.
+    ///
+    /// If this fails, the writer is not finished again when it is
+    /// dropped.
+    pub fn finish(mut self) -> io::Result<W> {
+        let result = self.try_finish();
+        // taking `inner` stops `Drop` from retrying, which would write
+        // an EOF marker or an empty member after the failed write
+        let inner = self.inner.take().unwrap();
+        result.map(|()| inner)
+    }
+
+    fn try_finish(&mut self) -> io::Result<()> {
+        if !self.buf.is_empty() || self.out_len > 0 || (!self.wrote_member && !self.bgzf) {
+            self.write_member()?;
+        }
+        let inner = self.inner.as_mut().unwrap();
+        if self.bgzf {
+            inner.write_all(&bgzf::EOF_MARKER)?;
+        }
+        inner.flush()
+    }
+
+    /// Compresses the buffered data into one gzip member (or BGZF
+    /// block) and writes it to the inner writer.
+    ///
+    /// If the inner writer fails, the compressed member and the number
+    /// of its bytes that were written are kept, so that the next call
+    /// resumes writing it where it stopped rather than writing it
+    /// again.
+    fn write_member(&mut self) -> io::Result<()> {
+        if self.out_len == 0 {
+            let sz = if self.bgzf {
+                self.out.resize(bgzf::MAX_BLOCK_SIZE, 0);
+                bgzf::compress_block(&mut self.compressor, &self.buf, &mut self.out)
+            } else {
+                self.out.resize(self.compressor.gzip_compress_bound(self.buf.len()), 0);
+                self.compressor.gzip_compress(&self.buf, &mut self.out)
+            }.expect("output buffer is sized by the compression bound");
+            self.out_len = sz;
+            self.out_pos = 0;
+        }
+
+        let inner = self.inner.as_mut().unwrap();
+        while self.out_pos < self.out_len {
+            match inner.write(&self.out[self.out_pos..self.out_len]) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write the whole gzip member")),
+                Ok(n) => self.out_pos += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+        self.out_len = 0;
+        self.buf.clear();
+        self.wrote_member = true;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for GzipWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        // a partially-written member must be finished before its block
+        // can take more data
+        if self.buf.len() == self.block_size || self.out_len > 0 {
+            self.write_member()?;
+        }
+        if self.buf.capacity() == 0 {
+            self.buf.reserve_exact(self.block_size);
+        }
+        let n = std::cmp::min(data.len(), self.block_size - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        Ok(n)
+    }
+
+    /// Compresses and writes any buffered data as a complete gzip
+    /// member, then flushes the inner writer. Frequent flushing
+    /// produces many small members, which compress poorly.
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buf.is_empty() || self.out_len > 0 {
+            self.write_member()?;
+        }
+        self.inner.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Write> Drop for GzipWriter<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
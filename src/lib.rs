@@ -20,6 +20,9 @@
 //! - DEFLATE data ([`deflate_decompress`])
 //! - zlib data ([`zlib_decompress`])
 //! - gzip data ([`gzip_decompress`])
+//! - multi-member (concatenated) gzip data ([`gzip_decompress_multi`])
+//! - any of the above, auto-detected with [`detect_format`]
+//!   ([`decompress_auto`])
 //!
 //! **Note**: `libdeflate` requires that the input *and* output
 //! buffers are pre-allocated before decompressing. Because of this,
@@ -27,11 +30,37 @@
 //! compressed data will decompress to; otherwise, a `decompress_*`
 //! function call will return `DecompressionError::InsufficientSpace`
 //!
+//! Each decompression method also has an `_ex` variant (e.g.
+//! [`gzip_decompress_ex`]) that reports how many bytes of compressed
+//! input were consumed. This is useful when the compressed data is
+//! embedded in a larger container and its length is not known
+//! up-front. When the decompressed size *is* known up-front, the
+//! `_exact` variants (e.g. [`gzip_decompress_exact`]) require the
+//! output buffer to be filled exactly.
+//!
+//! The `_uninit` variants (e.g. [`gzip_decompress_uninit`]) write into
+//! a `&mut [MaybeUninit<u8>]`, and the `_append` variants (e.g.
+//! [`gzip_decompress_append`]) write into the spare capacity of a
+//! `Vec<u8>`, so that large output buffers don't have to be
+//! zero-filled before `libdeflate` overwrites them.
+//!
+//! When the format is only known at runtime, [`decompress`] takes a
+//! [`Format`] instead.
+//!
 //! [`Decompressor::new`]: struct.Decompressor.html#method.new
+//! [`decompress`]: struct.Decompressor.html#method.decompress
+//! [`Format`]: enum.Format.html
 //! [`Decompressor`]: struct.Decompressor.html
 //! [`deflate_decompress`]: struct.Decompressor.html#method.deflate_decompress
 //! [`zlib_decompress`]: struct.Decompressor.html#method.zlib_decompress
 //! [`gzip_decompress`]: struct.Decompressor.html#method.gzip_decompress
+//! [`gzip_decompress_ex`]: struct.Decompressor.html#method.gzip_decompress_ex
+//! [`gzip_decompress_exact`]: struct.Decompressor.html#method.gzip_decompress_exact
+//! [`gzip_decompress_uninit`]: struct.Decompressor.html#method.gzip_decompress_uninit
+//! [`gzip_decompress_append`]: struct.Decompressor.html#method.gzip_decompress_append
+//! [`gzip_decompress_multi`]: struct.Decompressor.html#method.gzip_decompress_multi
+//! [`detect_format`]: fn.detect_format.html
+//! [`decompress_auto`]: struct.Decompressor.html#method.decompress_auto
 //! [`DecompressionError::InsufficientSpace`]: enum.DecompressionError.html
 //!
 //! # Compression
@@ -52,7 +81,26 @@
 //! - [`zlib_compress_bound`]
 //! - [`gzip_compress_bound`]
 //!
+//! Alternatively, the `*_compress_to_vec` methods (e.g.
+//! [`gzip_compress_to_vec`]) and `*_compress_append` methods (e.g.
+//! [`gzip_compress_append`]) size the output buffer from the bound
+//! automatically.
+//!
+//! Each `*_compress` method also has an `_uninit` variant (e.g.
+//! [`deflate_compress_uninit`]) that writes into a
+//! `&mut [MaybeUninit<u8>]`.
+//!
+//! [`Compressor::compress`] and [`Compressor::compress_bound`] take a
+//! [`Format`] instead, and the [`Codec`] trait abstracts over formats
+//! (and custom containers) for generic code such as
+//! [`Compressor::compress_to_vec`] and [`Decompressor::decompress_to_vec`].
+//!
 //! [`Compressor::new`]: struct.Compressor.html#method.new
+//! [`Compressor::compress`]: struct.Compressor.html#method.compress
+//! [`Compressor::compress_bound`]: struct.Compressor.html#method.compress_bound
+//! [`Codec`]: trait.Codec.html
+//! [`Compressor::compress_to_vec`]: struct.Compressor.html#method.compress_to_vec
+//! [`Decompressor::decompress_to_vec`]: struct.Decompressor.html#method.decompress_to_vec
 //! [`Compressor`]: struct.Compressor.html
 //! [`deflate_compress`]: struct.Compressor.html#method.deflate_compress
 //! [`zlib_compress`]: struct.Compressor.html#method.zlib_compress
@@ -60,82 +108,743 @@
 //! [`deflate_compress_bound`]: struct.Compressor.html#method.deflate_compress_bound
 //! [`zlib_compress_bound`]: struct.Compressor.html#method.zlib_compress_bound
 //! [`gzip_compress_bound`]: struct.Compressor.html#method.gzip_compress_bound
+//! [`gzip_compress_to_vec`]: struct.Compressor.html#method.gzip_compress_to_vec
+//! [`gzip_compress_append`]: struct.Compressor.html#method.gzip_compress_append
+//! [`deflate_compress_uninit`]: struct.Compressor.html#method.deflate_compress_uninit
+//!
+//! # Memory allocation
+//!
+//! By default, compressors and decompressors are allocated with the
+//! system allocator (or with Rust's global allocator if the
+//! `use_rust_alloc` feature is enabled). [`Compressor::try_new`] and
+//! [`Decompressor::try_new`] report allocation failures as an
+//! [`AllocError`] rather than panicking, and
+//! [`Compressor::with_allocator`] and
+//! [`Decompressor::with_allocator`] allocate an individual instance
+//! with a custom [`Allocator`].
+//!
+//! The memory allocated by each instance can be queried with
+//! `memory_usage` (or estimated up-front with
+//! [`Compressor::estimated_memory`]), and the memory allocated by all
+//! instances can be capped with [`set_memory_limit`].
+//!
+//! [`Compressor::try_new`]: struct.Compressor.html#method.try_new
+//! [`Decompressor::try_new`]: struct.Decompressor.html#method.try_new
+//! [`AllocError`]: struct.AllocError.html
+//! [`Compressor::with_allocator`]: struct.Compressor.html#method.with_allocator
+//! [`Decompressor::with_allocator`]: struct.Decompressor.html#method.with_allocator
+//! [`Allocator`]: trait.Allocator.html
+//! [`Compressor::estimated_memory`]: struct.Compressor.html#method.estimated_memory
+//! [`set_memory_limit`]: fn.set_memory_limit.html
+//!
+//! # Backends
+//!
+//! By default, all (de)compression is performed by `libdeflate`, which
+//! `libdeflate-sys` builds from source with a C compiler. Enabling the
+//! `rust_backend` feature (and disabling default features, so that
+//! `libdeflate` isn't built at all) swaps in a pure-Rust backend built
+//! on [`miniz_oxide`] and [`crc32fast`] instead. The API and its error
+//! semantics are the same with either backend, but the compressed
+//! output differs, and the pure-Rust backend is slower. The memory
+//! accounting described above is approximate with the pure-Rust
+//! backend, because `miniz_oxide` allocates most of its state itself.
+//!
+//! [`miniz_oxide`]: https://github.com/Frommi/miniz_oxide
+//! [`crc32fast`]: https://github.com/srijs/rust-crc32fast
+//!
+//! # gzip headers
+//!
+//! The [`gzip`] module contains a [`GzipHeader`] type that models
+//! every field of a gzip member header (file name, comment, MTIME,
+//! FEXTRA subfields, etc.). Headers can be parsed from existing gzip
+//! data with [`GzipHeader::parse`], written with
+//! [`Compressor::gzip_compress_with_header`], and read back with
+//! [`Decompressor::gzip_decompress_with_header`].
+//!
+//! [`gzip`]: gzip/index.html
+//! [`GzipHeader`]: gzip/struct.GzipHeader.html
+//! [`GzipHeader::parse`]: gzip/struct.GzipHeader.html#method.parse
+//! [`Compressor::gzip_compress_with_header`]: struct.Compressor.html#method.gzip_compress_with_header
+//! [`Decompressor::gzip_decompress_with_header`]: struct.Decompressor.html#method.gzip_decompress_with_header
+//!
+//! # BGZF
+//!
+//! The [`bgzf`] module can compress and decompress BGZF (blocked
+//! gzip) data, as used by BAM files and `bgzip`.
+//!
+//! [`bgzf`]: bgzf/index.html
+//!
+//! # Custom containers
+//!
+//! The [`container`] module builds gzip, zlib, or in-house container
+//! framing (magic bytes, length prefixes, checksum trailers, etc.) in
+//! Rust around raw DEFLATE data, by implementing its [`Framing`]
+//! trait.
+//!
+//! [`container`]: container/index.html
+//! [`Framing`]: container/trait.Framing.html
+//!
+//! # Parallel compression
+//!
+//! The [`parallel`] module contains a [`ParallelCompressor`] that
+//! compresses large buffers on multiple threads (like `pigz`) while
+//! still producing a single, standard DEFLATE, zlib, or gzip stream.
+//!
+//! [`parallel`]: parallel/index.html
+//! [`ParallelCompressor`]: parallel/struct.ParallelCompressor.html
+//!
+//! # Pooling
+//!
+//! The [`pool`] module contains a [`CompressorPool`] and a
+//! [`DecompressorPool`], which can be shared between threads and lend
+//! out reusable compressors/decompressors.
+//!
+//! [`pool`]: pool/index.html
+//! [`CompressorPool`]: pool/struct.CompressorPool.html
+//! [`DecompressorPool`]: pool/struct.DecompressorPool.html
+
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::alloc::Layout;
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::ops::Range;
+use core::ptr::NonNull;
+#[cfg(feature = "std")]
+use std::alloc::GlobalAlloc;
+#[cfg(feature = "std")]
 use std::error::Error;
-use std::fmt;
-use std::ptr::NonNull;
-use libdeflate_sys::{libdeflate_decompressor,
-                            libdeflate_free_decompressor,
-                            libdeflate_gzip_decompress,
-                            libdeflate_zlib_decompress,
-                            libdeflate_deflate_decompress,
-                            libdeflate_result,
-                            libdeflate_result_LIBDEFLATE_SUCCESS,
-                            libdeflate_result_LIBDEFLATE_BAD_DATA,
-                            libdeflate_result_LIBDEFLATE_INSUFFICIENT_SPACE,
-                            libdeflate_compressor,
-                            libdeflate_deflate_compress_bound,
-                            libdeflate_deflate_compress,
-                            libdeflate_zlib_compress_bound,
-                            libdeflate_zlib_compress,
-                            libdeflate_gzip_compress_bound,
-                            libdeflate_gzip_compress,
-                            libdeflate_free_compressor,
-                            libdeflate_crc32,
-                            libdeflate_adler32};
-
-#[cfg(feature = "use_rust_alloc")]
+mod backend;
 mod malloc_wrapper;
 
-unsafe fn alloc_compressor(compression_level: std::os::raw::c_int) -> *mut libdeflate_compressor {
-    #[cfg(feature = "use_rust_alloc")]
-    { libdeflate_sys::libdeflate_alloc_compressor_ex(compression_level, &malloc_wrapper::OPTIONS) }
-    #[cfg(not(feature = "use_rust_alloc"))]
-    { libdeflate_sys::libdeflate_alloc_compressor(compression_level) }
+pub mod gzip;
+pub mod bgzf;
+pub mod container;
+#[cfg(feature = "std")]
+pub mod parallel;
+#[cfg(feature = "std")]
+pub mod pool;
+#[cfg(feature = "std")]
+mod deflate_blocks;
+
+use gzip::GzipHeader;
+use malloc_wrapper::BoxedAllocator;
+
+mod sealed {
+    /// A parameter type that can't be named outside this crate, which
+    /// stops other crates from overriding the `Codec` methods that
+    /// take it.
+    #[derive(Clone, Copy, Debug)]
+    pub struct Token;
+}
+
+/// The amount of memory, in bytes, allocated by a
+/// [`Compressor`](struct.Compressor.html) or
+/// [`Decompressor`](struct.Decompressor.html) for its internal state
+/// (see `memory_usage`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// The number of bytes currently allocated.
+    pub live: usize,
+
+    /// The largest number of bytes that were allocated at once.
+    pub peak: usize,
+}
+
+/// Returns the number of bytes currently allocated by every
+/// [`Compressor`](struct.Compressor.html) and
+/// [`Decompressor`](struct.Decompressor.html) in the process.
+pub fn total_memory_usage() -> usize {
+    malloc_wrapper::total_live()
+}
+
+/// Sets a process-wide limit on the number of bytes that
+/// [`Compressor`](struct.Compressor.html)s and
+/// [`Decompressor`](struct.Decompressor.html)s may allocate in total
+/// (see [`total_memory_usage`](fn.total_memory_usage.html)), or
+/// removes the limit if `limit` is `None`.
+///
+/// Once the limit would be exceeded, constructing another
+/// compressor/decompressor fails cleanly: `try_new` returns an
+/// [`AllocError`](struct.AllocError.html) (and `new` panics) without
+/// the memory ever being requested from the allocator. Lowering the
+/// limit below the current usage does not affect existing instances.
+///
+/// With the `rust_backend` feature, only the fixed-size state of each
+/// instance is counted (and allocated with a custom
+/// [`Allocator`](trait.Allocator.html)). `miniz_oxide` allocates most
+/// of a compressor's memory (its hash and Huffman tables) itself, with
+/// the global allocator, so the limit and the figures reported by
+/// `memory_usage`, `total_memory_usage`, and
+/// `Compressor::estimated_memory` are much lower than the real
+/// footprint.
+pub fn set_memory_limit(limit: Option<usize>) {
+    malloc_wrapper::set_limit(limit)
+}
+
+/// Returns the limit set with
+/// [`set_memory_limit`](fn.set_memory_limit.html), if any.
+pub fn memory_limit() -> Option<usize> {
+    malloc_wrapper::limit()
+}
+
+/// A memory allocator that a single [`Compressor`] or
+/// [`Decompressor`] can use for its internal state instead of the
+/// default allocator (see [`Compressor::with_allocator`] and
+/// [`Decompressor::with_allocator`]).
+///
+/// This can be used to place, for example, each worker thread's
+/// compressor in its own arena or memory pool. `libdeflate` only
+/// allocates memory when a compressor/decompressor is constructed and
+/// only frees it when it is dropped, so an allocator is called at most
+/// a couple of times per instance.
+///
+/// The allocator is moved into the compressor/decompressor, so an
+/// allocator that is shared between several instances should be
+/// passed by reference (e.g. `&'static A`) or wrapped in an `Arc`.
+///
+/// Without the `std` feature, compressors and decompressors are
+/// constructed and dropped one at a time across the whole program,
+/// under a global spin lock that is held while the allocator runs. An
+/// allocator must not construct or drop a `Compressor` or
+/// `Decompressor` itself, and neither may an interrupt handler that can
+/// preempt one on the same core: either spins forever.
+///
+/// # Safety
+///
+/// Implementations must behave like
+/// [`GlobalAlloc`](https://doc.rust-lang.org/std/alloc/trait.GlobalAlloc.html):
+/// `allocate` must return either null or a pointer to a block of
+/// memory that fits `layout` and that stays valid until it is passed
+/// to `deallocate`.
+///
+/// [`Compressor`]: struct.Compressor.html
+/// [`Decompressor`]: struct.Decompressor.html
+/// [`Compressor::with_allocator`]: struct.Compressor.html#method.with_allocator
+/// [`Decompressor::with_allocator`]: struct.Decompressor.html#method.with_allocator
+pub unsafe trait Allocator {
+    /// Allocates a block of memory that fits `layout`, or returns null
+    /// if the allocation fails.
+    fn allocate(&self, layout: Layout) -> *mut u8;
+
+    /// Deallocates a block of memory.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by a call to `allocate` on this
+    /// allocator with the same `layout`.
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout);
+}
+
+#[cfg(feature = "std")]
+unsafe impl Allocator for std::alloc::System {
+    fn allocate(&self, layout: Layout) -> *mut u8 {
+        unsafe { GlobalAlloc::alloc(self, layout) }
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        GlobalAlloc::dealloc(self, ptr, layout)
+    }
+}
+
+unsafe impl<A: Allocator + ?Sized> Allocator for &A {
+    fn allocate(&self, layout: Layout) -> *mut u8 {
+        (**self).allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        (**self).deallocate(ptr, layout)
+    }
 }
 
-unsafe fn alloc_decompressor() -> *mut libdeflate_decompressor {
-    #[cfg(feature = "use_rust_alloc")]
-    { libdeflate_sys::libdeflate_alloc_decompressor_ex(&malloc_wrapper::OPTIONS) }
-    #[cfg(not(feature = "use_rust_alloc"))]
-    { libdeflate_sys::libdeflate_alloc_decompressor() }
+unsafe impl<A: Allocator + ?Sized> Allocator for alloc::sync::Arc<A> {
+    fn allocate(&self, layout: Layout) -> *mut u8 {
+        (**self).allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        (**self).deallocate(ptr, layout)
+    }
 }
 
+/// An error returned by [`Compressor::try_new`] and
+/// [`Decompressor::try_new`] when `libdeflate` could not allocate a
+/// compressor or decompressor.
+///
+/// [`Compressor::try_new`]: struct.Compressor.html#method.try_new
+/// [`Decompressor::try_new`]: struct.Decompressor.html#method.try_new
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AllocError;
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "libdeflate could not allocate a compressor or decompressor: out of memory")
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for AllocError {}
+
 /// A `libdeflate` decompressor that can inflate DEFLATE, zlib, or
 /// gzip data.
 pub struct Decompressor {
-    p: NonNull<libdeflate_decompressor>,
+    p: NonNull<backend::DecompressorState>,
+    allocator: Option<BoxedAllocator>,
+    memory: MemoryUsage,
 }
 unsafe impl Send for Decompressor {}
 
 /// An error that may be returned by one of the
 /// [`Decompressor`](struct.Decompressor.html)'s `decompress_*`
 /// methods when a decompression cannot be performed.
-#[derive(Debug, PartialEq)]
+///
+/// Before handing data to `libdeflate`, gzip and zlib headers are
+/// validated so that malformed headers are reported with a specific
+/// variant (e.g. `BadMagic`). When `libdeflate` rejects the data, the
+/// trailer is checked so that checksum and size mismatches can be
+/// reported with the expected and actual values. Anything else (e.g.
+/// corrupt DEFLATE data) is reported as `BadData`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum DecompressionError {
     /// The provided data is invalid in some way. For example, the
-    /// checksum in the data revealed possible corruption, magic
-    /// numbers in the data do not match expectations, etc.
+    /// DEFLATE data is corrupt, or unexpected data follows the end of
+    /// the compressed data.
     BadData,
 
     /// The provided output buffer is not large enough to accomodate
     /// the decompressed data.
     InsufficientSpace,
+
+    /// The data decompresses to more than the maximum output size
+    /// that was supplied to one of the `*_decompress_to_vec` methods.
+    OutputLimitExceeded,
+
+    /// The data decompresses to fewer bytes than the size of the
+    /// output buffer, and the method requires the output buffer to be
+    /// filled exactly.
+    ShortOutput,
+
+    /// The data does not start with the gzip magic bytes (ID1, ID2).
+    BadMagic,
+
+    /// The header names a compression method (gzip CM or zlib CM)
+    /// other than DEFLATE (8).
+    UnsupportedMethod(u8),
+
+    /// The gzip header has reserved FLG bits set. Holds the FLG byte.
+    ReservedFlags(u8),
+
+    /// The zlib header has FDICT set, i.e. the data was compressed
+    /// with a preset dictionary, which `libdeflate` does not support.
+    PresetDictionary,
+
+    /// The gzip header's FHCRC, or the zlib header's FCHECK, does not
+    /// match the rest of the header.
+    HeaderChecksumMismatch,
+
+    /// The data ends before the end of its header or trailer.
+    Truncated,
+
+    /// The CRC32 of the decompressed data does not match the CRC32
+    /// recorded in the gzip trailer.
+    CrcMismatch { expected: u32, actual: u32 },
+
+    /// The size (modulo 2^32) of the decompressed data does not match
+    /// the ISIZE recorded in the gzip trailer.
+    SizeMismatch { expected: u32, actual: u32 },
+
+    /// The Adler-32 of the decompressed data does not match the
+    /// Adler-32 recorded in the zlib trailer.
+    Adler32Mismatch { expected: u32, actual: u32 },
+
+    /// A decompressor could not be allocated by one of the
+    /// `DecompressorPool::*_decompress_to_vec` methods.
+    AllocationFailed,
 }
 
 impl fmt::Display for DecompressionError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self {
-            DecompressionError::BadData => write!(f, "the data provided to a libdeflater *_decompress function call was invalid in some way (e.g. bad magic numbers, bad checksum)"),
-            DecompressionError::InsufficientSpace => write!(f, "a buffer provided to a libdeflater *_decompress function call was too small to accommodate the decompressed data")
+            DecompressionError::BadData => write!(f, "the data provided to a libdeflater *_decompress function call was invalid in some way (e.g. corrupt DEFLATE data)"),
+            DecompressionError::InsufficientSpace => write!(f, "a buffer provided to a libdeflater *_decompress function call was too small to accommodate the decompressed data"),
+            DecompressionError::OutputLimitExceeded => write!(f, "the data provided to a libdeflater *_decompress_to_vec function call decompresses to more than the maximum allowed output size"),
+            DecompressionError::ShortOutput => write!(f, "the data provided to a libdeflater *_decompress function call decompresses to fewer bytes than the size of the output buffer"),
+            DecompressionError::BadMagic => write!(f, "the data does not start with the gzip magic bytes"),
+            DecompressionError::UnsupportedMethod(cm) => write!(f, "the data uses an unsupported compression method ({}, expected 8 (DEFLATE))", cm),
+            DecompressionError::ReservedFlags(flg) => write!(f, "the gzip header has reserved flag bits set (FLG = {:#04x})", flg),
+            DecompressionError::PresetDictionary => write!(f, "the zlib data requires a preset dictionary, which is not supported"),
+            DecompressionError::HeaderChecksumMismatch => write!(f, "the header checksum does not match the header"),
+            DecompressionError::Truncated => write!(f, "the data ends before the end of its header or trailer"),
+            DecompressionError::CrcMismatch { expected, actual } => write!(f, "CRC32 mismatch: the gzip trailer records {:#010x} but the decompressed data has {:#010x}", expected, actual),
+            DecompressionError::SizeMismatch { expected, actual } => write!(f, "size mismatch: the gzip trailer records {} bytes but the data decompressed to {} bytes (modulo 2^32)", expected, actual),
+            DecompressionError::Adler32Mismatch { expected, actual } => write!(f, "Adler-32 mismatch: the zlib trailer records {:#010x} but the decompressed data has {:#010x}", expected, actual),
+            DecompressionError::AllocationFailed => write!(f, "a decompressor for a libdeflater *_decompress_to_vec function call could not be allocated"),
         }
     }
 }
 
+#[cfg(feature = "std")]
 impl Error for DecompressionError {}
 
+#[cfg(feature = "std")]
+impl From<DecompressionError> for std::io::Error {
+    /// Converts a `DecompressionError` into an `io::Error` that wraps
+    /// it. Truncated data maps to `io::ErrorKind::UnexpectedEof`, other
+    /// problems with the data map to `io::ErrorKind::InvalidData`, and
+    /// problems with the output buffer map to `io::ErrorKind::Other`.
+    fn from(e: DecompressionError) -> std::io::Error {
+        let kind = match e {
+            DecompressionError::Truncated => std::io::ErrorKind::UnexpectedEof,
+            DecompressionError::InsufficientSpace | DecompressionError::ShortOutput => std::io::ErrorKind::Other,
+            DecompressionError::AllocationFailed => std::io::ErrorKind::OutOfMemory,
+            _ => std::io::ErrorKind::InvalidData,
+        };
+        std::io::Error::new(kind, e)
+    }
+}
+
 /// A result returned by decompression methods
-type DecompressionResult<T> = std::result::Result<T, DecompressionError>;
+pub type DecompressionResult<T> = core::result::Result<T, DecompressionError>;
+
+/// Signature shared by the `Decompressor::*_decompress_uninit` methods.
+type DecompressUninitFn = fn(&mut Decompressor, &[u8], &mut [MaybeUninit<u8>]) -> DecompressionResult<usize>;
+
+/// Views an initialized buffer as a possibly-uninitialized one, so
+/// that it can be written to by the `*_uninit` methods.
+///
+/// # Safety
+///
+/// Nothing may de-initialize bytes (e.g. write `MaybeUninit::uninit()`)
+/// through the returned slice, because `buf` is still a `&mut [u8]`
+/// once it is released. The backends, the `*_uninit` methods, and the
+/// `container` functions only ever write initialized bytes, so passing
+/// the result to them is fine.
+unsafe fn as_uninit_mut(buf: &mut [u8]) -> &mut [MaybeUninit<u8>] {
+    // `MaybeUninit<u8>` has the same layout as `u8`
+    &mut *(buf as *mut [u8] as *mut [MaybeUninit<u8>])
+}
+
+/// Zero-fills a possibly-uninitialized buffer, so that it can be
+/// passed to code that only writes into initialized buffers.
+fn zeroed(buf: &mut [MaybeUninit<u8>]) -> &mut [u8] {
+    buf.fill(MaybeUninit::new(0));
+    unsafe { &mut *(buf as *mut [MaybeUninit<u8>] as *mut [u8]) }
+}
+
+/// Views the start of a buffer that was written to by one of the
+/// `*_uninit` methods as initialized bytes.
+///
+/// # Safety
+///
+/// Every byte of `buf` must have been initialized.
+unsafe fn assume_init(buf: &[MaybeUninit<u8>]) -> &[u8] {
+    &*(buf as *const [MaybeUninit<u8>] as *const [u8])
+}
+
+/// Size of a zlib header (CMF and FLG).
+const ZLIB_HEADER_SIZE: usize = 2;
+
+/// Size of a zlib trailer (ADLER32).
+const ZLIB_FOOTER_SIZE: usize = 4;
+
+/// Validates the zlib header at the start of `data`.
+fn check_zlib_header(data: &[u8]) -> DecompressionResult<()> {
+    // zlib RFC1950: CMF is CM (low 4 bits) and CINFO (high 4 bits);
+    // FLG is FCHECK (low 5 bits), FDICT (bit 5), and FLEVEL
+    let cmf = *data.first().ok_or(DecompressionError::Truncated)?;
+    if cmf & 0x0f != 8 {
+        return Err(DecompressionError::UnsupportedMethod(cmf & 0x0f));
+    }
+    if cmf >> 4 > 7 {
+        // window size larger than 32 KiB
+        return Err(DecompressionError::BadData);
+    }
+    let flg = *data.get(1).ok_or(DecompressionError::Truncated)?;
+    if u16::from_be_bytes([cmf, flg]) % 31 != 0 {
+        return Err(DecompressionError::HeaderChecksumMismatch);
+    }
+    if flg & 0x20 != 0 {
+        return Err(DecompressionError::PresetDictionary);
+    }
+    Ok(())
+}
+
+/// Minimum output buffer size used by the `*_decompress_to_vec`
+/// methods when they have to guess the decompressed size.
+const MIN_TO_VEC_OUT_LEN: usize = 4096;
+
+/// Multiple of the compressed size used as the initial output buffer
+/// size by the `*_decompress_to_vec` methods when the decompressed
+/// size is not recorded in the compressed data.
+const TO_VEC_OUT_LEN_FACTOR: usize = 4;
+
+/// The largest possible ratio between decompressed and compressed
+/// DEFLATE data. Used to ignore implausible size hints (e.g. a
+/// corrupt gzip ISIZE trailer) before allocating.
+const MAX_DEFLATE_RATIO: usize = 1032;
+
+/// The outcome of a successful call to one of the
+/// [`Decompressor`](struct.Decompressor.html)'s `*_decompress_ex`
+/// methods.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DecompressOutcome {
+    /// The number of bytes that were read from the compressed input
+    /// data. This may be less than the length of the input if the
+    /// compressed data is followed by other (e.g. trailing) data.
+    pub consumed: usize,
+
+    /// The number of decompressed bytes that were written into the
+    /// output buffer.
+    pub produced: usize,
+}
+
+/// The location of a single member of a multi-member gzip buffer, as
+/// returned by
+/// [`Decompressor::gzip_decompress_multi`](struct.Decompressor.html#method.gzip_decompress_multi).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GzipMember {
+    /// The range of the member's compressed bytes (header, DEFLATE
+    /// data, and trailer) within the input buffer.
+    pub compressed: Range<usize>,
+
+    /// The range of the member's decompressed bytes within the output
+    /// buffer.
+    pub decompressed: Range<usize>,
+}
+
+/// A DEFLATE-based data format supported by `libdeflate`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// [`gzip`](https://tools.ietf.org/html/rfc1952) data.
+    Gzip,
+
+    /// [`zlib`](https://www.ietf.org/rfc/rfc1950.txt) data.
+    Zlib,
+
+    /// Raw [`deflate`](https://tools.ietf.org/html/rfc1951) data.
+    Deflate,
+}
+
+/// Guesses the [`Format`](enum.Format.html) of the compressed data in
+/// `data` by inspecting its first few bytes.
+///
+/// Returns `Format::Gzip` if `data` starts with the gzip magic bytes,
+/// `Format::Zlib` if `data` starts with a valid zlib CMF/FLG header,
+/// and otherwise falls back to `Format::Deflate`. Returns `None` if
+/// `data` cannot be any of the formats (e.g. it is empty, or its
+/// first DEFLATE block uses the reserved block type).
+///
+/// Note: this is a heuristic. Raw DEFLATE data can, by chance, start
+/// with bytes that look like a zlib header, in which case it is
+/// reported as `Format::Zlib`.
+/// [`Decompressor::decompress_auto`](struct.Decompressor.html#method.decompress_auto)
+/// retries such data as raw DEFLATE if it fails to decompress as zlib.
+pub fn detect_format(data: &[u8]) -> Option<Format> {
+    // gzip RFC1952: ID1, ID2, then CM (8 == deflate)
+    if data.len() >= 3 && data[0] == 0x1f && data[1] == 0x8b && data[2] == 8 {
+        return Some(Format::Gzip);
+    }
+
+    // zlib RFC1950: CMF has CM == 8 and CINFO <= 7, and CMF*256 + FLG
+    // is a multiple of 31
+    if data.len() >= 2 {
+        let cmf = data[0];
+        let flg = data[1];
+        if cmf & 0x0f == 8 && cmf >> 4 <= 7 && u16::from_be_bytes([cmf, flg]) % 31 == 0 {
+            return Some(Format::Zlib);
+        }
+    }
+
+    // DEFLATE RFC1951: the first three bits are BFINAL and BTYPE, and
+    // BTYPE == 3 is reserved
+    match data.first() {
+        Some(b) if (b >> 1) & 0b11 != 0b11 => Some(Format::Deflate),
+        _ => None,
+    }
+}
+
+/// A compressed data format (or container) that can be produced by a
+/// [`Compressor`] and read by a [`Decompressor`].
+///
+/// [`Format`] implements `Codec` for gzip, zlib, and raw DEFLATE data.
+/// Implementing it for another container (e.g. one that wraps DEFLATE
+/// data in a custom header) makes that container usable with the
+/// generic helpers: [`Compressor::compress_to_vec`],
+/// [`Decompressor::decompress_to_vec`], and the pools'
+/// `compress_to_vec` and `decompress_to_vec` methods.
+///
+/// ```
+/// use libdeflater::{Codec, CompressionLvl, CompressionResult, Compressor, DecompressionError,
+///                   DecompressionResult, Decompressor};
+///
+/// /// DEFLATE data prefixed with its decompressed size.
+/// struct SizePrefixed;
+///
+/// impl Codec for SizePrefixed {
+///     fn compress_bound(&self, compressor: &mut Compressor, n_bytes: usize) -> usize {
+///         4 + compressor.deflate_compress_bound(n_bytes)
+///     }
+///
+///     fn compress(&self, compressor: &mut Compressor, in_raw_data: &[u8], out: &mut [u8]) -> CompressionResult<usize> {
+///         let sz = compressor.deflate_compress(in_raw_data, &mut out[4..])?;
+///         out[..4].copy_from_slice(&(in_raw_data.len() as u32).to_le_bytes());
+///         Ok(4 + sz)
+///     }
+///
+///     fn decompress(&self, decompressor: &mut Decompressor, in_data: &[u8], out: &mut [u8]) -> DecompressionResult<usize> {
+///         let size = self.decompressed_size_hint(in_data);
+///         let out = out.get_mut(..size).ok_or(DecompressionError::InsufficientSpace)?;
+///         decompressor.deflate_decompress_exact(&in_data[4..], out)?;
+///         Ok(size)
+///     }
+///
+///     fn decompressed_size_hint(&self, in_data: &[u8]) -> usize {
+///         u32::from_le_bytes([in_data[0], in_data[1], in_data[2], in_data[3]]) as usize
+///     }
+/// }
+///
+/// let compressed = Compressor::new(CompressionLvl::default())
+///     .compress_to_vec(&SizePrefixed, b"hello, world!")
+///     .unwrap();
+/// let data = Decompressor::new().decompress_to_vec(&SizePrefixed, &compressed, 1 << 20).unwrap();
+/// assert_eq!(data, b"hello, world!");
+/// ```
+///
+/// [`Compressor`]: struct.Compressor.html
+/// [`Decompressor`]: struct.Decompressor.html
+/// [`Format`]: enum.Format.html
+/// [`Compressor::compress_to_vec`]: struct.Compressor.html#method.compress_to_vec
+/// [`Decompressor::decompress_to_vec`]: struct.Decompressor.html#method.decompress_to_vec
+pub trait Codec {
+    /// Returns the maximum number of bytes that `compress` may write
+    /// when compressing `n_bytes` with `compressor`. Like the
+    /// `*_compress_bound` methods, this must be a hard upper-bound.
+    fn compress_bound(&self, compressor: &mut Compressor, n_bytes: usize) -> usize;
+
+    /// Compresses `in_raw_data` with `compressor`, writing the
+    /// compressed data into `out`. Returns the number of bytes written
+    /// into `out`.
+    fn compress(&self,
+                compressor: &mut Compressor,
+                in_raw_data: &[u8],
+                out: &mut [u8]) -> CompressionResult<usize>;
+
+    /// Decompresses `in_data` with `decompressor`, writing the
+    /// decompressed data into `out`. Returns the number of bytes
+    /// written into `out`, or `DecompressionError::InsufficientSpace`
+    /// if `out` is too small (which makes
+    /// [`Decompressor::decompress_to_vec`](struct.Decompressor.html#method.decompress_to_vec)
+    /// retry with a larger buffer).
+    fn decompress(&self,
+                  decompressor: &mut Decompressor,
+                  in_data: &[u8],
+                  out: &mut [u8]) -> DecompressionResult<usize>;
+
+    // The `*_uninit` methods let the codecs in this crate skip
+    // zero-filling the output buffers of `compress_to_vec` and
+    // `decompress_to_vec`. Their implementations must initialize
+    // every byte that they report as written, which safe code can't
+    // be trusted to do, so they take a `sealed::Token` that can't be
+    // named outside this crate (and so can't be overridden there).
+
+    #[doc(hidden)]
+    fn compress_uninit(&self,
+                       compressor: &mut Compressor,
+                       in_raw_data: &[u8],
+                       out: &mut [MaybeUninit<u8>],
+                       _: sealed::Token) -> CompressionResult<usize> {
+        self.compress(compressor, in_raw_data, zeroed(out))
+    }
+
+    #[doc(hidden)]
+    fn decompress_uninit(&self,
+                         decompressor: &mut Decompressor,
+                         in_data: &[u8],
+                         out: &mut [MaybeUninit<u8>],
+                         _: sealed::Token) -> DecompressionResult<usize> {
+        self.decompress(decompressor, in_data, zeroed(out))
+    }
+
+    /// Returns a guess at the decompressed size of `in_data`, which is
+    /// used as the initial output buffer size by
+    /// [`Decompressor::decompress_to_vec`](struct.Decompressor.html#method.decompress_to_vec).
+    /// The default implementation guesses a small multiple of the
+    /// length of `in_data`.
+    fn decompressed_size_hint(&self, in_data: &[u8]) -> usize {
+        in_data.len().saturating_mul(TO_VEC_OUT_LEN_FACTOR)
+    }
+}
+
+impl Codec for Format {
+    fn compress_bound(&self, compressor: &mut Compressor, n_bytes: usize) -> usize {
+        compressor.compress_bound(*self, n_bytes)
+    }
+
+    fn compress(&self,
+                compressor: &mut Compressor,
+                in_raw_data: &[u8],
+                out: &mut [u8]) -> CompressionResult<usize> {
+        compressor.compress(*self, in_raw_data, out)
+    }
+
+    fn decompress(&self,
+                  decompressor: &mut Decompressor,
+                  in_data: &[u8],
+                  out: &mut [u8]) -> DecompressionResult<usize> {
+        decompressor.decompress(*self, in_data, out)
+    }
+
+    fn compress_uninit(&self,
+                       compressor: &mut Compressor,
+                       in_raw_data: &[u8],
+                       out: &mut [MaybeUninit<u8>],
+                       _: sealed::Token) -> CompressionResult<usize> {
+        match self {
+            Format::Gzip => compressor.gzip_compress_uninit(in_raw_data, out),
+            Format::Zlib => compressor.zlib_compress_uninit(in_raw_data, out),
+            Format::Deflate => compressor.deflate_compress_uninit(in_raw_data, out),
+        }
+    }
+
+    fn decompress_uninit(&self,
+                         decompressor: &mut Decompressor,
+                         in_data: &[u8],
+                         out: &mut [MaybeUninit<u8>],
+                         _: sealed::Token) -> DecompressionResult<usize> {
+        match self {
+            Format::Gzip => decompressor.gzip_decompress_uninit(in_data, out),
+            Format::Zlib => decompressor.zlib_decompress_uninit(in_data, out),
+            Format::Deflate => decompressor.deflate_decompress_uninit(in_data, out),
+        }
+    }
+
+    fn decompressed_size_hint(&self, in_data: &[u8]) -> usize {
+        match self {
+            Format::Gzip => gzip_size_hint(in_data),
+            Format::Zlib | Format::Deflate => in_data.len().saturating_mul(TO_VEC_OUT_LEN_FACTOR),
+        }
+    }
+}
+
+/// Returns the decompressed size recorded in the trailer of the gzip
+/// member at the end of `gz_data`, or 0 if `gz_data` is too short to
+/// hold a gzip member.
+fn gzip_size_hint(gz_data: &[u8]) -> usize {
+    // gzip RFC1952: the last four bytes of a gzip member (ISIZE)
+    // hold the decompressed size modulo 2^32
+    match gz_data.len() {
+        n if n >= 18 => {
+            let isize_bytes = [gz_data[n-4], gz_data[n-3], gz_data[n-2], gz_data[n-1]];
+            u32::from_le_bytes(isize_bytes) as usize
+        },
+        _ => 0,
+    }
+}
 
 impl Default for Decompressor {
     fn default() -> Self {
@@ -143,21 +852,72 @@ impl Default for Decompressor {
     }
 }
 
-#[allow(non_upper_case_globals)]
 impl Decompressor {
 
     /// Returns a newly constructed instance of a `Decompressor`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the decompressor could not be allocated. Use
+    /// [`try_new`](#method.try_new) to handle that case instead.
     pub fn new() -> Decompressor {
-        unsafe {
-            let ptr = alloc_decompressor();
-            if let Some(ptr) = NonNull::new(ptr) {
-                Decompressor{ p: ptr }
-            } else {
-                panic!("libdeflate_alloc_decompressor returned NULL: out of memory");
-            }
+        match Decompressor::try_new() {
+            Ok(decompressor) => decompressor,
+            Err(_) => panic!("libdeflate_alloc_decompressor returned NULL: out of memory"),
         }
     }
 
+    /// Returns a newly constructed instance of a `Decompressor`, or
+    /// an [`AllocError`](struct.AllocError.html) if it could not be
+    /// allocated.
+    pub fn try_new() -> Result<Decompressor, AllocError> {
+        Decompressor::try_new_in(None)
+    }
+
+    /// Returns a newly constructed instance of a `Decompressor` whose
+    /// internal state is allocated with `allocator` rather than the
+    /// default allocator.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the decompressor could not be allocated. Use
+    /// [`try_with_allocator`](#method.try_with_allocator) to handle
+    /// that case instead.
+    ///
+    /// Without the `std` feature, `allocator` must not itself construct
+    /// or drop a compressor/decompressor, or this deadlocks (see
+    /// [`Allocator`](trait.Allocator.html)).
+    pub fn with_allocator<A: Allocator + Send + 'static>(allocator: A) -> Decompressor {
+        match Decompressor::try_with_allocator(allocator) {
+            Ok(decompressor) => decompressor,
+            Err(_) => panic!("libdeflate_alloc_decompressor_ex returned NULL: out of memory"),
+        }
+    }
+
+    /// Returns a newly constructed instance of a `Decompressor` whose
+    /// internal state is allocated with `allocator`, or an
+    /// [`AllocError`](struct.AllocError.html) if `allocator` could not
+    /// allocate it.
+    pub fn try_with_allocator<A: Allocator + Send + 'static>(allocator: A) -> Result<Decompressor, AllocError> {
+        Decompressor::try_new_in(Some(Box::new(allocator)))
+    }
+
+    fn try_new_in(allocator: Option<BoxedAllocator>) -> Result<Decompressor, AllocError> {
+        let mut memory = MemoryUsage::default();
+        let ptr = unsafe { backend::alloc_decompressor(allocator.as_ref(), &mut memory) };
+        NonNull::new(ptr).map(|p| Decompressor{ p, allocator, memory }).ok_or(AllocError)
+    }
+
+    /// Returns the amount of memory allocated by this decompressor.
+    ///
+    /// `libdeflate` allocates all of a decompressor's state when it is
+    /// constructed, so this does not change over its lifetime. With
+    /// the `rust_backend` feature, only part of that state is counted
+    /// (see [`set_memory_limit`](fn.set_memory_limit.html)).
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.memory
+    }
+
     /// Decompresses `gz_data` (a buffer containing
     /// [`gzip`](https://tools.ietf.org/html/rfc1952) data) and writes
     /// the decompressed data into `out`. Returns the number of
@@ -167,34 +927,149 @@ impl Decompressor {
     pub fn gzip_decompress(&mut self,
                            gz_data: &[u8],
                            out: &mut [u8]) -> DecompressionResult<usize> {
-        unsafe {
-            let mut out_nbytes = 0;
-            let in_ptr = gz_data.as_ptr() as *const std::ffi::c_void;
-            let out_ptr = out.as_mut_ptr() as *mut std::ffi::c_void;
-            let ret: libdeflate_result =
-                libdeflate_gzip_decompress(self.p.as_ptr(),
-                                           in_ptr,
-                                           gz_data.len(),
-                                           out_ptr,
-                                           out.len(),
-                                           &mut out_nbytes);
-            match ret {
-                libdeflate_result_LIBDEFLATE_SUCCESS => {
-                    Ok(out_nbytes)
-                },
-                libdeflate_result_LIBDEFLATE_BAD_DATA => {
-                    Err(DecompressionError::BadData)
-                },
-                libdeflate_result_LIBDEFLATE_INSUFFICIENT_SPACE => {
-                    Err(DecompressionError::InsufficientSpace)
-                },
-                _ => {
-                    panic!("libdeflate_gzip_decompress returned an unknown error type: this is an internal bug that **must** be fixed");
-                }
+        self.gzip_decompress_ex(gz_data, out).map(|outcome| outcome.produced)
+    }
+
+    /// Decompresses `gz_data` (a buffer beginning with
+    /// [`gzip`](https://tools.ietf.org/html/rfc1952) data) and
+    /// writes the decompressed data into `out`. Unlike
+    /// [`gzip_decompress`](#method.gzip_decompress), `gz_data` may
+    /// contain additional data after the end of the gzip member.
+    ///
+    /// Returns a [`DecompressOutcome`](struct.DecompressOutcome.html)
+    /// holding the number of bytes consumed from `gz_data` and the
+    /// number of decompressed bytes written into `out`, or an error
+    /// (see [`DecompressionError`](enum.DecompressionError.html) for
+    /// error cases).
+    pub fn gzip_decompress_ex(&mut self,
+                              gz_data: &[u8],
+                              out: &mut [u8]) -> DecompressionResult<DecompressOutcome> {
+        self.gzip_decompress_impl(gz_data, unsafe { as_uninit_mut(out) }, false)
+    }
+
+    /// Decompresses `gz_data` (a buffer containing
+    /// [`gzip`](https://tools.ietf.org/html/rfc1952) data), which
+    /// must decompress to exactly `out.len()` bytes, into `out`.
+    ///
+    /// This is useful when the decompressed size is already known
+    /// (e.g. from a container format). Returns
+    /// `DecompressionError::ShortOutput` if the data decompresses to
+    /// fewer bytes, `DecompressionError::InsufficientSpace` if it
+    /// decompresses to more bytes, or another error (see
+    /// [`DecompressionError`](enum.DecompressionError.html) for error
+    /// cases).
+    pub fn gzip_decompress_exact(&mut self,
+                                 gz_data: &[u8],
+                                 out: &mut [u8]) -> DecompressionResult<()> {
+        self.gzip_decompress_impl(gz_data, unsafe { as_uninit_mut(out) }, true).map(|_| ())
+    }
+
+    /// Like [`gzip_decompress`](#method.gzip_decompress), but writes
+    /// into a possibly-uninitialized buffer, which saves callers from
+    /// zero-filling large output buffers. Returns the number of
+    /// decompressed bytes written into `out`, which are then
+    /// initialized.
+    pub fn gzip_decompress_uninit(&mut self,
+                                  gz_data: &[u8],
+                                  out: &mut [MaybeUninit<u8>]) -> DecompressionResult<usize> {
+        self.gzip_decompress_impl(gz_data, out, false).map(|outcome| outcome.produced)
+    }
+
+    /// Decompresses `gz_data` (a buffer containing
+    /// [`gzip`](https://tools.ietf.org/html/rfc1952) data) into the
+    /// spare capacity of `out` (i.e. after its current contents) and
+    /// extends `out` by the number of decompressed bytes, which is
+    /// returned.
+    ///
+    /// `out` is not grown, so callers should `reserve` enough space
+    /// for the decompressed data beforehand; otherwise,
+    /// `DecompressionError::InsufficientSpace` is returned and the
+    /// length of `out` is left unchanged.
+    pub fn gzip_decompress_append(&mut self,
+                                  gz_data: &[u8],
+                                  out: &mut Vec<u8>) -> DecompressionResult<usize> {
+        self.decompress_append(gz_data, out, Decompressor::gzip_decompress_uninit)
+    }
+
+    fn gzip_decompress_impl(&mut self,
+                            gz_data: &[u8],
+                            out: &mut [MaybeUninit<u8>],
+                            exact: bool) -> DecompressionResult<DecompressOutcome> {
+        let header_len = gzip::header_len(gz_data)?;
+        let ret = unsafe {
+            self.raw_decompress(backend::gzip_decompress, gz_data, out, exact)
+        };
+        match ret {
+            Err(DecompressionError::BadData) => Err(self.diagnose_gzip(gz_data, header_len, out)),
+            ret => ret,
+        }
+    }
+
+    /// Decompresses `gz_data` (a buffer containing one or more
+    /// concatenated [`gzip`](https://tools.ietf.org/html/rfc1952)
+    /// members, e.g. the output of `cat a.gz b.gz`, `pigz`, or
+    /// `bgzip`) and writes the decompressed data of each member back
+    /// to back into `out`.
+    ///
+    /// Returns a [`GzipMember`](struct.GzipMember.html) for each
+    /// member in `gz_data`, in order, describing where the member was
+    /// located in `gz_data` and where its decompressed data was
+    /// written in `out`. The total number of decompressed bytes is
+    /// the `end` of the last member's `decompressed` range. Returns an
+    /// error (see [`DecompressionError`](enum.DecompressionError.html)
+    /// for error cases) if any member cannot be decompressed, or if
+    /// `gz_data` contains trailing data that is not a gzip member.
+    pub fn gzip_decompress_multi(&mut self,
+                                 gz_data: &[u8],
+                                 out: &mut [u8]) -> DecompressionResult<Vec<GzipMember>> {
+        let mut members = Vec::new();
+        let mut in_pos = 0;
+        let mut out_pos = 0;
+
+        loop {
+            let outcome = self.gzip_decompress_ex(&gz_data[in_pos..], &mut out[out_pos..])?;
+
+            members.push(GzipMember {
+                compressed: in_pos..(in_pos + outcome.consumed),
+                decompressed: out_pos..(out_pos + outcome.produced),
+            });
+            in_pos += outcome.consumed;
+            out_pos += outcome.produced;
+
+            if in_pos == gz_data.len() {
+                return Ok(members);
             }
         }
     }
 
+    /// Decompresses `gz_data` (a buffer containing
+    /// [`gzip`](https://tools.ietf.org/html/rfc1952) data) and writes
+    /// the decompressed data into `out`. Unlike
+    /// [`gzip_decompress`](#method.gzip_decompress), the member's
+    /// header is parsed (and its FHCRC, if any, verified) rather than
+    /// skipped.
+    ///
+    /// Returns the parsed [`GzipHeader`](gzip/struct.GzipHeader.html)
+    /// and the number of decompressed bytes written into `out`, or an
+    /// error (see [`DecompressionError`](enum.DecompressionError.html)
+    /// for error cases).
+    pub fn gzip_decompress_with_header(&mut self,
+                                       gz_data: &[u8],
+                                       out: &mut [u8]) -> DecompressionResult<(GzipHeader, usize)> {
+        let (header, header_end) = GzipHeader::parse(gz_data)?;
+        let outcome = self.deflate_decompress_ex(&gz_data[header_end..], out)?;
+
+        let footer_start = header_end + outcome.consumed;
+        let footer = gz_data.get(footer_start..footer_start + gzip::FOOTER_SIZE)
+            .ok_or(DecompressionError::Truncated)?;
+        gzip::check_footer(footer, &out[..outcome.produced])?;
+        if gz_data.len() != footer_start + gzip::FOOTER_SIZE {
+            return Err(DecompressionError::BadData);
+        }
+
+        Ok((header, outcome.produced))
+    }
+
     /// Decompresses `zlib_data` (a buffer containing
     /// [`zlib`](https://www.ietf.org/rfc/rfc1950.txt) data) and
     /// writes the decompressed data to `out`. Returns the number of
@@ -204,32 +1079,74 @@ impl Decompressor {
     pub fn zlib_decompress(&mut self,
                            zlib_data: &[u8],
                            out: &mut [u8]) -> DecompressionResult<usize> {
-        unsafe {
-            let mut out_nbytes = 0;
-            let in_ptr = zlib_data.as_ptr() as *const std::ffi::c_void;
-            let out_ptr = out.as_mut_ptr() as *mut std::ffi::c_void;
-            let ret: libdeflate_result =
-                libdeflate_zlib_decompress(self.p.as_ptr(),
-                                           in_ptr,
-                                           zlib_data.len(),
-                                           out_ptr,
-                                           out.len(),
-                                           &mut out_nbytes);
-
-            match ret {
-                libdeflate_result_LIBDEFLATE_SUCCESS => {
-                    Ok(out_nbytes)
-                },
-                libdeflate_result_LIBDEFLATE_BAD_DATA => {
-                    Err(DecompressionError::BadData)
-                },
-                libdeflate_result_LIBDEFLATE_INSUFFICIENT_SPACE => {
-                    Err(DecompressionError::InsufficientSpace)
-                },
-                _ => {
-                    panic!("libdeflate_zlib_decompress returned an unknown error type: this is an internal bug that **must** be fixed");
-                }
-            }
+        self.zlib_decompress_ex(zlib_data, out).map(|outcome| outcome.produced)
+    }
+
+    /// Decompresses `zlib_data` (a buffer beginning with
+    /// [`zlib`](https://www.ietf.org/rfc/rfc1950.txt) data) and
+    /// writes the decompressed data into `out`. Unlike
+    /// [`zlib_decompress`](#method.zlib_decompress), `zlib_data` may
+    /// contain additional data after the end of the zlib stream.
+    ///
+    /// Returns a [`DecompressOutcome`](struct.DecompressOutcome.html)
+    /// holding the number of bytes consumed from `zlib_data` and the
+    /// number of decompressed bytes written into `out`, or an error
+    /// (see [`DecompressionError`](enum.DecompressionError.html) for
+    /// error cases).
+    pub fn zlib_decompress_ex(&mut self,
+                              zlib_data: &[u8],
+                              out: &mut [u8]) -> DecompressionResult<DecompressOutcome> {
+        self.zlib_decompress_impl(zlib_data, unsafe { as_uninit_mut(out) }, false)
+    }
+
+    /// Decompresses `zlib_data` (a buffer containing
+    /// [`zlib`](https://www.ietf.org/rfc/rfc1950.txt) data), which
+    /// must decompress to exactly `out.len()` bytes, into `out`.
+    ///
+    /// Returns `DecompressionError::ShortOutput` if the data
+    /// decompresses to fewer bytes,
+    /// `DecompressionError::InsufficientSpace` if it decompresses to
+    /// more bytes, or another error (see
+    /// [`DecompressionError`](enum.DecompressionError.html) for error
+    /// cases).
+    pub fn zlib_decompress_exact(&mut self,
+                                 zlib_data: &[u8],
+                                 out: &mut [u8]) -> DecompressionResult<()> {
+        self.zlib_decompress_impl(zlib_data, unsafe { as_uninit_mut(out) }, true).map(|_| ())
+    }
+
+    /// Like [`zlib_decompress`](#method.zlib_decompress), but writes
+    /// into a possibly-uninitialized buffer. Returns the number of
+    /// decompressed bytes written into `out`, which are then
+    /// initialized.
+    pub fn zlib_decompress_uninit(&mut self,
+                                  zlib_data: &[u8],
+                                  out: &mut [MaybeUninit<u8>]) -> DecompressionResult<usize> {
+        self.zlib_decompress_impl(zlib_data, out, false).map(|outcome| outcome.produced)
+    }
+
+    /// Decompresses `zlib_data` (a buffer containing
+    /// [`zlib`](https://www.ietf.org/rfc/rfc1950.txt) data) into the
+    /// spare capacity of `out` and extends `out` by the number of
+    /// decompressed bytes, which is returned. See
+    /// [`gzip_decompress_append`](#method.gzip_decompress_append).
+    pub fn zlib_decompress_append(&mut self,
+                                  zlib_data: &[u8],
+                                  out: &mut Vec<u8>) -> DecompressionResult<usize> {
+        self.decompress_append(zlib_data, out, Decompressor::zlib_decompress_uninit)
+    }
+
+    fn zlib_decompress_impl(&mut self,
+                            zlib_data: &[u8],
+                            out: &mut [MaybeUninit<u8>],
+                            exact: bool) -> DecompressionResult<DecompressOutcome> {
+        check_zlib_header(zlib_data)?;
+        let ret = unsafe {
+            self.raw_decompress(backend::zlib_decompress, zlib_data, out, exact)
+        };
+        match ret {
+            Err(DecompressionError::BadData) => Err(self.diagnose_zlib(zlib_data, out)),
+            ret => ret,
         }
     }
 
@@ -242,31 +1159,298 @@ impl Decompressor {
     pub fn deflate_decompress(&mut self,
                               deflate_data: &[u8],
                               out: &mut [u8]) -> DecompressionResult<usize> {
+        self.deflate_decompress_ex(deflate_data, out).map(|outcome| outcome.produced)
+    }
+
+    /// Decompresses `deflate_data` (a buffer beginning with
+    /// [`deflate`](https://tools.ietf.org/html/rfc1951) data) and
+    /// writes the decompressed data into `out`. Unlike
+    /// [`deflate_decompress`](#method.deflate_decompress),
+    /// `deflate_data` may contain additional data after the end of
+    /// the DEFLATE stream.
+    ///
+    /// Returns a [`DecompressOutcome`](struct.DecompressOutcome.html)
+    /// holding the number of bytes consumed from `deflate_data` and the
+    /// number of decompressed bytes written into `out`, or an error
+    /// (see [`DecompressionError`](enum.DecompressionError.html) for
+    /// error cases).
+    pub fn deflate_decompress_ex(&mut self,
+                                 deflate_data: &[u8],
+                                 out: &mut [u8]) -> DecompressionResult<DecompressOutcome> {
+        self.deflate_decompress_impl(deflate_data, unsafe { as_uninit_mut(out) }, false)
+    }
+
+    /// Decompresses `deflate_data` (a buffer containing
+    /// [`deflate`](https://tools.ietf.org/html/rfc1951) data), which
+    /// must decompress to exactly `out.len()` bytes, into `out`.
+    ///
+    /// Returns `DecompressionError::ShortOutput` if the data
+    /// decompresses to fewer bytes,
+    /// `DecompressionError::InsufficientSpace` if it decompresses to
+    /// more bytes, or another error (see
+    /// [`DecompressionError`](enum.DecompressionError.html) for error
+    /// cases).
+    pub fn deflate_decompress_exact(&mut self,
+                                    deflate_data: &[u8],
+                                    out: &mut [u8]) -> DecompressionResult<()> {
+        self.deflate_decompress_impl(deflate_data, unsafe { as_uninit_mut(out) }, true).map(|_| ())
+    }
+
+    /// Like [`deflate_decompress`](#method.deflate_decompress), but
+    /// writes into a possibly-uninitialized buffer. Returns the number
+    /// of decompressed bytes written into `out`, which are then
+    /// initialized.
+    pub fn deflate_decompress_uninit(&mut self,
+                                     deflate_data: &[u8],
+                                     out: &mut [MaybeUninit<u8>]) -> DecompressionResult<usize> {
+        self.deflate_decompress_impl(deflate_data, out, false).map(|outcome| outcome.produced)
+    }
+
+    /// Decompresses `deflate_data` (a buffer containing
+    /// [`deflate`](https://tools.ietf.org/html/rfc1951) data) into the
+    /// spare capacity of `out` and extends `out` by the number of
+    /// decompressed bytes, which is returned. See
+    /// [`gzip_decompress_append`](#method.gzip_decompress_append).
+    pub fn deflate_decompress_append(&mut self,
+                                     deflate_data: &[u8],
+                                     out: &mut Vec<u8>) -> DecompressionResult<usize> {
+        self.decompress_append(deflate_data, out, Decompressor::deflate_decompress_uninit)
+    }
+
+    fn deflate_decompress_impl(&mut self,
+                               deflate_data: &[u8],
+                               out: &mut [MaybeUninit<u8>],
+                               exact: bool) -> DecompressionResult<DecompressOutcome> {
         unsafe {
-            let mut out_nbytes = 0;
-            let in_ptr = deflate_data.as_ptr() as *const std::ffi::c_void;
-            let out_ptr = out.as_mut_ptr() as *mut std::ffi::c_void;
-            let ret: libdeflate_result =
-                libdeflate_deflate_decompress(self.p.as_ptr(),
-                                              in_ptr,
-                                              deflate_data.len(),
-                                              out_ptr,
-                                              out.len(),
-                                              &mut out_nbytes);
-
-            match ret {
-                libdeflate_result_LIBDEFLATE_SUCCESS => {
-                    Ok(out_nbytes)
+            self.raw_decompress(backend::deflate_decompress, deflate_data, out, exact)
+        }
+    }
+
+    /// Calls `decompress` on the spare capacity of `out`, then extends
+    /// `out` to cover the decompressed bytes.
+    fn decompress_append(&mut self,
+                         in_data: &[u8],
+                         out: &mut Vec<u8>,
+                         decompress: DecompressUninitFn) -> DecompressionResult<usize> {
+        let sz = decompress(self, in_data, out.spare_capacity_mut())?;
+        assert!(sz <= out.capacity() - out.len(), "decompression reported more bytes than fit in the output buffer");
+        unsafe {
+            out.set_len(out.len() + sz);
+        }
+        Ok(sz)
+    }
+
+    /// Calls one of the backend's `*_decompress` functions.
+    ///
+    /// # Safety
+    ///
+    /// `decompress` must be one of the backend's `*_decompress`
+    /// functions.
+    ///
+    /// If `exact` is true, the data must decompress to exactly
+    /// `out.len()` bytes.
+    unsafe fn raw_decompress(&mut self,
+                             decompress: backend::RawDecompressFn,
+                             in_data: &[u8],
+                             out: &mut [MaybeUninit<u8>],
+                             exact: bool) -> DecompressionResult<DecompressOutcome> {
+        decompress(self.p.as_ptr(), in_data, out, exact)
+    }
+
+    /// Works out why `libdeflate` rejected the gzip member at the
+    /// start of `gz_data` (whose header has already been validated)
+    /// by decompressing its DEFLATE data and checking its trailer.
+    fn diagnose_gzip(&mut self, gz_data: &[u8], header_len: usize, out: &mut [MaybeUninit<u8>]) -> DecompressionError {
+        let outcome = match self.deflate_decompress_impl(&gz_data[header_len..], out, false) {
+            Ok(outcome) => outcome,
+            Err(e) => return e,
+        };
+        let footer_start = header_len + outcome.consumed;
+        match gz_data.get(footer_start..footer_start + gzip::FOOTER_SIZE) {
+            Some(footer) => gzip::check_footer(footer, unsafe { assume_init(&out[..outcome.produced]) })
+                .err()
+                .unwrap_or(DecompressionError::BadData),
+            None => DecompressionError::Truncated,
+        }
+    }
+
+    /// Works out why `libdeflate` rejected the zlib stream at the
+    /// start of `zlib_data` (whose header has already been validated)
+    /// by decompressing its DEFLATE data and checking its trailer.
+    fn diagnose_zlib(&mut self, zlib_data: &[u8], out: &mut [MaybeUninit<u8>]) -> DecompressionError {
+        let outcome = match self.deflate_decompress_impl(&zlib_data[ZLIB_HEADER_SIZE..], out, false) {
+            Ok(outcome) => outcome,
+            Err(e) => return e,
+        };
+        let footer_start = ZLIB_HEADER_SIZE + outcome.consumed;
+        let footer = match zlib_data.get(footer_start..footer_start + ZLIB_FOOTER_SIZE) {
+            Some(footer) => footer,
+            None => return DecompressionError::Truncated,
+        };
+
+        let expected = u32::from_be_bytes([footer[0], footer[1], footer[2], footer[3]]);
+        let actual = adler32(unsafe { assume_init(&out[..outcome.produced]) });
+        if expected != actual {
+            DecompressionError::Adler32Mismatch { expected, actual }
+        } else {
+            DecompressionError::BadData
+        }
+    }
+
+    /// Decompresses `data` (a buffer containing gzip, zlib, or raw
+    /// DEFLATE data) and writes the decompressed data into `out`. The
+    /// format of `data` is guessed with
+    /// [`detect_format`](fn.detect_format.html) and the matching
+    /// `*_decompress` method is called. Data that is detected as zlib
+    /// but fails to decompress as zlib is retried as raw DEFLATE, since
+    /// raw DEFLATE data can start with bytes that look like a zlib
+    /// header.
+    ///
+    /// Returns the detected [`Format`](enum.Format.html) and the
+    /// number of decompressed bytes written into `out`, or an error
+    /// (see [`DecompressionError`](enum.DecompressionError.html) for
+    /// error cases). Returns `DecompressionError::BadData` if the
+    /// format could not be detected.
+    pub fn decompress_auto(&mut self,
+                           data: &[u8],
+                           out: &mut [u8]) -> DecompressionResult<(Format, usize)> {
+        let format = detect_format(data).ok_or(DecompressionError::BadData)?;
+        match self.decompress(format, data, out) {
+            Ok(sz) => Ok((format, sz)),
+            Err(err) if format == Format::Zlib => match self.deflate_decompress(data, out) {
+                Ok(sz) => Ok((Format::Deflate, sz)),
+                Err(_) => Err(err),
+            },
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Decompresses `data` (a buffer containing data in the supplied
+    /// [`Format`](enum.Format.html)) and writes the decompressed data
+    /// into `out` with the matching `*_decompress` method. Returns the
+    /// number of decompressed bytes written into `out`, or an error
+    /// (see [`DecompressionError`](enum.DecompressionError.html) for
+    /// error cases).
+    pub fn decompress(&mut self,
+                      format: Format,
+                      data: &[u8],
+                      out: &mut [u8]) -> DecompressionResult<usize> {
+        match format {
+            Format::Gzip => self.gzip_decompress(data, out),
+            Format::Zlib => self.zlib_decompress(data, out),
+            Format::Deflate => self.deflate_decompress(data, out),
+        }
+    }
+
+    /// Decompresses `gz_data` (a buffer containing
+    /// [`gzip`](https://tools.ietf.org/html/rfc1952) data) into a
+    /// newly-allocated `Vec`.
+    ///
+    /// The output buffer is initially sized from the gzip ISIZE
+    /// trailer and is grown geometrically whenever it is too small.
+    /// Returns `DecompressionError::OutputLimitExceeded` if the data
+    /// decompresses to more than `max_out_len` bytes, which protects
+    /// callers against decompression bombs. See
+    /// [`DecompressionError`](enum.DecompressionError.html) for other
+    /// error cases.
+    pub fn gzip_decompress_to_vec(&mut self,
+                                  gz_data: &[u8],
+                                  max_out_len: usize) -> DecompressionResult<Vec<u8>> {
+        let size_hint = gzip_size_hint(gz_data);
+        self.decompress_to_growing_vec(gz_data, size_hint, max_out_len, Decompressor::gzip_decompress_uninit)
+    }
+
+    /// Decompresses `zlib_data` (a buffer containing
+    /// [`zlib`](https://www.ietf.org/rfc/rfc1950.txt) data) into a
+    /// newly-allocated `Vec`.
+    ///
+    /// zlib data does not record its decompressed size, so the
+    /// output buffer is initially sized from the length of
+    /// `zlib_data` and is grown geometrically whenever it is too
+    /// small. Returns `DecompressionError::OutputLimitExceeded` if the
+    /// data decompresses to more than `max_out_len` bytes. See
+    /// [`DecompressionError`](enum.DecompressionError.html) for other
+    /// error cases.
+    pub fn zlib_decompress_to_vec(&mut self,
+                                  zlib_data: &[u8],
+                                  max_out_len: usize) -> DecompressionResult<Vec<u8>> {
+        let size_hint = zlib_data.len().saturating_mul(TO_VEC_OUT_LEN_FACTOR);
+        self.decompress_to_growing_vec(zlib_data, size_hint, max_out_len, Decompressor::zlib_decompress_uninit)
+    }
+
+    /// Decompresses `deflate_data` (a buffer containing
+    /// [`deflate`](https://tools.ietf.org/html/rfc1951) data) into a
+    /// newly-allocated `Vec`.
+    ///
+    /// DEFLATE data does not record its decompressed size, so the
+    /// output buffer is initially sized from the length of
+    /// `deflate_data` and is grown geometrically whenever it is too
+    /// small. Returns `DecompressionError::OutputLimitExceeded` if the
+    /// data decompresses to more than `max_out_len` bytes. See
+    /// [`DecompressionError`](enum.DecompressionError.html) for other
+    /// error cases.
+    pub fn deflate_decompress_to_vec(&mut self,
+                                     deflate_data: &[u8],
+                                     max_out_len: usize) -> DecompressionResult<Vec<u8>> {
+        let size_hint = deflate_data.len().saturating_mul(TO_VEC_OUT_LEN_FACTOR);
+        self.decompress_to_growing_vec(deflate_data, size_hint, max_out_len, Decompressor::deflate_decompress_uninit)
+    }
+
+    /// Decompresses `in_data` with `codec` (e.g. a
+    /// [`Format`](enum.Format.html), or a user-defined
+    /// [`Codec`](trait.Codec.html)) into a newly-allocated `Vec`.
+    ///
+    /// The output buffer is initially sized from the codec's
+    /// `decompressed_size_hint` and is grown geometrically whenever it
+    /// is too small. Returns `DecompressionError::OutputLimitExceeded`
+    /// if the data decompresses to more than `max_out_len` bytes. See
+    /// [`DecompressionError`](enum.DecompressionError.html) for other
+    /// error cases.
+    pub fn decompress_to_vec<C: Codec + ?Sized>(&mut self,
+                                                codec: &C,
+                                                in_data: &[u8],
+                                                max_out_len: usize) -> DecompressionResult<Vec<u8>> {
+        let size_hint = codec.decompressed_size_hint(in_data);
+        self.decompress_to_growing_vec(in_data, size_hint, max_out_len, |decompressor, in_data, out| {
+            codec.decompress_uninit(decompressor, in_data, out, sealed::Token)
+        })
+    }
+
+    /// Repeatedly calls `decompress` with a geometrically growing
+    /// (uninitialized) output buffer (starting at `size_hint`, capped
+    /// at `max_out_len`) until the data fits.
+    fn decompress_to_growing_vec(&mut self,
+                                 in_data: &[u8],
+                                 size_hint: usize,
+                                 max_out_len: usize,
+                                 mut decompress: impl FnMut(&mut Decompressor, &[u8], &mut [MaybeUninit<u8>]) -> DecompressionResult<usize>) -> DecompressionResult<Vec<u8>> {
+        let size_hint = core::cmp::min(size_hint, in_data.len().saturating_mul(MAX_DEFLATE_RATIO));
+        let mut out = Vec::new();
+        let mut out_len = core::cmp::min(core::cmp::max(size_hint, MIN_TO_VEC_OUT_LEN), max_out_len);
+
+        loop {
+            out.reserve_exact(out_len);
+
+            match decompress(self, in_data, &mut out.spare_capacity_mut()[..out_len]) {
+                Ok(sz) => {
+                    // `decompress` may be a `Codec`'s (safe,
+                    // user-implemented) `decompress`, so its result
+                    // can't be trusted
+                    assert!(sz <= out_len, "Codec::decompress reported more bytes than fit in the output buffer");
+                    unsafe {
+                        out.set_len(sz);
+                    }
+                    return Ok(out);
                 },
-                libdeflate_result_LIBDEFLATE_BAD_DATA => {
-                    Err(DecompressionError::BadData)
+                Err(DecompressionError::InsufficientSpace) => {
+                    if out_len >= max_out_len {
+                        return Err(DecompressionError::OutputLimitExceeded);
+                    }
+                    out_len = core::cmp::min(out_len.saturating_mul(2), max_out_len);
                 },
-                libdeflate_result_LIBDEFLATE_INSUFFICIENT_SPACE => {
-                    Err(DecompressionError::InsufficientSpace)
+                Err(e) => {
+                    return Err(e);
                 },
-                _ => {
-                    panic!("libdeflate_deflate_decompress returned an unknown error type: this is an internal bug that **must** be fixed");
-                }
             }
         }
     }
@@ -274,8 +1458,9 @@ impl Decompressor {
 
 impl Drop for Decompressor {
     fn drop(&mut self) {
+        let p = self.p.as_ptr();
         unsafe {
-            libdeflate_free_decompressor(self.p.as_ptr());
+            backend::free_decompressor(p, self.allocator.as_ref(), &mut self.memory);
         }
     }
 }
@@ -382,26 +1567,39 @@ impl From<&CompressionLvl> for i32 {
 /// An error that may be returned when calling one of the
 /// [`Compressor`](struct.Compressor.html)'s `compress_*` methods.
 #[derive(Debug, PartialEq)]
+#[non_exhaustive]
 pub enum CompressionError {
     InsufficientSpace,
+
+    /// The output buffer could not be allocated by one of the
+    /// `*_compress_to_vec` or `*_compress_append` methods, or a
+    /// compressor could not be allocated by one of the
+    /// `CompressorPool::*_compress_to_vec` methods.
+    AllocationFailed,
 }
 
 impl fmt::Display for CompressionError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self {
             CompressionError::InsufficientSpace => write!(f, "the output buffer provided to a libdeflater *_compress function call was too small for the input data"),
+            CompressionError::AllocationFailed => write!(f, "the output buffer for a libdeflater *_compress_to_vec or *_compress_append function call could not be allocated"),
         }
     }
 }
 
+#[cfg(feature = "std")]
 impl Error for CompressionError {}
 
-type CompressionResult<T> = std::result::Result<T, CompressionError>;
+/// A result returned by compression methods
+pub type CompressionResult<T> = core::result::Result<T, CompressionError>;
 
 /// A `libdeflate` compressor that can compress arbitrary data into
 /// DEFLATE, zlib, or gzip formats.
 pub struct Compressor {
-    p: NonNull<libdeflate_compressor>,
+    p: NonNull<backend::CompressorState>,
+    lvl: CompressionLvl,
+    allocator: Option<BoxedAllocator>,
+    memory: MemoryUsage,
 }
 unsafe impl Send for Compressor {}
 
@@ -416,17 +1614,96 @@ impl Compressor {
     /// Returns a newly constructed `Compressor` that compresses data
     /// with the supplied
     /// [`CompressionLvl`](struct.CompressionLvl.html)
+    ///
+    /// # Panics
+    ///
+    /// Panics if the compressor could not be allocated. Use
+    /// [`try_new`](#method.try_new) to handle that case instead.
     pub fn new(lvl: CompressionLvl) -> Compressor {
-        unsafe {
-            let ptr = alloc_compressor(lvl.0 as _);
-            if let Some(ptr) = NonNull::new(ptr) {
-                Compressor{ p: ptr }
-            } else {
-                panic!("libdeflate_alloc_compressor returned NULL: out of memory");
-            }
+        match Compressor::try_new(lvl) {
+            Ok(compressor) => compressor,
+            Err(_) => panic!("libdeflate_alloc_compressor returned NULL: out of memory"),
+        }
+    }
+
+    /// Returns a newly constructed `Compressor` that compresses data
+    /// with the supplied
+    /// [`CompressionLvl`](struct.CompressionLvl.html), or an
+    /// [`AllocError`](struct.AllocError.html) if it could not be
+    /// allocated.
+    ///
+    /// Compressors for the higher compression levels require
+    /// considerably more memory, so this is useful for services that
+    /// would rather shed load than panic when memory is constrained.
+    pub fn try_new(lvl: CompressionLvl) -> Result<Compressor, AllocError> {
+        Compressor::try_new_in(lvl, None)
+    }
+
+    /// Returns a newly constructed `Compressor` that compresses data
+    /// with the supplied
+    /// [`CompressionLvl`](struct.CompressionLvl.html) and whose
+    /// internal state is allocated with `allocator` rather than the
+    /// default allocator.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the compressor could not be allocated. Use
+    /// [`try_with_allocator`](#method.try_with_allocator) to handle
+    /// that case instead.
+    ///
+    /// Without the `std` feature, `allocator` must not itself construct
+    /// or drop a compressor/decompressor, or this deadlocks (see
+    /// [`Allocator`](trait.Allocator.html)).
+    pub fn with_allocator<A: Allocator + Send + 'static>(lvl: CompressionLvl, allocator: A) -> Compressor {
+        match Compressor::try_with_allocator(lvl, allocator) {
+            Ok(compressor) => compressor,
+            Err(_) => panic!("libdeflate_alloc_compressor_ex returned NULL: out of memory"),
         }
     }
 
+    /// Returns a newly constructed `Compressor` that compresses data
+    /// with the supplied
+    /// [`CompressionLvl`](struct.CompressionLvl.html) and whose
+    /// internal state is allocated with `allocator`, or an
+    /// [`AllocError`](struct.AllocError.html) if `allocator` could not
+    /// allocate it.
+    pub fn try_with_allocator<A: Allocator + Send + 'static>(lvl: CompressionLvl, allocator: A) -> Result<Compressor, AllocError> {
+        Compressor::try_new_in(lvl, Some(Box::new(allocator)))
+    }
+
+    fn try_new_in(lvl: CompressionLvl, allocator: Option<BoxedAllocator>) -> Result<Compressor, AllocError> {
+        let mut memory = MemoryUsage::default();
+        let ptr = unsafe { backend::alloc_compressor(lvl, allocator.as_ref(), &mut memory) };
+        NonNull::new(ptr).map(|p| Compressor{ p, lvl, allocator, memory }).ok_or(AllocError)
+    }
+
+    /// Returns the [`CompressionLvl`](struct.CompressionLvl.html) that
+    /// this compressor was constructed with.
+    pub fn lvl(&self) -> CompressionLvl {
+        self.lvl
+    }
+
+    /// Returns the amount of memory allocated by this compressor.
+    ///
+    /// `libdeflate` allocates all of a compressor's state when it is
+    /// constructed, so this does not change over its lifetime. With
+    /// the `rust_backend` feature, only part of that state is counted
+    /// (see [`set_memory_limit`](fn.set_memory_limit.html)).
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.memory
+    }
+
+    /// Returns the number of bytes that a `Compressor` with the
+    /// supplied [`CompressionLvl`](struct.CompressionLvl.html)
+    /// allocates, without constructing one. This is useful for sizing
+    /// pools of compressors by memory.
+    ///
+    /// With the `rust_backend` feature this underestimates the real
+    /// footprint (see [`set_memory_limit`](fn.set_memory_limit.html)).
+    pub fn estimated_memory(lvl: CompressionLvl) -> usize {
+        backend::compressor_memory(lvl)
+    }
+
     /// Returns the maximum number of bytes required to encode
     /// `n_bytes` as [`deflate`](https://tools.ietf.org/html/rfc1951)
     /// data. This is a hard upper-bound that assumes the worst
@@ -434,7 +1711,7 @@ impl Compressor {
     /// compressed), format overhead, etc.
     pub fn deflate_compress_bound(&mut self, n_bytes: usize) -> usize {
         unsafe {
-            libdeflate_deflate_compress_bound(self.p.as_ptr(), n_bytes)
+            backend::deflate_compress_bound(self.p.as_ptr(), n_bytes)
         }
     }
 
@@ -445,24 +1722,57 @@ impl Compressor {
     pub fn deflate_compress(&mut self,
                             in_raw_data: &[u8],
                             out_deflate_data: &mut [u8]) -> CompressionResult<usize> {
+        self.deflate_compress_uninit(in_raw_data, unsafe { as_uninit_mut(out_deflate_data) })
+    }
+
+    /// Like [`deflate_compress`](#method.deflate_compress), but writes
+    /// into a possibly-uninitialized buffer, which saves callers from
+    /// zero-filling large output buffers. Returns the number of bytes
+    /// written into `out_deflate_data`, which are then initialized.
+    pub fn deflate_compress_uninit(&mut self,
+                                   in_raw_data: &[u8],
+                                   out_deflate_data: &mut [MaybeUninit<u8>]) -> CompressionResult<usize> {
         unsafe {
-            let in_ptr = in_raw_data.as_ptr() as *const std::ffi::c_void;
-            let out_ptr = out_deflate_data.as_mut_ptr() as *mut std::ffi::c_void;
-
-            let sz = libdeflate_deflate_compress(self.p.as_ptr(),
-                                                 in_ptr,
-                                                 in_raw_data.len(),
-                                                 out_ptr,
-                                                 out_deflate_data.len());
-
-            if sz != 0 {
-                Ok(sz)
-            } else {
-                Err(CompressionError::InsufficientSpace)
-            }
+            self.raw_compress(backend::deflate_compress, in_raw_data, out_deflate_data)
         }
     }
 
+    /// Compresses `in_raw_data` as
+    /// [`deflate`](https://tools.ietf.org/html/rfc1951) data into a
+    /// newly-allocated `Vec`.
+    ///
+    /// The `Vec` is allocated with room for
+    /// [`deflate_compress_bound`](#method.deflate_compress_bound) bytes and is
+    /// then shrunk to fit the compressed data. Returns
+    /// `CompressionError::AllocationFailed`, rather than aborting, if
+    /// it cannot be allocated.
+    pub fn deflate_compress_to_vec(&mut self,
+                                   in_raw_data: &[u8]) -> CompressionResult<Vec<u8>> {
+        let mut out = Vec::new();
+        self.deflate_compress_append(in_raw_data, &mut out)?;
+        out.shrink_to_fit();
+        Ok(out)
+    }
+
+    /// Compresses `in_raw_data` as
+    /// [`deflate`](https://tools.ietf.org/html/rfc1951) data, appending
+    /// the compressed data to `out`. Returns the number of bytes
+    /// appended.
+    ///
+    /// `out` is grown (with `try_reserve`) so that it has room for
+    /// [`deflate_compress_bound`](#method.deflate_compress_bound) more bytes,
+    /// and the compressed data is written straight into its spare
+    /// capacity. `out` is not shrunk afterwards, which suits callers
+    /// that reuse it; call `shrink_to_fit` on it if that is required.
+    /// Returns `CompressionError::AllocationFailed`, rather than
+    /// aborting, if `out` cannot be grown.
+    pub fn deflate_compress_append(&mut self,
+                                   in_raw_data: &[u8],
+                                   out: &mut Vec<u8>) -> CompressionResult<usize> {
+        let bound = self.deflate_compress_bound(in_raw_data.len());
+        self.compress_append(in_raw_data, out, bound, Compressor::deflate_compress_uninit)
+    }
+
     /// Returns the maximum number of bytes required to encode
     /// `n_bytes` as [`zlib`](https://www.ietf.org/rfc/rfc1950.txt)
     /// data. This is a hard upper-bound that assumes the worst
@@ -470,7 +1780,7 @@ impl Compressor {
     /// compressed), format overhead, etc.
     pub fn zlib_compress_bound(&mut self, n_bytes: usize) -> usize {
         unsafe {
-            libdeflate_zlib_compress_bound(self.p.as_ptr(), n_bytes)
+            backend::zlib_compress_bound(self.p.as_ptr(), n_bytes)
         }
     }
 
@@ -481,24 +1791,45 @@ impl Compressor {
     pub fn zlib_compress(&mut self,
                          in_raw_data: &[u8],
                          out_zlib_data: &mut [u8]) -> CompressionResult<usize> {
+        self.zlib_compress_uninit(in_raw_data, unsafe { as_uninit_mut(out_zlib_data) })
+    }
+
+    /// Like [`zlib_compress`](#method.zlib_compress), but writes
+    /// into a possibly-uninitialized buffer. Returns the number of
+    /// bytes written into `out_zlib_data`, which are then initialized.
+    pub fn zlib_compress_uninit(&mut self,
+                                in_raw_data: &[u8],
+                                out_zlib_data: &mut [MaybeUninit<u8>]) -> CompressionResult<usize> {
         unsafe {
-            let in_ptr = in_raw_data.as_ptr() as *const std::ffi::c_void;
-            let out_ptr = out_zlib_data.as_mut_ptr() as *mut std::ffi::c_void;
-
-            let sz = libdeflate_zlib_compress(self.p.as_ptr(),
-                                              in_ptr,
-                                              in_raw_data.len(),
-                                              out_ptr,
-                                              out_zlib_data.len());
-
-            if sz != 0 {
-                Ok(sz)
-            } else {
-                Err(CompressionError::InsufficientSpace)
-            }
+            self.raw_compress(backend::zlib_compress, in_raw_data, out_zlib_data)
         }
     }
 
+    /// Compresses `in_raw_data` as
+    /// [`zlib`](https://www.ietf.org/rfc/rfc1950.txt) data into a
+    /// newly-allocated `Vec`, which is shrunk to fit the compressed
+    /// data. See
+    /// [`deflate_compress_to_vec`](#method.deflate_compress_to_vec).
+    pub fn zlib_compress_to_vec(&mut self,
+                                in_raw_data: &[u8]) -> CompressionResult<Vec<u8>> {
+        let mut out = Vec::new();
+        self.zlib_compress_append(in_raw_data, &mut out)?;
+        out.shrink_to_fit();
+        Ok(out)
+    }
+
+    /// Compresses `in_raw_data` as
+    /// [`zlib`](https://www.ietf.org/rfc/rfc1950.txt) data, appending
+    /// the compressed data to `out`. Returns the number of bytes
+    /// appended. See
+    /// [`deflate_compress_append`](#method.deflate_compress_append).
+    pub fn zlib_compress_append(&mut self,
+                                in_raw_data: &[u8],
+                                out: &mut Vec<u8>) -> CompressionResult<usize> {
+        let bound = self.zlib_compress_bound(in_raw_data.len());
+        self.compress_append(in_raw_data, out, bound, Compressor::zlib_compress_uninit)
+    }
+
     /// Returns the maximum number of bytes required to encode
     /// `n_bytes` as [`gzip`](https://tools.ietf.org/html/rfc1952)
     /// data. This is a hard upper-bound that assumes the worst
@@ -506,7 +1837,7 @@ impl Compressor {
     /// compressed), format overhead, etc.
     pub fn gzip_compress_bound(&mut self, n_bytes: usize) -> usize {
         unsafe {
-            libdeflate_gzip_compress_bound(self.p.as_ptr(), n_bytes)
+            backend::gzip_compress_bound(self.p.as_ptr(), n_bytes)
         }
     }
 
@@ -517,29 +1848,175 @@ impl Compressor {
     pub fn gzip_compress(&mut self,
                          in_raw_data: &[u8],
                          out_gzip_data: &mut [u8]) -> CompressionResult<usize> {
+        self.gzip_compress_uninit(in_raw_data, unsafe { as_uninit_mut(out_gzip_data) })
+    }
+
+    /// Like [`gzip_compress`](#method.gzip_compress), but writes
+    /// into a possibly-uninitialized buffer. Returns the number of
+    /// bytes written into `out_gzip_data`, which are then initialized.
+    pub fn gzip_compress_uninit(&mut self,
+                                in_raw_data: &[u8],
+                                out_gzip_data: &mut [MaybeUninit<u8>]) -> CompressionResult<usize> {
         unsafe {
-            let in_ptr = in_raw_data.as_ptr() as *const std::ffi::c_void;
-            let out_ptr = out_gzip_data.as_mut_ptr() as *mut std::ffi::c_void;
-
-            let sz = libdeflate_gzip_compress(self.p.as_ptr(),
-                                              in_ptr,
-                                              in_raw_data.len(),
-                                              out_ptr,
-                                              out_gzip_data.len());
-
-            if sz != 0 {
-                Ok(sz)
-            } else {
-                Err(CompressionError::InsufficientSpace)
-            }
+            self.raw_compress(backend::gzip_compress, in_raw_data, out_gzip_data)
         }
     }
+
+    /// Compresses `in_raw_data` as
+    /// [`gzip`](https://tools.ietf.org/html/rfc1952) data into a
+    /// newly-allocated `Vec`, which is shrunk to fit the compressed
+    /// data. See
+    /// [`deflate_compress_to_vec`](#method.deflate_compress_to_vec).
+    pub fn gzip_compress_to_vec(&mut self,
+                                in_raw_data: &[u8]) -> CompressionResult<Vec<u8>> {
+        let mut out = Vec::new();
+        self.gzip_compress_append(in_raw_data, &mut out)?;
+        out.shrink_to_fit();
+        Ok(out)
+    }
+
+    /// Compresses `in_raw_data` as
+    /// [`gzip`](https://tools.ietf.org/html/rfc1952) data, appending
+    /// the compressed data to `out`. Returns the number of bytes
+    /// appended. See
+    /// [`deflate_compress_append`](#method.deflate_compress_append).
+    pub fn gzip_compress_append(&mut self,
+                                in_raw_data: &[u8],
+                                out: &mut Vec<u8>) -> CompressionResult<usize> {
+        let bound = self.gzip_compress_bound(in_raw_data.len());
+        self.compress_append(in_raw_data, out, bound, Compressor::gzip_compress_uninit)
+    }
+
+    /// Reserves `bound` bytes in `out`, calls `compress` on its spare
+    /// capacity, then extends `out` to cover the compressed bytes.
+    fn compress_append(&mut self,
+                       in_raw_data: &[u8],
+                       out: &mut Vec<u8>,
+                       bound: usize,
+                       compress: impl FnOnce(&mut Compressor, &[u8], &mut [MaybeUninit<u8>]) -> CompressionResult<usize>) -> CompressionResult<usize> {
+        out.try_reserve(bound).map_err(|_| CompressionError::AllocationFailed)?;
+        let sz = compress(self, in_raw_data, &mut out.spare_capacity_mut()[..bound])?;
+        // `compress` may be a `Codec`'s (safe, user-implemented)
+        // `compress`, so its result can't be trusted
+        assert!(sz <= bound, "Codec::compress reported more bytes than fit in the output buffer");
+        unsafe {
+            out.set_len(out.len() + sz);
+        }
+        Ok(sz)
+    }
+
+    /// Calls one of the backend's `*_compress` functions.
+    ///
+    /// # Safety
+    ///
+    /// `compress` must be one of the backend's `*_compress` functions.
+    unsafe fn raw_compress(&mut self,
+                           compress: backend::RawCompressFn,
+                           in_raw_data: &[u8],
+                           out: &mut [MaybeUninit<u8>]) -> CompressionResult<usize> {
+        let sz = compress(self.p.as_ptr(), in_raw_data, out);
+
+        if sz != 0 {
+            Ok(sz)
+        } else {
+            Err(CompressionError::InsufficientSpace)
+        }
+    }
+
+    /// Returns the maximum number of bytes required to encode
+    /// `n_bytes` as data in the supplied [`Format`](enum.Format.html)
+    /// (see the `*_compress_bound` methods).
+    pub fn compress_bound(&mut self, format: Format, n_bytes: usize) -> usize {
+        match format {
+            Format::Gzip => self.gzip_compress_bound(n_bytes),
+            Format::Zlib => self.zlib_compress_bound(n_bytes),
+            Format::Deflate => self.deflate_compress_bound(n_bytes),
+        }
+    }
+
+    /// Compresses `in_raw_data` as data in the supplied
+    /// [`Format`](enum.Format.html) with the matching `*_compress`
+    /// method, writing the data into `out`. Returns the number of
+    /// bytes written into `out`.
+    pub fn compress(&mut self,
+                    format: Format,
+                    in_raw_data: &[u8],
+                    out: &mut [u8]) -> CompressionResult<usize> {
+        match format {
+            Format::Gzip => self.gzip_compress(in_raw_data, out),
+            Format::Zlib => self.zlib_compress(in_raw_data, out),
+            Format::Deflate => self.deflate_compress(in_raw_data, out),
+        }
+    }
+
+    /// Compresses `in_raw_data` with `codec` (e.g. a
+    /// [`Format`](enum.Format.html), or a user-defined
+    /// [`Codec`](trait.Codec.html)) into a newly-allocated `Vec`,
+    /// which is shrunk to fit the compressed data. See
+    /// [`deflate_compress_to_vec`](#method.deflate_compress_to_vec).
+    pub fn compress_to_vec<C: Codec + ?Sized>(&mut self,
+                                              codec: &C,
+                                              in_raw_data: &[u8]) -> CompressionResult<Vec<u8>> {
+        let bound = codec.compress_bound(self, in_raw_data.len());
+        let mut out = Vec::new();
+        self.compress_append(in_raw_data, &mut out, bound, |compressor, in_raw_data, out| {
+            codec.compress_uninit(compressor, in_raw_data, out, sealed::Token)
+        })?;
+        out.shrink_to_fit();
+        Ok(out)
+    }
+
+    /// Returns the maximum number of bytes required to encode
+    /// `n_bytes` as [`gzip`](https://tools.ietf.org/html/rfc1952)
+    /// data with the supplied `header` (see
+    /// [`gzip_compress_with_header`](#method.gzip_compress_with_header)).
+    /// This is a hard upper-bound that assumes the worst possible
+    /// compression ratio (i.e. assumes the data cannot be
+    /// compressed), format overhead, etc.
+    pub fn gzip_compress_with_header_bound(&mut self,
+                                           header: &GzipHeader,
+                                           n_bytes: usize) -> usize {
+        header.encoded_len() + self.deflate_compress_bound(n_bytes) + gzip::FOOTER_SIZE
+    }
+
+    /// Compresses `in_raw_data` as
+    /// [`gzip`](https://tools.ietf.org/html/rfc1952) data with the
+    /// supplied `header`, writing the data into `out_gzip_data`.
+    /// Returns the number of bytes written into `out_gzip_data`.
+    ///
+    /// Unlike [`gzip_compress`](#method.gzip_compress), which always
+    /// writes `libdeflate`'s minimal header, this can be used to
+    /// preserve a file name, set a fixed MTIME, attach FEXTRA
+    /// subfields, protect the header with an FHCRC, etc.
+    pub fn gzip_compress_with_header(&mut self,
+                                     header: &GzipHeader,
+                                     in_raw_data: &[u8],
+                                     out_gzip_data: &mut [u8]) -> CompressionResult<usize> {
+        let header_bytes = header.to_bytes();
+        let header_end = header_bytes.len();
+        if out_gzip_data.len() < header_end + gzip::FOOTER_SIZE {
+            return Err(CompressionError::InsufficientSpace);
+        }
+        out_gzip_data[..header_end].copy_from_slice(&header_bytes);
+
+        let deflate_end = out_gzip_data.len() - gzip::FOOTER_SIZE;
+        let deflate_sz = self.deflate_compress(in_raw_data, &mut out_gzip_data[header_end..deflate_end])?;
+        let footer_start = header_end + deflate_sz;
+
+        let mut crc = Crc::new();
+        crc.update(in_raw_data);
+        out_gzip_data[footer_start..footer_start + 4].copy_from_slice(&crc.sum().to_le_bytes());
+        out_gzip_data[footer_start + 4..footer_start + 8].copy_from_slice(&(in_raw_data.len() as u32).to_le_bytes());
+
+        Ok(footer_start + gzip::FOOTER_SIZE)
+    }
 }
 
 impl Drop for Compressor {
     fn drop(&mut self) {
+        let p = self.p.as_ptr();
         unsafe {
-            libdeflate_free_compressor(self.p.as_ptr());
+            backend::free_compressor(p, self.allocator.as_ref(), &mut self.memory);
         }
     }
 }
@@ -564,17 +2041,20 @@ impl Crc {
 
     /// Update the CRC with the bytes in `data`
     pub fn update(&mut self, data: &[u8]) {
-        unsafe {
-            self.val = libdeflate_crc32(self.val,
-                                        data.as_ptr() as *const core::ffi::c_void,
-                                        data.len());
-        }
+        self.val = backend::crc32(self.val, data);
     }
 
     /// Returns the current CRC32 checksum
     pub const fn sum(&self) -> u32 {
         self.val
     }
+
+    /// Updates the CRC as if it had also been updated with the
+    /// `other_len` bytes that `other` was computed from, without
+    /// needing those bytes (see [`crc32_combine`](fn.crc32_combine.html)).
+    pub fn combine(&mut self, other: &Crc, other_len: u64) {
+        self.val = crc32_combine(self.val, other.val, other_len);
+    }
 }
 
 /// Returns the CRC32 checksum of the bytes in `data`.
@@ -584,10 +2064,67 @@ impl Crc {
 /// (e.g.) a stream should use [`Crc`](struct.Crc.html)
 pub fn crc32(data: &[u8]) -> u32 {
     let mut crc = Crc::new();
-    crc.update(&data);
+    crc.update(data);
     crc.sum()
 }
 
+/// The CRC32 polynomial, bit-reversed.
+const CRC32_POLY: u32 = 0xedb88320;
+
+/// `X2N_TABLE[k]` is x^(2^k) modulo the CRC32 polynomial.
+const X2N_TABLE: [u32; 32] = {
+    let mut table = [0; 32];
+    let mut p = 1 << 30; // x^1
+    let mut k = 0;
+    while k < 32 {
+        table[k] = p;
+        p = multmodp(p, p);
+        k += 1;
+    }
+    table
+};
+
+/// Multiplies `a` and `b` modulo the CRC32 polynomial.
+const fn multmodp(a: u32, mut b: u32) -> u32 {
+    let mut m = 1 << 31;
+    let mut p = 0;
+    loop {
+        if a & m != 0 {
+            p ^= b;
+            if a & (m - 1) == 0 {
+                break;
+            }
+        }
+        m >>= 1;
+        b = if b & 1 != 0 { (b >> 1) ^ CRC32_POLY } else { b >> 1 };
+    }
+    p
+}
+
+/// Returns the CRC32 checksum of two concatenated sequences of bytes,
+/// given the checksum of the first sequence (`crc1`), the checksum of
+/// the second sequence (`crc2`), and the length of the second
+/// sequence (`len2`).
+///
+/// This is zlib's `crc32_combine`. It takes O(log(`len2`)) time and
+/// does not need the bytes themselves, so checksums of chunks that
+/// were processed independently (e.g. on different threads) can be
+/// merged.
+pub fn crc32_combine(crc1: u32, crc2: u32, len2: u64) -> u32 {
+    // multiply crc1 by x^(8 * len2), i.e. append len2 zero bytes
+    let mut x2n = 1 << 31; // x^0
+    let mut n = len2;
+    let mut k = 3;
+    while n != 0 {
+        if n & 1 != 0 {
+            x2n = multmodp(X2N_TABLE[k & 31], x2n);
+        }
+        n >>= 1;
+        k += 1;
+    }
+    multmodp(x2n, crc1) ^ crc2
+}
+
 /// Struct holding the state required to compute a rolling adler32
 /// value.
 pub struct Adler32 {
@@ -607,17 +2144,18 @@ impl Adler32 {
     }
     /// Update the Adler32 with the bytes in `data`
     pub fn update(&mut self, data: &[u8]) {
-        unsafe
-            {
-                self.val = libdeflate_adler32(self.val,
-                                              data.as_ptr() as *const core::ffi::c_void,
-                                              data.len());
-            }
+        self.val = backend::adler32(self.val, data);
     }
     /// Returns the current Adler32 checksum
     pub const fn sum(&self) -> u32 {
         self.val
     }
+    /// Updates the Adler32 as if it had also been updated with the
+    /// `other_len` bytes that `other` was computed from, without
+    /// needing those bytes (see [`adler32_combine`](fn.adler32_combine.html)).
+    pub fn combine(&mut self, other: &Adler32, other_len: u64) {
+        self.val = adler32_combine(self.val, other.val, other_len);
+    }
 }
 /// Returns the Adler32 checksum of the bytes in `data`.
 ///
@@ -626,6 +2164,32 @@ impl Adler32 {
 /// (e.g.) a stream should use [`Adler32`](struct.Adler32.html)
 pub fn adler32(data:&[u8]) -> u32 {
     let mut adler32 = Adler32::new();
-    adler32.update(&data);
+    adler32.update(data);
     adler32.sum()
-}
\ No newline at end of file
+}
+
+/// Largest prime smaller than 65536, which Adler32 sums are taken
+/// modulo.
+const ADLER32_BASE: u64 = 65521;
+
+/// Returns the Adler32 checksum of two concatenated sequences of
+/// bytes, given the checksum of the first sequence (`adler1`), the
+/// checksum of the second sequence (`adler2`), and the length of the
+/// second sequence (`len2`).
+///
+/// This is zlib's `adler32_combine`. It takes constant time and does
+/// not need the bytes themselves, so checksums of chunks that were
+/// processed independently (e.g. on different threads) can be merged.
+pub fn adler32_combine(adler1: u32, adler2: u32, len2: u64) -> u32 {
+    let rem = len2 % ADLER32_BASE;
+    let a1 = (adler1 & 0xffff) as u64;
+    let b1 = (adler1 >> 16) as u64;
+    let a2 = (adler2 & 0xffff) as u64;
+    let b2 = (adler2 >> 16) as u64;
+
+    // the second sequence's B sum counts every byte of the first
+    // sequence's A sum once per byte of the second sequence
+    let a = (a1 + a2 + ADLER32_BASE - 1) % ADLER32_BASE;
+    let b = (rem * a1 % ADLER32_BASE + b1 + b2 + ADLER32_BASE - rem) % ADLER32_BASE;
+    (a | (b << 16)) as u32
+}
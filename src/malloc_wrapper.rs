@@ -21,30 +21,295 @@
 //! `sizeof(usize)` back and get the original `size_and_data_ptr`.
 //! At this point we can read `size` back and call the Rust `dealloc`
 //! for the whole allocated chunk.
+//!
+//! Like their C counterparts, `malloc` returns NULL when the
+//! allocation fails (which `libdeflate` reports to us as a NULL
+//! compressor/decompressor) and `free` ignores NULL.
+//!
+//! `libdeflate`'s allocation callbacks don't take a context pointer,
+//! so the per-instance state they need (a custom [`Allocator`], if
+//! any, and the instance's [`MemoryUsage`]) is passed to them through
+//! a thread-local: [`with_context`] installs it for the duration of a
+//! `libdeflate_alloc_*_ex` or `libdeflate_free_*` call (`libdeflate`
+//! doesn't allocate anywhere else). Without the `std` feature there
+//! are no thread-locals, so the context is a global that is guarded
+//! by a (non-re-entrant) spin lock instead. Without a custom allocator, the global
+//! Rust allocator is used if the `use_rust_alloc` feature is enabled
+//! (or the `std` feature is disabled), and the system allocator
+//! otherwise.
+//!
+//! Every allocation is also counted towards the process-wide total,
+//! which is checked against the limit set with `set_memory_limit`.
+//!
+//! The pure-Rust backend (the `rust_backend` feature) doesn't need
+//! `malloc` and `free`, and allocates its state with [`allocate`] and
+//! [`deallocate`] inside [`with_context`] instead.
 
+use alloc::alloc::Layout;
+use alloc::boxed::Box;
+#[cfg(not(feature = "rust_backend"))]
 use libdeflate_sys::libdeflate_options;
-use std::alloc::*;
-use std::ffi::c_void;
-use std::mem::{align_of, size_of};
+#[cfg(not(feature = "rust_backend"))]
+use core::ffi::c_void;
+#[cfg(not(feature = "rust_backend"))]
+use core::mem::{align_of, size_of};
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(feature = "std"))]
+use core::sync::atomic::AtomicBool;
+use crate::{Allocator, MemoryUsage};
+
+/// A per-instance allocator, as stored by a `Compressor` or
+/// `Decompressor`.
+pub(crate) type BoxedAllocator = Box<dyn Allocator + Send>;
+
+/// Number of bytes currently allocated by `malloc`, across all threads.
+static TOTAL_LIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// Maximum value of `TOTAL_LIVE`. `usize::MAX` means no limit.
+static LIMIT: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// The state used by `malloc` and `free` during a `libdeflate` call.
+#[derive(Clone, Copy)]
+struct Context {
+    allocator: Option<*const (dyn Allocator + Send)>,
+    usage: *mut MemoryUsage,
+    /// Record the size of allocations without performing them.
+    probe: bool,
+}
+
+#[cfg(feature = "std")]
+thread_local! {
+    static CURRENT_CONTEXT: core::cell::Cell<Option<Context>> = const { core::cell::Cell::new(None) };
+}
+
+#[cfg(not(feature = "std"))]
+struct GlobalContext(core::cell::UnsafeCell<Option<Context>>);
+
+// only accessed while holding `CONTEXT_LOCK`
+#[cfg(not(feature = "std"))]
+unsafe impl Sync for GlobalContext {}
+
+#[cfg(not(feature = "std"))]
+static CURRENT_CONTEXT: GlobalContext = GlobalContext(core::cell::UnsafeCell::new(None));
 
-unsafe fn layout_for(size: usize) -> Layout {
-    Layout::from_size_align_unchecked(size_of::<usize>() + size, align_of::<usize>())
+#[cfg(not(feature = "std"))]
+static CONTEXT_LOCK: AtomicBool = AtomicBool::new(false);
+
+/// Calls `f`, which allocates or frees a `libdeflate`
+/// compressor/decompressor, with `allocator` (or the default
+/// allocator) used by `malloc` and `free`, and with the memory they
+/// allocate or free recorded in `usage`.
+pub(crate) fn with_context<R>(allocator: Option<&BoxedAllocator>, usage: &mut MemoryUsage, f: impl FnOnce() -> R) -> R {
+    let context = Context {
+        allocator: allocator.map(|allocator| &**allocator as *const (dyn Allocator + Send)),
+        usage,
+        probe: false,
+    };
+    run_in_context(context, f)
+}
+
+/// Calls `f`, which allocates a `libdeflate` compressor/decompressor,
+/// and returns the number of bytes it tried to allocate, without
+/// actually allocating anything. `f` sees every allocation fail.
+#[cfg(not(feature = "rust_backend"))]
+pub(crate) fn probe_allocation_size(f: impl FnOnce()) -> usize {
+    let mut usage = MemoryUsage::default();
+    let context = Context { allocator: None, usage: &mut usage, probe: true };
+    run_in_context(context, f);
+    usage.peak
+}
+
+/// Restores the previous context when dropped, i.e. when `f` returns
+/// or panics.
+#[cfg(feature = "std")]
+struct ContextGuard(Option<Context>);
+
+#[cfg(feature = "std")]
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        CURRENT_CONTEXT.with(|current| current.set(self.0));
+    }
+}
+
+#[cfg(feature = "std")]
+fn run_in_context<R>(context: Context, f: impl FnOnce() -> R) -> R {
+    let _guard = ContextGuard(CURRENT_CONTEXT.with(|current| current.replace(Some(context))));
+    f()
+}
+
+#[cfg(feature = "std")]
+fn current_context() -> Option<Context> {
+    CURRENT_CONTEXT.with(core::cell::Cell::get)
 }
 
+/// Clears the context and releases `CONTEXT_LOCK` when dropped, i.e.
+/// when `f` returns or panics, so that a panicking allocator doesn't
+/// leave the lock held forever.
+#[cfg(not(feature = "std"))]
+struct ContextGuard;
+
+#[cfg(not(feature = "std"))]
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        unsafe {
+            *CURRENT_CONTEXT.0.get() = None;
+        }
+        CONTEXT_LOCK.store(false, Ordering::Release);
+    }
+}
+
+/// The lock isn't re-entrant: `f` must not construct or drop another
+/// compressor/decompressor (e.g. from within a custom `Allocator`), and
+/// must not be interrupted by code that does on the same core, or it
+/// spins forever.
+#[cfg(not(feature = "std"))]
+fn run_in_context<R>(context: Context, f: impl FnOnce() -> R) -> R {
+    while CONTEXT_LOCK.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+        core::hint::spin_loop();
+    }
+    let _guard = ContextGuard;
+    unsafe {
+        *CURRENT_CONTEXT.0.get() = Some(context);
+    }
+    f()
+}
+
+#[cfg(not(feature = "std"))]
+fn current_context() -> Option<Context> {
+    // `malloc` and `free` are only called by `libdeflate` from within
+    // `run_in_context`, i.e. while holding `CONTEXT_LOCK`
+    unsafe { *CURRENT_CONTEXT.0.get() }
+}
+
+/// Returns the number of bytes currently allocated by `libdeflate`
+/// across all threads.
+pub(crate) fn total_live() -> usize {
+    TOTAL_LIVE.load(Ordering::SeqCst)
+}
+
+pub(crate) fn set_limit(limit: Option<usize>) {
+    LIMIT.store(limit.unwrap_or(usize::MAX), Ordering::SeqCst);
+}
+
+pub(crate) fn limit() -> Option<usize> {
+    match LIMIT.load(Ordering::SeqCst) {
+        usize::MAX => None,
+        limit => Some(limit),
+    }
+}
+
+/// Adds `size` bytes to the process-wide total, unless that would
+/// exceed the limit.
+fn reserve(size: usize) -> bool {
+    let limit = LIMIT.load(Ordering::SeqCst);
+    TOTAL_LIVE
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |live| {
+            live.checked_add(size).filter(|&total| total <= limit)
+        })
+        .is_ok()
+}
+
+fn release(size: usize) {
+    TOTAL_LIVE.fetch_sub(size, Ordering::SeqCst);
+}
+
+#[cfg(not(feature = "rust_backend"))]
+fn layout_for(size: usize) -> Option<Layout> {
+    let total_size = size_of::<usize>().checked_add(size)?;
+    Layout::from_size_align(total_size, align_of::<usize>()).ok()
+}
+
+unsafe fn default_alloc(layout: Layout) -> *mut u8 {
+    #[cfg(any(feature = "use_rust_alloc", not(feature = "std")))]
+    { alloc::alloc::alloc(layout) }
+    #[cfg(not(any(feature = "use_rust_alloc", not(feature = "std"))))]
+    { std::alloc::GlobalAlloc::alloc(&std::alloc::System, layout) }
+}
+
+unsafe fn default_dealloc(ptr: *mut u8, layout: Layout) {
+    #[cfg(any(feature = "use_rust_alloc", not(feature = "std")))]
+    { alloc::alloc::dealloc(ptr, layout) }
+    #[cfg(not(any(feature = "use_rust_alloc", not(feature = "std"))))]
+    { std::alloc::GlobalAlloc::dealloc(&std::alloc::System, ptr, layout) }
+}
+
+/// Allocates a block of memory that fits `layout` with the current
+/// context's allocator (or the default allocator), counting it towards
+/// the context's usage and the process-wide total. Returns null if the
+/// allocation fails, would exceed the limit, or is only being probed.
+pub(crate) unsafe fn allocate(layout: Layout) -> *mut u8 {
+    let context = current_context();
+
+    if let Some(Context { usage, probe: true, .. }) = context {
+        (*usage).peak += layout.size();
+        return core::ptr::null_mut();
+    }
+    if !reserve(layout.size()) {
+        return core::ptr::null_mut();
+    }
+
+    let ptr = match context.and_then(|context| context.allocator) {
+        Some(allocator) => (*allocator).allocate(layout),
+        None => default_alloc(layout),
+    };
+    if ptr.is_null() {
+        release(layout.size());
+        return core::ptr::null_mut();
+    }
+    if let Some(context) = context {
+        let usage = &mut *context.usage;
+        usage.live += layout.size();
+        usage.peak = core::cmp::max(usage.peak, usage.live);
+    }
+    ptr
+}
+
+/// Deallocates a block of memory that was returned by `allocate`
+/// (with the same `layout`) in the same context.
+pub(crate) unsafe fn deallocate(ptr: *mut u8, layout: Layout) {
+    let context = current_context();
+
+    match context.and_then(|context| context.allocator) {
+        Some(allocator) => (*allocator).deallocate(ptr, layout),
+        None => default_dealloc(ptr, layout),
+    }
+    release(layout.size());
+    if let Some(context) = context {
+        let usage = &mut *context.usage;
+        usage.live = usage.live.saturating_sub(layout.size());
+    }
+}
+
+#[cfg(not(feature = "rust_backend"))]
 unsafe extern "C" fn malloc(size: usize) -> *mut c_void {
-    let size_and_data_ptr = alloc(layout_for(size));
+    let layout = match layout_for(size) {
+        Some(layout) => layout,
+        None => return core::ptr::null_mut(),
+    };
+    let size_and_data_ptr = allocate(layout);
+    if size_and_data_ptr.is_null() {
+        return core::ptr::null_mut();
+    }
+
     *(size_and_data_ptr as *mut usize) = size;
     size_and_data_ptr.add(size_of::<usize>()) as _
 }
 
+#[cfg(not(feature = "rust_backend"))]
 unsafe extern "C" fn free(data_ptr: *mut c_void) {
-    let size_and_data_ptr = data_ptr.sub(size_of::<usize>());
+    if data_ptr.is_null() {
+        return;
+    }
+    let size_and_data_ptr = data_ptr.sub(size_of::<usize>()) as *mut u8;
     let size = *(size_and_data_ptr as *const usize);
-    dealloc(size_and_data_ptr as _, layout_for(size))
+    // the layout was valid when the chunk was allocated
+    let layout = layout_for(size).unwrap();
+    deallocate(size_and_data_ptr, layout);
 }
 
+#[cfg(not(feature = "rust_backend"))]
 pub static OPTIONS: libdeflate_options = libdeflate_options {
     sizeof_options: size_of::<libdeflate_options>(),
     malloc_func: Some(malloc),
     free_func: Some(free),
-};
\ No newline at end of file
+};
@@ -0,0 +1,309 @@
+//! Parallel (`pigz`-style) compression of large buffers.
+//!
+//! A [`Compressor`] only uses one thread. [`ParallelCompressor`]
+//! splits its input into chunks, compresses each chunk with its own
+//! `Compressor` on its own thread, and stitches the resulting DEFLATE
+//! streams together into a single DEFLATE, zlib, or gzip stream that
+//! any decompressor (e.g. `gunzip`) can read.
+//!
+//! Every chunk except the last has its final block marked as
+//! non-final and is terminated by an empty stored block (the same
+//! thing that zlib's `Z_SYNC_FLUSH` emits), which byte-aligns it so
+//! that the next chunk can be appended directly.
+//!
+//! Because each chunk is compressed independently, matches cannot
+//! refer to data in previous chunks, so the output is slightly larger
+//! than single-threaded output. Larger chunks reduce this overhead.
+//!
+//! This module also contains [`crc32`] and [`adler32`], which
+//! checksum a single large buffer on multiple threads.
+//!
+//! With the `rayon` feature enabled, chunks are compressed on the
+//! current [rayon](https://docs.rs/rayon) thread pool instead of on
+//! newly-spawned threads (and likewise for checksums).
+//!
+//! [`Compressor`]: ../struct.Compressor.html
+//! [`ParallelCompressor`]: struct.ParallelCompressor.html
+//! [`crc32`]: fn.crc32.html
+//! [`adler32`]: fn.adler32.html
+
+#[cfg(not(feature = "rayon"))]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(feature = "rayon"))]
+use std::sync::Mutex;
+use std::thread;
+
+use crate::deflate_blocks;
+use crate::gzip::GzipHeader;
+use crate::{adler32_combine, backend, crc32_combine, CompressionLvl, Compressor};
+
+/// Default number of uncompressed bytes in each chunk (1 MiB).
+pub const DEFAULT_CHUNK_SIZE: usize = 1 << 20;
+
+/// Compresses data into a single DEFLATE, zlib, or gzip stream using
+/// multiple threads.
+///
+/// ```
+/// use libdeflater::CompressionLvl;
+/// use libdeflater::parallel::ParallelCompressor;
+///
+/// let data = vec![0u8; 4 << 20];
+/// let compressor = ParallelCompressor::new(CompressionLvl::best())
+///     .threads(4)
+///     .chunk_size(1 << 20);
+/// let gz_data = compressor.gzip_compress(&data);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ParallelCompressor {
+    lvl: CompressionLvl,
+    threads: usize,
+    chunk_size: usize,
+}
+
+impl Default for ParallelCompressor {
+    fn default() -> Self {
+        Self::new(CompressionLvl::default())
+    }
+}
+
+impl ParallelCompressor {
+    /// Returns a new `ParallelCompressor` that compresses data with
+    /// the supplied [`CompressionLvl`](../struct.CompressionLvl.html),
+    /// one thread per available CPU, and chunks of
+    /// [`DEFAULT_CHUNK_SIZE`](constant.DEFAULT_CHUNK_SIZE.html) bytes.
+    pub fn new(lvl: CompressionLvl) -> ParallelCompressor {
+        let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        ParallelCompressor { lvl, threads, chunk_size: DEFAULT_CHUNK_SIZE }
+    }
+
+    /// Sets the maximum number of threads that compress chunks. This
+    /// has no effect when the `rayon` feature is enabled, in which
+    /// case the size of the current rayon thread pool is used.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threads` is 0.
+    pub fn threads(mut self, threads: usize) -> ParallelCompressor {
+        assert!(threads > 0, "a ParallelCompressor needs at least one thread");
+        self.threads = threads;
+        self
+    }
+
+    /// Sets the number of uncompressed bytes in each chunk.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is 0.
+    pub fn chunk_size(mut self, chunk_size: usize) -> ParallelCompressor {
+        assert!(chunk_size > 0, "a ParallelCompressor's chunk size must be greater than 0");
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Compresses `in_raw_data` as
+    /// [`deflate`](https://tools.ietf.org/html/rfc1951) data.
+    pub fn deflate_compress(&self, in_raw_data: &[u8]) -> Vec<u8> {
+        let chunks = self.compress_chunks(in_raw_data, |_| 0);
+        let mut out = Vec::new();
+        for chunk in chunks {
+            out.extend_from_slice(&chunk.data);
+        }
+        out
+    }
+
+    /// Compresses `in_raw_data` as
+    /// [`zlib`](https://www.ietf.org/rfc/rfc1950.txt) data.
+    pub fn zlib_compress(&self, in_raw_data: &[u8]) -> Vec<u8> {
+        let chunks = self.compress_chunks(in_raw_data, crate::adler32);
+
+        let mut out = backend::zlib_header(self.lvl).to_vec();
+        let mut adler32 = 1;
+        for chunk in chunks {
+            out.extend_from_slice(&chunk.data);
+            adler32 = adler32_combine(adler32, chunk.checksum, chunk.len as u64);
+        }
+        out.extend_from_slice(&adler32.to_be_bytes());
+        out
+    }
+
+    /// Compresses `in_raw_data` as a single
+    /// [`gzip`](https://tools.ietf.org/html/rfc1952) member.
+    pub fn gzip_compress(&self, in_raw_data: &[u8]) -> Vec<u8> {
+        let chunks = self.compress_chunks(in_raw_data, crate::crc32);
+
+        let mut out = gzip_header(self.lvl).to_bytes();
+        let mut crc = 0;
+        for chunk in chunks {
+            out.extend_from_slice(&chunk.data);
+            crc = crc32_combine(crc, chunk.checksum, chunk.len as u64);
+        }
+        out.extend_from_slice(&crc.to_le_bytes());
+        out.extend_from_slice(&(in_raw_data.len() as u32).to_le_bytes());
+        out
+    }
+
+    /// Compresses and checksums `data` in chunks, in parallel.
+    /// Concatenating the returned chunks' data yields a single
+    /// DEFLATE stream.
+    #[cfg(not(feature = "rayon"))]
+    fn compress_chunks(&self, data: &[u8], checksum: fn(&[u8]) -> u32) -> Vec<CompressedChunk> {
+        let chunks = self.split(data);
+        let next_chunk = AtomicUsize::new(0);
+        let results: Vec<Mutex<Option<CompressedChunk>>> = chunks.iter().map(|_| Mutex::new(None)).collect();
+
+        thread::scope(|s| {
+            for _ in 0..std::cmp::min(self.threads, chunks.len()) {
+                s.spawn(|| {
+                    let mut compressor = Compressor::new(self.lvl);
+                    loop {
+                        let i = next_chunk.fetch_add(1, Ordering::Relaxed);
+                        if i >= chunks.len() {
+                            break;
+                        }
+                        let compressed = compress_chunk(&mut compressor, chunks[i], i == chunks.len() - 1, checksum);
+                        *results[i].lock().unwrap() = Some(compressed);
+                    }
+                });
+            }
+        });
+
+        results.into_iter().map(|r| r.into_inner().unwrap().unwrap()).collect()
+    }
+
+    /// Compresses and checksums `data` in chunks on the current rayon
+    /// thread pool. Concatenating the returned chunks' data yields a
+    /// single DEFLATE stream.
+    #[cfg(feature = "rayon")]
+    fn compress_chunks(&self, data: &[u8], checksum: fn(&[u8]) -> u32) -> Vec<CompressedChunk> {
+        use rayon::prelude::*;
+
+        let chunks = self.split(data);
+        let last = chunks.len() - 1;
+        chunks.par_iter()
+            .enumerate()
+            .map_init(|| Compressor::new(self.lvl), |compressor, (i, chunk)| {
+                compress_chunk(compressor, chunk, i == last, checksum)
+            })
+            .collect()
+    }
+
+    /// Splits `data` into chunks. Always returns at least one
+    /// (possibly empty) chunk, so that empty input still produces a
+    /// valid stream.
+    fn split<'a>(&self, data: &'a [u8]) -> Vec<&'a [u8]> {
+        if data.is_empty() {
+            vec![data]
+        } else {
+            data.chunks(self.chunk_size).collect()
+        }
+    }
+}
+
+/// Returns the CRC32 checksum of the bytes in `data`, computed on
+/// multiple threads.
+///
+/// `data` is split into one part per thread (parts are at least
+/// [`MIN_CHECKSUM_PART_SIZE`](constant.MIN_CHECKSUM_PART_SIZE.html)
+/// bytes) and the parts' checksums are merged with
+/// [`crc32_combine`](../fn.crc32_combine.html).
+pub fn crc32(data: &[u8]) -> u32 {
+    checksum(data, crate::crc32, crc32_combine, 0)
+}
+
+/// Returns the Adler32 checksum of the bytes in `data`, computed on
+/// multiple threads.
+///
+/// `data` is split into one part per thread (parts are at least
+/// [`MIN_CHECKSUM_PART_SIZE`](constant.MIN_CHECKSUM_PART_SIZE.html)
+/// bytes) and the parts' checksums are merged with
+/// [`adler32_combine`](../fn.adler32_combine.html).
+pub fn adler32(data: &[u8]) -> u32 {
+    checksum(data, crate::adler32, adler32_combine, 1)
+}
+
+/// Minimum number of bytes that [`crc32`](fn.crc32.html) and
+/// [`adler32`](fn.adler32.html) checksum on each thread. Smaller
+/// inputs are checksummed on the calling thread.
+pub const MIN_CHECKSUM_PART_SIZE: usize = 1 << 20;
+
+fn checksum(data: &[u8], sum: fn(&[u8]) -> u32, combine: fn(u32, u32, u64) -> u32, initial: u32) -> u32 {
+    let n_parts = std::cmp::min(num_threads(), data.len() / MIN_CHECKSUM_PART_SIZE);
+    if n_parts <= 1 {
+        return sum(data);
+    }
+    let part_size = data.len().div_ceil(n_parts);
+
+    #[cfg(not(feature = "rayon"))]
+    let sums: Vec<u32> = thread::scope(|s| {
+        let handles: Vec<_> = data.chunks(part_size)
+            .map(|part| s.spawn(move || sum(part)))
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    #[cfg(feature = "rayon")]
+    let sums: Vec<u32> = {
+        use rayon::prelude::*;
+        data.par_chunks(part_size).map(sum).collect()
+    };
+
+    data.chunks(part_size)
+        .zip(sums)
+        .fold(initial, |acc, (part, part_sum)| combine(acc, part_sum, part.len() as u64))
+}
+
+#[cfg(not(feature = "rayon"))]
+fn num_threads() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+#[cfg(feature = "rayon")]
+fn num_threads() -> usize {
+    rayon::current_num_threads()
+}
+
+/// A compressed chunk of a larger input, along with the checksum and
+/// length of its uncompressed data.
+struct CompressedChunk {
+    data: Vec<u8>,
+    checksum: u32,
+    len: usize,
+}
+
+/// Compresses `chunk` as DEFLATE data. If the chunk is not the last
+/// one, its final block is made non-final and an empty stored block
+/// is appended so that another DEFLATE stream can follow it.
+fn compress_chunk(compressor: &mut Compressor, chunk: &[u8], is_last: bool, checksum: fn(&[u8]) -> u32) -> CompressedChunk {
+    let mut out = Vec::new();
+    compressor.deflate_compress_append(chunk, &mut out)
+        .expect("failed to allocate an output buffer for a compressed chunk");
+
+    if !is_last {
+        make_non_final(&mut out);
+    }
+    CompressedChunk { data: out, checksum: checksum(chunk), len: chunk.len() }
+}
+
+fn make_non_final(stream: &mut Vec<u8>) {
+    let (final_block_start, end) = deflate_blocks::final_block_bounds(stream)
+        .expect("libdeflate produced an invalid DEFLATE stream");
+
+    // clear BFINAL
+    stream[final_block_start / 8] &= !(1 << (final_block_start % 8));
+
+    // drop anything after the last block (i.e. padding bits), then
+    // append an empty, non-final stored block: a 3-bit zero header,
+    // zero padding up to the next byte boundary, LEN = 0, NLEN = !0
+    stream.truncate(end.div_ceil(8));
+    if end % 8 != 0 {
+        let last = stream.len() - 1;
+        stream[last] &= (1 << (end % 8)) - 1;
+    }
+    stream.resize((end + 3).div_ceil(8), 0);
+    stream.extend_from_slice(&[0x00, 0x00, 0xff, 0xff]);
+}
+
+/// Returns the gzip header that `libdeflate` writes at `lvl`.
+fn gzip_header(lvl: CompressionLvl) -> GzipHeader {
+    GzipHeader { xfl: backend::gzip_xfl(lvl), ..GzipHeader::default() }
+}
@@ -0,0 +1,329 @@
+//! Thread-safe pools of reusable compressors and decompressors.
+//!
+//! Allocating a [`Compressor`] is relatively expensive (especially at
+//! the higher compression levels), and compressors and decompressors
+//! are `Send` but not `Sync`, so they can't simply be shared between
+//! threads. A [`CompressorPool`] or [`DecompressorPool`] can be shared
+//! between threads (e.g. in a `static` or an `Arc`) and lends out
+//! instances through RAII guards that return them to the pool when
+//! they are dropped.
+//!
+//! The pools' `*_to_vec` one-liners report a compressor or
+//! decompressor that could not be allocated as
+//! `CompressionError::AllocationFailed` or
+//! `DecompressionError::AllocationFailed` rather than panicking.
+//!
+//! ```
+//! use libdeflater::CompressionLvl;
+//! use libdeflater::pool::{CompressorPool, DecompressorPool};
+//!
+//! let compressors = CompressorPool::new();
+//! let decompressors = DecompressorPool::new();
+//!
+//! let gz_data = compressors.gzip_compress_to_vec(CompressionLvl::default(), b"hello, world!").unwrap();
+//! let data = decompressors.gzip_decompress_to_vec(&gz_data, 1 << 20).unwrap();
+//! assert_eq!(data, b"hello, world!");
+//! ```
+//!
+//! [`Compressor`]: ../struct.Compressor.html
+//! [`CompressorPool`]: struct.CompressorPool.html
+//! [`DecompressorPool`]: struct.DecompressorPool.html
+
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::{AllocError, Codec, CompressionError, CompressionLvl, CompressionResult, Compressor, DecompressionError, DecompressionResult, Decompressor};
+
+/// Returns the default maximum number of idle instances kept by a
+/// pool (per compression level, for a `CompressorPool`): one per
+/// available CPU.
+fn default_max_idle() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+/// A thread-safe pool of [`Compressor`](../struct.Compressor.html)s,
+/// keyed by [`CompressionLvl`](../struct.CompressionLvl.html).
+pub struct CompressorPool {
+    /// Idle compressors, indexed by compression level.
+    idle: Mutex<Vec<Vec<Compressor>>>,
+    max_idle: usize,
+}
+
+impl Default for CompressorPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CompressorPool {
+    /// Returns a new, empty `CompressorPool` that keeps up to one idle
+    /// compressor per available CPU for each compression level.
+    pub fn new() -> CompressorPool {
+        Self::with_max_idle(default_max_idle())
+    }
+
+    /// Returns a new, empty `CompressorPool` that keeps up to
+    /// `max_idle` idle compressors for each compression level.
+    /// Compressors that are returned to a pool that is already full
+    /// are dropped.
+    pub fn with_max_idle(max_idle: usize) -> CompressorPool {
+        let idle = CompressionLvl::iter().map(|_| Vec::new()).collect();
+        CompressorPool { idle: Mutex::new(idle), max_idle }
+    }
+
+    /// Lends out an idle compressor with the supplied
+    /// [`CompressionLvl`](../struct.CompressionLvl.html), or a new one
+    /// if there are none.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a new compressor could not be allocated. Use
+    /// [`try_get`](#method.try_get) to handle that case instead.
+    pub fn get(&self, lvl: CompressionLvl) -> PooledCompressor<'_> {
+        match self.try_get(lvl) {
+            Ok(compressor) => compressor,
+            Err(_) => panic!("libdeflate_alloc_compressor returned NULL: out of memory"),
+        }
+    }
+
+    /// Lends out an idle compressor with the supplied
+    /// [`CompressionLvl`](../struct.CompressionLvl.html), or a new one
+    /// if there are none. Returns an
+    /// [`AllocError`](../struct.AllocError.html) if a new compressor
+    /// could not be allocated.
+    pub fn try_get(&self, lvl: CompressionLvl) -> Result<PooledCompressor<'_>, AllocError> {
+        let idle = self.idle.lock().unwrap()[lvl_index(lvl)].pop();
+        let compressor = match idle {
+            Some(compressor) => compressor,
+            None => Compressor::try_new(lvl)?,
+        };
+        Ok(PooledCompressor { pool: self, lvl, compressor: Some(compressor) })
+    }
+
+    /// Returns the number of idle compressors in the pool, across all
+    /// compression levels.
+    pub fn idle(&self) -> usize {
+        self.idle.lock().unwrap().iter().map(Vec::len).sum()
+    }
+
+    /// Drops every idle compressor in the pool.
+    pub fn clear(&self) {
+        self.idle.lock().unwrap().iter_mut().for_each(Vec::clear);
+    }
+
+    /// Compresses `in_raw_data` as DEFLATE data with a pooled
+    /// compressor. See
+    /// [`Compressor::deflate_compress_to_vec`](../struct.Compressor.html#method.deflate_compress_to_vec).
+    pub fn deflate_compress_to_vec(&self, lvl: CompressionLvl, in_raw_data: &[u8]) -> CompressionResult<Vec<u8>> {
+        self.try_get(lvl).map_err(|_| CompressionError::AllocationFailed)?.deflate_compress_to_vec(in_raw_data)
+    }
+
+    /// Compresses `in_raw_data` as zlib data with a pooled
+    /// compressor. See
+    /// [`Compressor::zlib_compress_to_vec`](../struct.Compressor.html#method.zlib_compress_to_vec).
+    pub fn zlib_compress_to_vec(&self, lvl: CompressionLvl, in_raw_data: &[u8]) -> CompressionResult<Vec<u8>> {
+        self.try_get(lvl).map_err(|_| CompressionError::AllocationFailed)?.zlib_compress_to_vec(in_raw_data)
+    }
+
+    /// Compresses `in_raw_data` as gzip data with a pooled
+    /// compressor. See
+    /// [`Compressor::gzip_compress_to_vec`](../struct.Compressor.html#method.gzip_compress_to_vec).
+    pub fn gzip_compress_to_vec(&self, lvl: CompressionLvl, in_raw_data: &[u8]) -> CompressionResult<Vec<u8>> {
+        self.try_get(lvl).map_err(|_| CompressionError::AllocationFailed)?.gzip_compress_to_vec(in_raw_data)
+    }
+
+    /// Compresses `in_raw_data` with `codec` and a pooled compressor.
+    /// See
+    /// [`Compressor::compress_to_vec`](../struct.Compressor.html#method.compress_to_vec).
+    pub fn compress_to_vec<C: Codec + ?Sized>(&self, codec: &C, lvl: CompressionLvl, in_raw_data: &[u8]) -> CompressionResult<Vec<u8>> {
+        self.try_get(lvl).map_err(|_| CompressionError::AllocationFailed)?.compress_to_vec(codec, in_raw_data)
+    }
+
+    fn put(&self, lvl: CompressionLvl, compressor: Compressor) {
+        let mut idle = self.idle.lock().unwrap();
+        let idle = &mut idle[lvl_index(lvl)];
+        if idle.len() < self.max_idle {
+            idle.push(compressor);
+        }
+    }
+}
+
+fn lvl_index(lvl: CompressionLvl) -> usize {
+    i32::from(lvl) as usize
+}
+
+/// A [`Compressor`](../struct.Compressor.html) lent out by a
+/// [`CompressorPool`](struct.CompressorPool.html), which is returned
+/// to the pool when this guard is dropped.
+pub struct PooledCompressor<'a> {
+    pool: &'a CompressorPool,
+    lvl: CompressionLvl,
+    compressor: Option<Compressor>,
+}
+
+impl PooledCompressor<'_> {
+    /// Returns the compressor's compression level.
+    pub fn lvl(&self) -> CompressionLvl {
+        self.lvl
+    }
+
+    /// Takes the compressor out of the pool for good.
+    pub fn detach(mut self) -> Compressor {
+        self.compressor.take().unwrap()
+    }
+}
+
+impl Deref for PooledCompressor<'_> {
+    type Target = Compressor;
+
+    fn deref(&self) -> &Compressor {
+        self.compressor.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledCompressor<'_> {
+    fn deref_mut(&mut self) -> &mut Compressor {
+        self.compressor.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledCompressor<'_> {
+    fn drop(&mut self) {
+        if let Some(compressor) = self.compressor.take() {
+            self.pool.put(self.lvl, compressor);
+        }
+    }
+}
+
+/// A thread-safe pool of
+/// [`Decompressor`](../struct.Decompressor.html)s.
+pub struct DecompressorPool {
+    idle: Mutex<Vec<Decompressor>>,
+    max_idle: usize,
+}
+
+impl Default for DecompressorPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DecompressorPool {
+    /// Returns a new, empty `DecompressorPool` that keeps up to one
+    /// idle decompressor per available CPU.
+    pub fn new() -> DecompressorPool {
+        Self::with_max_idle(default_max_idle())
+    }
+
+    /// Returns a new, empty `DecompressorPool` that keeps up to
+    /// `max_idle` idle decompressors. Decompressors that are returned
+    /// to a pool that is already full are dropped.
+    pub fn with_max_idle(max_idle: usize) -> DecompressorPool {
+        DecompressorPool { idle: Mutex::new(Vec::new()), max_idle }
+    }
+
+    /// Lends out an idle decompressor, or a new one if there are none.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a new decompressor could not be allocated. Use
+    /// [`try_get`](#method.try_get) to handle that case instead.
+    pub fn get(&self) -> PooledDecompressor<'_> {
+        match self.try_get() {
+            Ok(decompressor) => decompressor,
+            Err(_) => panic!("libdeflate_alloc_decompressor returned NULL: out of memory"),
+        }
+    }
+
+    /// Lends out an idle decompressor, or a new one if there are none.
+    /// Returns an [`AllocError`](../struct.AllocError.html) if a new
+    /// decompressor could not be allocated.
+    pub fn try_get(&self) -> Result<PooledDecompressor<'_>, AllocError> {
+        let idle = self.idle.lock().unwrap().pop();
+        let decompressor = match idle {
+            Some(decompressor) => decompressor,
+            None => Decompressor::try_new()?,
+        };
+        Ok(PooledDecompressor { pool: self, decompressor: Some(decompressor) })
+    }
+
+    /// Returns the number of idle decompressors in the pool.
+    pub fn idle(&self) -> usize {
+        self.idle.lock().unwrap().len()
+    }
+
+    /// Drops every idle decompressor in the pool.
+    pub fn clear(&self) {
+        self.idle.lock().unwrap().clear();
+    }
+
+    /// Decompresses `deflate_data` with a pooled decompressor. See
+    /// [`Decompressor::deflate_decompress_to_vec`](../struct.Decompressor.html#method.deflate_decompress_to_vec).
+    pub fn deflate_decompress_to_vec(&self, deflate_data: &[u8], max_out_len: usize) -> DecompressionResult<Vec<u8>> {
+        self.try_get().map_err(|_| DecompressionError::AllocationFailed)?.deflate_decompress_to_vec(deflate_data, max_out_len)
+    }
+
+    /// Decompresses `zlib_data` with a pooled decompressor. See
+    /// [`Decompressor::zlib_decompress_to_vec`](../struct.Decompressor.html#method.zlib_decompress_to_vec).
+    pub fn zlib_decompress_to_vec(&self, zlib_data: &[u8], max_out_len: usize) -> DecompressionResult<Vec<u8>> {
+        self.try_get().map_err(|_| DecompressionError::AllocationFailed)?.zlib_decompress_to_vec(zlib_data, max_out_len)
+    }
+
+    /// Decompresses `gz_data` with a pooled decompressor. See
+    /// [`Decompressor::gzip_decompress_to_vec`](../struct.Decompressor.html#method.gzip_decompress_to_vec).
+    pub fn gzip_decompress_to_vec(&self, gz_data: &[u8], max_out_len: usize) -> DecompressionResult<Vec<u8>> {
+        self.try_get().map_err(|_| DecompressionError::AllocationFailed)?.gzip_decompress_to_vec(gz_data, max_out_len)
+    }
+
+    /// Decompresses `in_data` with `codec` and a pooled decompressor.
+    /// See
+    /// [`Decompressor::decompress_to_vec`](../struct.Decompressor.html#method.decompress_to_vec).
+    pub fn decompress_to_vec<C: Codec + ?Sized>(&self, codec: &C, in_data: &[u8], max_out_len: usize) -> DecompressionResult<Vec<u8>> {
+        self.try_get().map_err(|_| DecompressionError::AllocationFailed)?.decompress_to_vec(codec, in_data, max_out_len)
+    }
+
+    fn put(&self, decompressor: Decompressor) {
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() < self.max_idle {
+            idle.push(decompressor);
+        }
+    }
+}
+
+/// A [`Decompressor`](../struct.Decompressor.html) lent out by a
+/// [`DecompressorPool`](struct.DecompressorPool.html), which is
+/// returned to the pool when this guard is dropped.
+pub struct PooledDecompressor<'a> {
+    pool: &'a DecompressorPool,
+    decompressor: Option<Decompressor>,
+}
+
+impl PooledDecompressor<'_> {
+    /// Takes the decompressor out of the pool for good.
+    pub fn detach(mut self) -> Decompressor {
+        self.decompressor.take().unwrap()
+    }
+}
+
+impl Deref for PooledDecompressor<'_> {
+    type Target = Decompressor;
+
+    fn deref(&self) -> &Decompressor {
+        self.decompressor.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledDecompressor<'_> {
+    fn deref_mut(&mut self) -> &mut Decompressor {
+        self.decompressor.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledDecompressor<'_> {
+    fn drop(&mut self) {
+        if let Some(decompressor) = self.decompressor.take() {
+            self.pool.put(decompressor);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+extern crate libdeflater;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use libdeflater::{CompressionLvl, Compressor};
+
+// The `libdeflater-bench` binary (the `cli` feature) is tested by
+// running it on a scratch directory of fixtures.
+
+
+
+// helpers
+
+/// Returns a new scratch directory holding copies of the fixtures,
+/// one of them in a subdirectory.
+fn sample_dir() -> PathBuf {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    let dir = std::env::temp_dir().join(format!("libdeflater-bench-{}-{}", std::process::id(), id));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("nested")).unwrap();
+    fs::copy("tests/hello", dir.join("hello")).unwrap();
+    fs::copy("src/lib.rs", dir.join("nested").join("lib.rs")).unwrap();
+    dir
+}
+
+fn bench(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_libdeflater-bench"))
+        .args(args)
+        .arg(dir)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+fn samples_len() -> u64 {
+    fs::metadata("tests/hello").unwrap().len() + fs::metadata("src/lib.rs").unwrap().len()
+}
+
+
+
+// CSV output
+
+#[test]
+fn test_csv_output_has_a_row_per_format_and_level() {
+    let output = bench(&sample_dir(), &["-n", "1"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next().unwrap(),
+               "format,level,input_bytes,compressed_bytes,ratio,compress_mb_s,decompress_mb_s,compressor_memory");
+
+    let rows: Vec<Vec<String>> = lines.map(|l| l.split(',').map(String::from).collect()).collect();
+    assert_eq!(rows.len(), 3 * CompressionLvl::iter().count());
+    for (row, lvl) in rows.iter().zip(CompressionLvl::iter()) {
+        assert_eq!(row[0], "gzip");
+        assert_eq!(row[1], i32::from(lvl).to_string());
+        assert_eq!(row[2], samples_len().to_string());
+        assert_eq!(row[7], Compressor::estimated_memory(lvl).to_string());
+    }
+    assert!(rows.iter().any(|row| row[0] == "zlib"));
+    assert!(rows.iter().any(|row| row[0] == "deflate"));
+}
+
+#[test]
+fn test_compressed_sizes_match_compressor_output() {
+    let output = bench(&sample_dir(), &["-n", "1", "--formats=zlib"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let hello = fs::read("tests/hello").unwrap();
+    let lib_rs = fs::read("src/lib.rs").unwrap();
+    for (line, lvl) in stdout(&output).lines().skip(1).zip(CompressionLvl::iter()) {
+        let row: Vec<&str> = line.split(',').collect();
+        let mut compressor = Compressor::new(lvl);
+        let expected = compressor.zlib_compress_to_vec(&hello).unwrap().len()
+            + compressor.zlib_compress_to_vec(&lib_rs).unwrap().len();
+
+        assert_eq!(row[0], "zlib");
+        assert_eq!(row[3], expected.to_string());
+    }
+}
+
+#[test]
+fn test_csv_recommendation_is_printed_to_stderr() {
+    let output = bench(&sample_dir(), &["-n", "1", "-f", "gzip", "--target-ratio", "1.5"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).starts_with("recommended gzip level: "));
+}
+
+#[test]
+fn test_unreachable_target_recommends_nothing() {
+    let output = bench(&sample_dir(), &["-n", "1", "-f", "gzip", "--target-ratio", "1000"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("recommended gzip level: none"));
+}
+
+
+
+// JSON output
+
+#[test]
+fn test_json_output_has_results_and_recommendations() {
+    let output = bench(&sample_dir(), &["-n", "1", "-o", "json", "-f", "gzip,deflate", "--target-throughput", "0.001"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.starts_with("{\n"));
+    assert!(stdout.trim_end().ends_with('}'));
+    assert!(stdout.contains("\"files\": 2,"));
+    assert!(stdout.contains("\"target_throughput\": 0.001,"));
+    assert!(stdout.contains("\"target_ratio\": null,"));
+    assert_eq!(stdout.matches("\"format\": \"gzip\"").count(), CompressionLvl::iter().count() + 1);
+    assert_eq!(stdout.matches("\"format\": \"deflate\"").count(), CompressionLvl::iter().count() + 1);
+    assert!(!stdout.contains("inf") && !stdout.contains("NaN"));
+}
+
+#[test]
+fn test_throughput_target_recommends_best_ratio() {
+    // every level meets a tiny throughput target, so the level with
+    // the best ratio is recommended
+    let output = bench(&sample_dir(), &["-n", "1", "-f", "gzip", "--target-throughput=0.000001"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let best_size = stdout(&output).lines().skip(1)
+        .map(|line| line.split(',').nth(3).unwrap().parse::<u64>().unwrap())
+        .min()
+        .unwrap();
+    let recommended_lvl: i32 = stderr(&output)
+        .trim_start_matches("recommended gzip level: ")
+        .split(' ')
+        .next()
+        .unwrap()
+        .parse()
+        .unwrap();
+    let row = stdout(&output).lines().skip(1)
+        .find(|line| line.split(',').nth(1).unwrap() == recommended_lvl.to_string())
+        .unwrap()
+        .to_string();
+
+    assert_eq!(row.split(',').nth(3).unwrap(), best_size.to_string());
+}
+
+
+
+// usage
+
+#[test]
+fn test_invalid_arguments_are_rejected() {
+    let dir = sample_dir();
+
+    for args in &[&["-o", "xml"][..], &["-f", "brotli"][..], &["-n", "0"][..], &["--target-ratio", "-1"][..], &["--bogus"][..]] {
+        let output = bench(&dir, args);
+        assert_eq!(output.status.code(), Some(1), "{:?}", args);
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_libdeflater-bench")).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("missing directory operand"));
+}
+
+#[test]
+fn test_empty_directory_is_rejected() {
+    let dir = sample_dir();
+    fs::remove_dir_all(&dir).unwrap();
+    fs::create_dir_all(&dir).unwrap();
+
+    let output = bench(&dir, &[]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("no files to measure"));
+}
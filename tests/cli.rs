@@ -0,0 +1,352 @@
+extern crate libdeflater;
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use libdeflater::{CompressionLvl, Compressor, Decompressor};
+
+// The `libdeflater` binary (the `cli` feature) is tested by running it
+// on copies of the fixtures in a scratch directory per test.
+
+
+
+// helpers
+
+fn read_fixture_content() -> Vec<u8> {
+    fs::read("tests/hello").unwrap()
+}
+
+/// Returns a new, empty scratch directory.
+fn scratch_dir() -> PathBuf {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    let dir = std::env::temp_dir().join(format!("libdeflater-cli-{}-{}", std::process::id(), id));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn libdeflater(dir: &Path, args: &[&str]) -> Output {
+    libdeflater_with_stdin(dir, args, &[])
+}
+
+fn libdeflater_with_stdin(dir: &Path, args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_libdeflater"))
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+
+
+// compression
+
+#[test]
+fn test_compresses_file_in_place() {
+    let dir = scratch_dir();
+    fs::write(dir.join("hello"), read_fixture_content()).unwrap();
+
+    let output = libdeflater(&dir, &["hello"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!dir.join("hello").exists());
+    let gz_data = fs::read(dir.join("hello.gz")).unwrap();
+    assert_eq!(gz_data, Compressor::new(CompressionLvl::default()).gzip_compress_to_vec(&read_fixture_content()).unwrap());
+}
+
+#[test]
+fn test_keep_flag_keeps_input_file() {
+    let dir = scratch_dir();
+    fs::write(dir.join("hello"), read_fixture_content()).unwrap();
+
+    let output = libdeflater(&dir, &["-k", "hello"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(dir.join("hello").exists());
+    assert!(dir.join("hello.gz").exists());
+}
+
+#[test]
+fn test_level_flags_select_compression_level() {
+    let dir = scratch_dir();
+    let in_data = read_fixture_content();
+
+    for lvl in &["-1", "-9", "-12", "--fast", "--best"] {
+        let output = libdeflater_with_stdin(&dir, &[lvl], &in_data);
+        let expected_lvl = match *lvl {
+            "--fast" => 1,
+            "--best" => 12,
+            lvl => lvl[1..].parse().unwrap(),
+        };
+        let expected = Compressor::new(CompressionLvl::new(expected_lvl).unwrap()).gzip_compress_to_vec(&in_data).unwrap();
+
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert_eq!(output.stdout, expected, "{}", lvl);
+    }
+}
+
+#[test]
+fn test_invalid_level_is_rejected() {
+    let output = libdeflater_with_stdin(&scratch_dir(), &["-13"], b"hello");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("invalid compression level"));
+}
+
+#[test]
+fn test_flags_can_be_combined() {
+    let dir = scratch_dir();
+    fs::write(dir.join("hello"), read_fixture_content()).unwrap();
+
+    let output = libdeflater(&dir, &["-c9", "hello"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(dir.join("hello").exists());
+    assert!(!dir.join("hello.gz").exists());
+    assert_eq!(output.stdout, Compressor::new(CompressionLvl::new(9).unwrap()).gzip_compress_to_vec(&read_fixture_content()).unwrap());
+}
+
+#[test]
+fn test_zlib_and_raw_flags_select_format_and_suffix() {
+    let dir = scratch_dir();
+    let in_data = read_fixture_content();
+    fs::write(dir.join("a"), &in_data).unwrap();
+    fs::write(dir.join("b"), &in_data).unwrap();
+
+    assert!(libdeflater(&dir, &["--zlib", "a"]).status.success());
+    assert!(libdeflater(&dir, &["--raw", "b"]).status.success());
+
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    assert_eq!(fs::read(dir.join("a.zz")).unwrap(), compressor.zlib_compress_to_vec(&in_data).unwrap());
+    assert_eq!(fs::read(dir.join("b.deflate")).unwrap(), compressor.deflate_compress_to_vec(&in_data).unwrap());
+}
+
+#[test]
+fn test_file_with_suffix_is_not_compressed_again() {
+    let dir = scratch_dir();
+    fs::write(dir.join("hello.gz"), b"data").unwrap();
+
+    let output = libdeflater(&dir, &["hello.gz"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("already has .gz suffix"));
+    assert_eq!(fs::read(dir.join("hello.gz")).unwrap(), b"data");
+}
+
+#[test]
+fn test_existing_output_is_only_overwritten_with_force() {
+    let dir = scratch_dir();
+    fs::write(dir.join("hello"), read_fixture_content()).unwrap();
+    fs::write(dir.join("hello.gz"), b"existing").unwrap();
+
+    let output = libdeflater(&dir, &["hello"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("already exists"));
+    assert_eq!(fs::read(dir.join("hello.gz")).unwrap(), b"existing");
+    assert!(dir.join("hello").exists());
+
+    let output = libdeflater(&dir, &["-f", "hello"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_ne!(fs::read(dir.join("hello.gz")).unwrap(), b"existing");
+    assert!(!dir.join("hello").exists());
+}
+
+
+
+// decompression
+
+#[test]
+fn test_decompresses_file_in_place() {
+    let dir = scratch_dir();
+    fs::copy("tests/hello.gz", dir.join("hello.gz")).unwrap();
+
+    let output = libdeflater(&dir, &["-d", "hello.gz"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!dir.join("hello.gz").exists());
+    assert_eq!(fs::read(dir.join("hello")).unwrap(), read_fixture_content());
+}
+
+#[test]
+fn test_decompresses_stdin_to_stdout() {
+    let gz_data = fs::read("tests/hello.gz").unwrap();
+
+    let output = libdeflater_with_stdin(&scratch_dir(), &["-d"], &gz_data);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(output.stdout, read_fixture_content());
+}
+
+#[test]
+fn test_decompresses_multi_member_input() {
+    let mut gz_data = fs::read("tests/hello.gz").unwrap();
+    gz_data.extend(fs::read("tests/hello.gz").unwrap());
+    let mut expected = read_fixture_content();
+    expected.extend(read_fixture_content());
+
+    let output = libdeflater_with_stdin(&scratch_dir(), &["-dc", "-"], &gz_data);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(output.stdout, expected);
+}
+
+#[test]
+fn test_decompresses_zlib_and_raw_data_with_custom_suffix() {
+    let dir = scratch_dir();
+    fs::copy("tests/hello.zz", dir.join("a.zz")).unwrap();
+    fs::copy("tests/hello.deflate", dir.join("b.z")).unwrap();
+
+    assert!(libdeflater(&dir, &["-d", "--zlib", "a.zz"]).status.success());
+    assert!(libdeflater(&dir, &["-d", "--raw", "-S", ".z", "b.z"]).status.success());
+
+    assert_eq!(fs::read(dir.join("a")).unwrap(), read_fixture_content());
+    assert_eq!(fs::read(dir.join("b")).unwrap(), read_fixture_content());
+}
+
+#[test]
+fn test_custom_suffix_roundtrips() {
+    let dir = scratch_dir();
+    fs::write(dir.join("hello"), read_fixture_content()).unwrap();
+
+    assert!(libdeflater(&dir, &["--suffix=.libd", "hello"]).status.success());
+    assert!(dir.join("hello.libd").exists());
+    assert!(libdeflater(&dir, &["-dS.libd", "hello.libd"]).status.success());
+    assert_eq!(fs::read(dir.join("hello")).unwrap(), read_fixture_content());
+}
+
+#[test]
+fn test_file_with_unknown_suffix_is_not_decompressed() {
+    let dir = scratch_dir();
+    fs::copy("tests/hello.gz", dir.join("hello.gzip")).unwrap();
+
+    let output = libdeflater(&dir, &["-d", "hello.gzip"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("unknown suffix"));
+    assert!(dir.join("hello.gzip").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_decompresses_file_whose_name_is_not_unicode() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = scratch_dir();
+    let name = OsStr::from_bytes(b"hello\xff.gz");
+    fs::copy("tests/hello.gz", dir.join(name)).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_libdeflater"))
+        .arg("-d")
+        .arg(name)
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(fs::read(dir.join(OsStr::from_bytes(b"hello\xff"))).unwrap(), read_fixture_content());
+}
+
+#[test]
+fn test_corrupt_data_fails_and_keeps_input() {
+    let dir = scratch_dir();
+    let mut gz_data = fs::read("tests/hello.gz").unwrap();
+    let n = gz_data.len();
+    gz_data[n - 8] ^= 0xff;
+    fs::write(dir.join("hello.gz"), &gz_data).unwrap();
+
+    let output = libdeflater(&dir, &["-d", "hello.gz"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(dir.join("hello.gz").exists());
+    assert!(!dir.join("hello").exists());
+}
+
+#[test]
+fn test_compress_then_decompress_roundtrips_data_at_every_level() {
+    let in_data = read_fixture_content();
+    let dir = scratch_dir();
+
+    for lvl in 1..=12 {
+        let compressed = libdeflater_with_stdin(&dir, &[&format!("-{}", lvl)], &in_data).stdout;
+        let output = libdeflater_with_stdin(&dir, &["-d"], &compressed);
+
+        assert_eq!(output.stdout, in_data, "lvl = {}", lvl);
+        assert_eq!(Decompressor::new().gzip_decompress_to_vec(&compressed, 1 << 20).unwrap(), in_data);
+    }
+}
+
+
+
+// testing and listing
+
+#[test]
+fn test_test_flag_accepts_valid_data_and_rejects_corrupt_data() {
+    let dir = scratch_dir();
+    fs::copy("tests/hello.gz", dir.join("good.gz")).unwrap();
+    fs::write(dir.join("bad.gz"), b"not gzip data").unwrap();
+
+    let output = libdeflater(&dir, &["-t", "good.gz"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(output.stdout.is_empty());
+
+    let output = libdeflater(&dir, &["-t", "good.gz", "bad.gz"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("bad.gz"));
+    assert!(dir.join("good.gz").exists());
+}
+
+#[test]
+fn test_list_flag_prints_sizes() {
+    let dir = scratch_dir();
+    fs::copy("tests/hello.gz", dir.join("hello.gz")).unwrap();
+    let compressed_len = fs::metadata(dir.join("hello.gz")).unwrap().len();
+    let decompressed_len = read_fixture_content().len();
+
+    let output = libdeflater(&dir, &["-l", "hello.gz"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines = stdout.lines();
+    assert!(lines.next().unwrap().contains("uncompressed_name"));
+    let fields: Vec<&str> = lines.next().unwrap().split_whitespace().collect();
+    assert_eq!(fields[0], compressed_len.to_string());
+    assert_eq!(fields[1], decompressed_len.to_string());
+    assert_eq!(fields[3], "hello");
+    assert_eq!(lines.next(), None);
+}
+
+
+
+// usage
+
+#[test]
+fn test_unknown_option_is_rejected() {
+    let output = libdeflater(&scratch_dir(), &["--bogus"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("unrecognized option '--bogus'"));
+}
+
+#[test]
+fn test_help_and_version_flags() {
+    let output = libdeflater(&scratch_dir(), &["--help"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap().starts_with("Usage: libdeflater"));
+
+    let output = libdeflater(&scratch_dir(), &["-V"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap().trim(), format!("libdeflater {}", env!("CARGO_PKG_VERSION")));
+}
@@ -2,10 +2,20 @@ extern crate libdeflater;
 
 use std::fs::File;
 use std::io::Read;
+use std::mem::MaybeUninit;
+use std::alloc::{Layout, System};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::vec::Vec;
 use std::error::Error;
 use std::thread;
-use libdeflater::{Compressor, CompressionLvl, CompressionError, Decompressor, DecompressionError, CompressionLvlError};
+use libdeflater::{AllocError, Allocator, Codec, CompressionResult, Compressor, CompressionLvl, CompressionError, Decompressor, DecompressionError, DecompressionResult, CompressionLvlError, DecompressOutcome, GzipMember, Format, detect_format};
+use libdeflater::gzip::{self, GzipHeader};
+use libdeflater::bgzf;
+use libdeflater::container::{self, Framing, GzipFraming, ZlibFraming};
+use libdeflater::parallel::{self, ParallelCompressor};
+use libdeflater::pool::{CompressorPool, DecompressorPool};
+#[allow(clippy::single_component_path_imports)]
 use flate2;
 
 
@@ -17,6 +27,11 @@ fn test_can_create_new_decompressor() {
     Decompressor::new();
 }
 
+#[test]
+fn test_can_try_new_decompressor() {
+    Decompressor::try_new().unwrap();
+}
+
 fn read_fixture(pth: &str) -> Vec<u8> {
     let mut f = File::open(pth).unwrap();
     let mut content = Vec::new();
@@ -45,6 +60,7 @@ fn read_fixture_gz_with_bad_magic_num() -> Vec<u8> {
     data
 }
 
+#[allow(clippy::assign_op_pattern)]
 fn read_fixture_gz_with_bad_crc32() -> Vec<u8> {
     let mut data = read_fixture_gz();
     let crc32_start = data.len()-8;
@@ -55,6 +71,7 @@ fn read_fixture_gz_with_bad_crc32() -> Vec<u8> {
     data
 }
 
+#[allow(clippy::assign_op_pattern)]
 fn read_fixture_gz_with_bad_isize() -> Vec<u8> {
     let mut data = read_fixture_gz();
     let isize_start = data.len()-4;
@@ -81,6 +98,7 @@ fn read_fixture_zlib_with_bad_cmf_field() -> Vec<u8> {
     data
 }
 
+#[allow(clippy::assign_op_pattern)]
 fn read_fixture_zlib_with_bad_adler32_checksum() -> Vec<u8> {
     let mut data = read_fixture_zlib();
     let adler32_start = data.len() - 4;
@@ -95,12 +113,25 @@ fn read_fixture_deflate() -> Vec<u8> {
 }
 
 #[test]
+#[allow(unused_parens)]
 fn test_decompression_error_derives_error() {
     let bd = DecompressionError::BadData;
     let _e = (&bd) as &(dyn Error);
 }
 
 #[test]
+fn test_decompression_error_converts_into_io_error_that_wraps_it() {
+    let err = std::io::Error::from(DecompressionError::CrcMismatch { expected: 1, actual: 2 });
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(err.get_ref().unwrap().downcast_ref::<DecompressionError>(),
+               Some(&DecompressionError::CrcMismatch { expected: 1, actual: 2 }));
+
+    let err = std::io::Error::from(DecompressionError::Truncated);
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+#[allow(clippy::slow_vector_initialization)]
 fn test_can_send_decompressor_to_another_thread() {
     // note: this is a compile-time test: it just ensures that a
     // `Decompressor` can be sent between threads easily (i.e. that
@@ -138,6 +169,7 @@ fn test_can_send_compressor_to_another_thread() {
 // gz decompression
 
 #[test]
+#[allow(clippy::slow_vector_initialization)]
 fn test_calling_gzip_decompress_with_valid_args_works() {
     let mut decompressor = Decompressor::new();
     let content = read_fixture_gz();
@@ -160,6 +192,7 @@ fn test_calling_gzip_decompress_with_undersized_outbuf_returns_insufficient_spac
 }
 
 #[test]
+#[allow(clippy::slow_vector_initialization)]
 fn test_calling_gzip_decompress_with_valid_buf_fills_buf_with_expected_content() {
     let decompressed = {
         let mut decompressor = Decompressor::new();
@@ -176,6 +209,7 @@ fn test_calling_gzip_decompress_with_valid_buf_fills_buf_with_expected_content()
 }
 
 #[test]
+#[allow(clippy::let_and_return, clippy::slow_vector_initialization)]
 fn test_calling_gzip_decompress_with_oversized_buf_returns_correct_size() {
     const OVERSIZED_FACTOR: usize = 2;
 
@@ -192,42 +226,82 @@ fn test_calling_gzip_decompress_with_oversized_buf_returns_correct_size() {
 }
 
 #[test]
-fn test_calling_gzip_decompress_with_bad_magic_num_returns_bad_data() {
+#[allow(clippy::slow_vector_initialization)]
+fn test_calling_gzip_decompress_with_bad_magic_num_returns_bad_magic() {
     let mut decompressor = Decompressor::new();
     let content = read_fixture_gz_with_bad_magic_num();
     let mut decompressed = Vec::new();
     decompressed.resize(fixture_content_size(), 0);
     let result = decompressor.gzip_decompress(&content, &mut decompressed);
 
-    assert_eq!(result.unwrap_err(), DecompressionError::BadData);
+    assert_eq!(result.unwrap_err(), DecompressionError::BadMagic);
 }
 
 #[test]
-fn test_calling_gzip_decompress_with_corrupted_crc32_returns_bad_data() {
+#[allow(clippy::slow_vector_initialization)]
+fn test_calling_gzip_decompress_with_corrupted_crc32_returns_crc_mismatch() {
     let mut decompressor = Decompressor::new();
     let content = read_fixture_gz_with_bad_crc32();
     let mut decompressed = Vec::new();
     decompressed.resize(fixture_content_size(), 0);
     let result = decompressor.gzip_decompress(&content, &mut decompressed);
 
-    assert_eq!(result.unwrap_err(), DecompressionError::BadData);
+    assert_eq!(result.unwrap_err(), DecompressionError::CrcMismatch { expected: 909783073, actual: 909783072 });
 }
 
 #[test]
-fn test_calling_gzip_decompress_with_corrupted_isize_returns_bad_data() {
+#[allow(clippy::slow_vector_initialization)]
+fn test_calling_gzip_decompress_with_corrupted_isize_returns_size_mismatch() {
     let mut decompressor = Decompressor::new();
     let content = read_fixture_gz_with_bad_isize();
     let mut decompressed = Vec::new();
     decompressed.resize(fixture_content_size(), 0);
     let result = decompressor.gzip_decompress(&content, &mut decompressed);
 
-    assert_eq!(result.unwrap_err(), DecompressionError::BadData);
+    assert_eq!(result.unwrap_err(), DecompressionError::SizeMismatch { expected: 7, actual: 6 });
+}
+
+#[test]
+fn test_calling_gzip_decompress_with_unsupported_method_returns_unsupported_method() {
+    let mut decompressor = Decompressor::new();
+    let mut content = read_fixture_gz();
+    content[2] = 7;
+    let mut decompressed = vec![0; fixture_content_size()];
+    let ret = decompressor.gzip_decompress(&content, &mut decompressed);
+
+    assert_eq!(ret.unwrap_err(), DecompressionError::UnsupportedMethod(7));
+}
+
+#[test]
+fn test_calling_gzip_decompress_with_reserved_flags_returns_reserved_flags() {
+    let mut decompressor = Decompressor::new();
+    let mut content = read_fixture_gz();
+    content[3] |= 0x80;
+    let flg = content[3];
+    let mut decompressed = vec![0; fixture_content_size()];
+    let ret = decompressor.gzip_decompress(&content, &mut decompressed);
+
+    assert_eq!(ret.unwrap_err(), DecompressionError::ReservedFlags(flg));
+}
+
+#[test]
+fn test_calling_gzip_decompress_with_truncated_header_or_trailer_returns_truncated() {
+    let mut decompressor = Decompressor::new();
+    let content = read_fixture_gz();
+    let mut decompressed = vec![0; fixture_content_size()];
+
+    let ret = decompressor.gzip_decompress(&content[..5], &mut decompressed);
+    assert_eq!(ret.unwrap_err(), DecompressionError::Truncated);
+
+    let ret = decompressor.gzip_decompress(&content[..content.len() - 3], &mut decompressed);
+    assert_eq!(ret.unwrap_err(), DecompressionError::Truncated);
 }
 
 
 // zlib decompression
 
 #[test]
+#[allow(clippy::slow_vector_initialization)]
 fn test_calling_zlib_decompress_with_valid_args_works() {
     let mut decompressor = Decompressor::new();
     let content = read_fixture_zlib();
@@ -251,6 +325,7 @@ fn test_calling_zlib_decompress_with_undersized_outbuf_returns_insufficient_spac
 }
 
 #[test]
+#[allow(clippy::slow_vector_initialization)]
 fn test_calling_zlib_decompress_with_valid_buf_fills_buf_with_expected_content() {
     let decompressed_content = {
         let mut decompressor = Decompressor::new();
@@ -267,6 +342,7 @@ fn test_calling_zlib_decompress_with_valid_buf_fills_buf_with_expected_content()
 }
 
 #[test]
+#[allow(clippy::let_and_return, clippy::slow_vector_initialization)]
 fn test_calling_zlib_decompress_with_oversized_buf_returns_correct_size() {
     const OVERSIZED_FACTOR: usize = 2;
 
@@ -283,6 +359,7 @@ fn test_calling_zlib_decompress_with_oversized_buf_returns_correct_size() {
 }
 
 #[test]
+#[allow(clippy::slow_vector_initialization)]
 fn test_calling_zlib_decompress_with_bad_cmf_field_returns_bad_data() {
     let ret = {
         let mut decompressor = Decompressor::new();
@@ -297,7 +374,8 @@ fn test_calling_zlib_decompress_with_bad_cmf_field_returns_bad_data() {
 }
 
 #[test]
-fn test_calling_zlib_decompress_with_bad_adler32_checksum_returns_bad_data() {
+#[allow(clippy::slow_vector_initialization)]
+fn test_calling_zlib_decompress_with_bad_adler32_checksum_returns_adler32_mismatch() {
     let ret = {
         let mut decompressor = Decompressor::new();
         let content = read_fixture_zlib_with_bad_adler32_checksum();
@@ -307,13 +385,59 @@ fn test_calling_zlib_decompress_with_bad_adler32_checksum_returns_bad_data() {
         decompressor.zlib_decompress(&content, &mut decompressed)
     };
 
-    assert_eq!(ret.unwrap_err(), DecompressionError::BadData);
+    assert_eq!(ret.unwrap_err(), DecompressionError::Adler32Mismatch { expected: 155910687, actual: 139133471 });
+}
+
+#[test]
+fn test_calling_zlib_decompress_with_non_deflate_method_returns_unsupported_method() {
+    let mut decompressor = Decompressor::new();
+    let mut content = read_fixture_zlib();
+    content[0] = (content[0] & 0xf0) | 0x0f;
+    let mut decompressed = vec![0; fixture_content_size()];
+    let ret = decompressor.zlib_decompress(&content, &mut decompressed);
+
+    assert_eq!(ret.unwrap_err(), DecompressionError::UnsupportedMethod(15));
+}
+
+#[test]
+fn test_calling_zlib_decompress_with_bad_fcheck_returns_header_checksum_mismatch() {
+    let mut decompressor = Decompressor::new();
+    let mut content = read_fixture_zlib();
+    content[1] ^= 0x01;
+    let mut decompressed = vec![0; fixture_content_size()];
+    let ret = decompressor.zlib_decompress(&content, &mut decompressed);
+
+    assert_eq!(ret.unwrap_err(), DecompressionError::HeaderChecksumMismatch);
+}
+
+#[test]
+fn test_calling_zlib_decompress_with_fdict_returns_preset_dictionary() {
+    let mut decompressor = Decompressor::new();
+    let mut content = read_fixture_zlib();
+    // CMF = 0x78, FLG = FDICT with a valid FCHECK
+    content[0] = 0x78;
+    content[1] = 0x20;
+    let mut decompressed = vec![0; fixture_content_size()];
+    let ret = decompressor.zlib_decompress(&content, &mut decompressed);
+
+    assert_eq!(ret.unwrap_err(), DecompressionError::PresetDictionary);
+}
+
+#[test]
+fn test_calling_zlib_decompress_with_truncated_trailer_returns_truncated() {
+    let mut decompressor = Decompressor::new();
+    let content = read_fixture_zlib();
+    let mut decompressed = vec![0; fixture_content_size()];
+    let ret = decompressor.zlib_decompress(&content[..content.len() - 2], &mut decompressed);
+
+    assert_eq!(ret.unwrap_err(), DecompressionError::Truncated);
 }
 
 
 // DEFLATE decompression
 
 #[test]
+#[allow(clippy::slow_vector_initialization)]
 fn test_calling_deflate_decompress_with_valid_args_works() {
     let mut decompressor = Decompressor::new();
     let content = read_fixture_deflate();
@@ -337,6 +461,7 @@ fn test_calling_deflate_decompress_with_undersized_outbuf_returns_insufficient_s
 }
 
 #[test]
+#[allow(clippy::slow_vector_initialization)]
 fn test_calling_deflate_decompress_with_valid_buf_fills_buf_with_expected_content() {
     let decompressed_content = {
         let mut decompressor = Decompressor::new();
@@ -353,6 +478,7 @@ fn test_calling_deflate_decompress_with_valid_buf_fills_buf_with_expected_conten
 }
 
 #[test]
+#[allow(clippy::let_and_return, clippy::slow_vector_initialization)]
 fn test_calling_deflate_decompress_with_oversized_buf_returns_correct_size() {
     const OVERSIZED_FACTOR: usize = 2;
 
@@ -369,274 +495,2403 @@ fn test_calling_deflate_decompress_with_oversized_buf_returns_correct_size() {
 }
 
 
+// *_decompress_ex (consumed + produced byte counts)
 
-// compression
+fn with_trailing_garbage(mut data: Vec<u8>) -> Vec<u8> {
+    data.extend_from_slice(b"trailing data that is not part of the stream");
+    data
+}
 
 #[test]
-fn test_compression_lvl_new_returns_ok_for_0() {
-    // see issuse #21 - some use-cases require uncompressed blocks
-    CompressionLvl::new(0).unwrap();
+fn test_calling_gzip_decompress_ex_with_trailing_data_returns_consumed_and_produced_sizes() {
+    let content = read_fixture_gz();
+    let input = with_trailing_garbage(content.clone());
+
+    let mut decompressor = Decompressor::new();
+    let mut decompressed = vec![0; fixture_content_size()];
+    let outcome = decompressor.gzip_decompress_ex(&input, &mut decompressed).unwrap();
+
+    assert_eq!(outcome, DecompressOutcome { consumed: content.len(), produced: fixture_content_size() });
+    assert_eq!(decompressed, read_fixture_content());
 }
 
 #[test]
-fn test_compression_lvl_new_returns_ok_for_7() {
-    CompressionLvl::new(7).unwrap();
+fn test_calling_zlib_decompress_ex_with_trailing_data_returns_consumed_and_produced_sizes() {
+    let content = read_fixture_zlib();
+    let input = with_trailing_garbage(content.clone());
+
+    let mut decompressor = Decompressor::new();
+    let mut decompressed = vec![0; fixture_content_size()];
+    let outcome = decompressor.zlib_decompress_ex(&input, &mut decompressed).unwrap();
+
+    assert_eq!(outcome, DecompressOutcome { consumed: content.len(), produced: fixture_content_size() });
+    assert_eq!(decompressed, read_fixture_content());
 }
 
 #[test]
-fn test_compression_lvl_new_returns_err_for_13() {
-    // If libdeflate changes in the future this test will need to be
-    // scrapped, but it's ok as a smoke test
-    let ret = CompressionLvl::new(13).unwrap_err();
+fn test_calling_deflate_decompress_ex_with_trailing_data_returns_consumed_and_produced_sizes() {
+    let content = read_fixture_deflate();
+    let input = with_trailing_garbage(content.clone());
 
-    assert_eq!(ret, CompressionLvlError::InvalidValue);
+    let mut decompressor = Decompressor::new();
+    let mut decompressed = vec![0; fixture_content_size()];
+    let outcome = decompressor.deflate_decompress_ex(&input, &mut decompressed).unwrap();
+
+    assert_eq!(outcome, DecompressOutcome { consumed: content.len(), produced: fixture_content_size() });
+    assert_eq!(decompressed, read_fixture_content());
 }
 
 #[test]
-fn test_compressor_with_fastest_compression_lvl_calls_with_no_panics() {
-    Compressor::new(CompressionLvl::fastest());
+fn test_calling_gzip_decompress_ex_with_undersized_outbuf_returns_insufficient_space() {
+    let mut decompressor = Decompressor::new();
+    let content = read_fixture_gz();
+    let mut decompressed = Vec::new();
+    let result = decompressor.gzip_decompress_ex(&content, &mut decompressed);
+
+    assert_eq!(result.unwrap_err(), DecompressionError::InsufficientSpace);
 }
 
 #[test]
-fn test_compressor_with_default_compression_lvl_calls_with_no_panics() {
-    Compressor::new(CompressionLvl::default());
+fn test_calling_zlib_decompress_ex_with_bad_adler32_checksum_returns_adler32_mismatch() {
+    let mut decompressor = Decompressor::new();
+    let content = read_fixture_zlib_with_bad_adler32_checksum();
+    let mut decompressed = vec![0; fixture_content_size()];
+    let result = decompressor.zlib_decompress_ex(&content, &mut decompressed);
+
+    assert_eq!(result.unwrap_err(), DecompressionError::Adler32Mismatch { expected: 155910687, actual: 139133471 });
 }
 
+
+
+// *_decompress_exact (output must be filled exactly)
+
 #[test]
-fn test_compressor_with_best_compression_lvl_calls_with_no_panics() {
-    Compressor::new(CompressionLvl::best());
+fn test_calling_gzip_decompress_exact_with_exactly_sized_outbuf_decompresses_content() {
+    let mut decompressor = Decompressor::new();
+    let content = read_fixture_gz();
+    let mut decompressed = vec![0; fixture_content_size()];
+    decompressor.gzip_decompress_exact(&content, &mut decompressed).unwrap();
+
+    assert_eq!(decompressed, read_fixture_content());
 }
 
 #[test]
-fn test_compression_iter_starts_at_compression_lvl_zero() {
-    let val = CompressionLvl::iter().next().unwrap();
-    assert_eq!(val, CompressionLvl::fastest());
-    assert_eq!(val, CompressionLvl::new(0).unwrap());
+fn test_calling_gzip_decompress_exact_with_oversized_outbuf_returns_short_output() {
+    let mut decompressor = Decompressor::new();
+    let content = read_fixture_gz();
+    let mut decompressed = vec![0; fixture_content_size() + 1];
+    let result = decompressor.gzip_decompress_exact(&content, &mut decompressed);
+
+    assert_eq!(result.unwrap_err(), DecompressionError::ShortOutput);
 }
 
 #[test]
-fn test_compressor_with_compression_lvl_can_be_called_with_all_lvls_with_no_panics() {
-    for lvl in CompressionLvl::iter() {
-        Compressor::new(lvl);
-    }
+fn test_calling_gzip_decompress_exact_with_undersized_outbuf_returns_insufficient_space() {
+    let mut decompressor = Decompressor::new();
+    let content = read_fixture_gz();
+    let mut decompressed = vec![0; fixture_content_size() - 1];
+    let result = decompressor.gzip_decompress_exact(&content, &mut decompressed);
+
+    assert_eq!(result.unwrap_err(), DecompressionError::InsufficientSpace);
 }
 
+#[test]
+fn test_calling_gzip_decompress_exact_with_bad_crc32_returns_crc_mismatch() {
+    let mut decompressor = Decompressor::new();
+    let content = read_fixture_gz_with_bad_crc32();
+    let mut decompressed = vec![0; fixture_content_size()];
+    let result = decompressor.gzip_decompress_exact(&content, &mut decompressed);
 
-// compress DEFLATE
+    assert_eq!(result.unwrap_err(), DecompressionError::CrcMismatch { expected: 909783073, actual: 909783072 });
+}
 
 #[test]
-fn test_compressor_deflate_compress_bound_returns_nonzero_when_given_nonzero_input() {
-    let returned_bound = {
-        let mut compressor = Compressor::new(CompressionLvl::default());
-        compressor.deflate_compress_bound(1)
-    };
+fn test_calling_zlib_decompress_exact_with_exactly_sized_outbuf_decompresses_content() {
+    let mut decompressor = Decompressor::new();
+    let content = read_fixture_zlib();
+    let mut decompressed = vec![0; fixture_content_size()];
+    decompressor.zlib_decompress_exact(&content, &mut decompressed).unwrap();
 
-    assert_ne!(returned_bound, 0);
+    assert_eq!(decompressed, read_fixture_content());
 }
 
 #[test]
-fn test_compressor_deflate_compress_with_valid_args_does_not_panic() {
-    let mut compressor = Compressor::new(CompressionLvl::default());
-    let in_data = read_fixture_content();
-    let mut out_data = Vec::new();
-    let out_max_sz = compressor.deflate_compress_bound(in_data.len());
-    out_data.resize(out_max_sz, 0);
+fn test_calling_zlib_decompress_exact_with_oversized_outbuf_returns_short_output() {
+    let mut decompressor = Decompressor::new();
+    let content = read_fixture_zlib();
+    let mut decompressed = vec![0; fixture_content_size() + 1];
+    let result = decompressor.zlib_decompress_exact(&content, &mut decompressed);
 
-    compressor.deflate_compress(&in_data, &mut out_data).unwrap();
+    assert_eq!(result.unwrap_err(), DecompressionError::ShortOutput);
 }
 
 #[test]
-fn test_compressor_deflate_compress_with_undersized_buf_returns_insufficient_space() {
-    let ret = {
-        let mut compressor = Compressor::new(CompressionLvl::default());
-        let in_data = read_fixture_content();
-        let mut out_data = Vec::new();
-        compressor.deflate_compress(&in_data, &mut out_data)
-    };
+fn test_calling_zlib_decompress_exact_with_undersized_outbuf_returns_insufficient_space() {
+    let mut decompressor = Decompressor::new();
+    let content = read_fixture_zlib();
+    let mut decompressed = vec![0; fixture_content_size() - 1];
+    let result = decompressor.zlib_decompress_exact(&content, &mut decompressed);
 
-    assert_eq!(ret.unwrap_err(), CompressionError::InsufficientSpace);
+    assert_eq!(result.unwrap_err(), DecompressionError::InsufficientSpace);
 }
 
 #[test]
-fn test_compressor_deflate_compress_with_correct_args_returns_a_nonzero_size() {
-    let ret = {
-        let mut compressor = Compressor::new(CompressionLvl::default());
-        let in_data = read_fixture_content();
-        let mut out_data = Vec::new();
-        let out_max_sz = compressor.deflate_compress_bound(in_data.len());
-        out_data.resize(out_max_sz, 0);
-        compressor.deflate_compress(&in_data, &mut out_data).unwrap()
-    };
+fn test_calling_deflate_decompress_exact_with_exactly_sized_outbuf_decompresses_content() {
+    let mut decompressor = Decompressor::new();
+    let content = read_fixture_deflate();
+    let mut decompressed = vec![0; fixture_content_size()];
+    decompressor.deflate_decompress_exact(&content, &mut decompressed).unwrap();
 
-    assert_ne!(0, ret);
+    assert_eq!(decompressed, read_fixture_content());
 }
 
 #[test]
-fn test_compressor_deflate_compress_with_correct_args_resized_outbuf_to_return_val_produces_nonempty_nonzero_data() {
-    // This is, give or take, essentially how users *should* use the
-    // lib for raw DEFLATE compressions
+fn test_calling_deflate_decompress_exact_with_oversized_outbuf_returns_short_output() {
+    let mut decompressor = Decompressor::new();
+    let content = read_fixture_deflate();
+    let mut decompressed = vec![0; fixture_content_size() + 1];
+    let result = decompressor.deflate_decompress_exact(&content, &mut decompressed);
 
-    let compressed_data = {
-        let mut compressor = Compressor::new(CompressionLvl::default());
-        let in_data = read_fixture_content();
-        let mut out_data = Vec::new();
-        let out_max_sz = compressor.deflate_compress_bound(in_data.len());
-        out_data.resize(out_max_sz, 0);
-        let sz = compressor.deflate_compress(&in_data, &mut out_data).unwrap();
-        out_data.resize(sz, 0);
-        out_data
-    };
+    assert_eq!(result.unwrap_err(), DecompressionError::ShortOutput);
+}
 
-    assert_ne!(compressed_data.len(), 0);
-    assert!(compressed_data.iter().any(|byte| *byte != 0x00));
+#[test]
+fn test_calling_deflate_decompress_exact_with_undersized_outbuf_returns_insufficient_space() {
+    let mut decompressor = Decompressor::new();
+    let content = read_fixture_deflate();
+    let mut decompressed = vec![0; fixture_content_size() - 1];
+    let result = decompressor.deflate_decompress_exact(&content, &mut decompressed);
+
+    assert_eq!(result.unwrap_err(), DecompressionError::InsufficientSpace);
 }
 
 
-// compress zlib
+
+// uninitialized output (*_uninit and *_decompress_append)
 
 #[test]
-fn test_compressor_zlib_compress_bound_returns_nonzero_when_given_nonzero_input() {
-    let returned_bound = {
-        let mut compressor = Compressor::new(CompressionLvl::default());
-        compressor.zlib_compress_bound(1)
-    };
+fn test_calling_gzip_decompress_uninit_with_uninitialized_outbuf_decompresses_content() {
+    let mut decompressor = Decompressor::new();
+    let content = read_fixture_gz();
+    let mut decompressed = vec![MaybeUninit::uninit(); fixture_content_size()];
+    let sz = decompressor.gzip_decompress_uninit(&content, &mut decompressed).unwrap();
 
-    assert_ne!(returned_bound, 0);
+    let decompressed: Vec<u8> = decompressed[..sz].iter().map(|b| unsafe { b.assume_init() }).collect();
+    assert_eq!(decompressed, read_fixture_content());
 }
 
 #[test]
-fn test_compressor_compresss_zlib_with_valid_args_does_not_panic() {
-    let mut compressor = Compressor::new(CompressionLvl::default());
-    let in_data = read_fixture_content();
-    let mut out_data = Vec::new();
-    let out_max_sz = compressor.zlib_compress_bound(in_data.len());
-    out_data.resize(out_max_sz, 0);
+fn test_calling_gzip_decompress_uninit_with_bad_crc32_returns_crc_mismatch() {
+    let mut decompressor = Decompressor::new();
+    let content = read_fixture_gz_with_bad_crc32();
+    let mut decompressed = vec![MaybeUninit::uninit(); fixture_content_size()];
+    let result = decompressor.gzip_decompress_uninit(&content, &mut decompressed);
 
-    compressor.zlib_compress(&in_data, &mut out_data).unwrap();
+    assert_eq!(result.unwrap_err(), DecompressionError::CrcMismatch { expected: 909783073, actual: 909783072 });
 }
 
 #[test]
-fn test_compressor_zlib_compress_with_undersized_outbuf_returns_insufficient_space() {
-    let ret = {
-        let mut compressor = Compressor::new(CompressionLvl::default());
-        let in_data = read_fixture_content();
-        let mut out_data = Vec::new();
-        compressor.zlib_compress(&in_data, &mut out_data)
-    };
+fn test_calling_gzip_decompress_append_appends_content_after_existing_data() {
+    let mut decompressor = Decompressor::new();
+    let content = read_fixture_gz();
+    let mut decompressed = b"prefix".to_vec();
+    decompressed.reserve(fixture_content_size());
+    let sz = decompressor.gzip_decompress_append(&content, &mut decompressed).unwrap();
 
-    assert_eq!(ret.unwrap_err(), CompressionError::InsufficientSpace);
+    let mut expected = b"prefix".to_vec();
+    expected.extend_from_slice(&read_fixture_content());
+    assert_eq!(sz, fixture_content_size());
+    assert_eq!(decompressed, expected);
 }
 
 #[test]
-fn test_compressor_zlib_compress_with_correct_args_returns_nonzero_size() {
-    let ret = {
-        let mut compressor = Compressor::new(CompressionLvl::default());
-        let in_data = read_fixture_content();
-        let mut out_data = Vec::new();
-        let out_max_sz = compressor.zlib_compress_bound(in_data.len());
-        out_data.resize(out_max_sz, 0);
-
-        compressor.zlib_compress(&in_data, &mut out_data).unwrap()
-    };
+fn test_calling_gzip_decompress_append_without_enough_capacity_returns_insufficient_space_and_leaves_vec_unchanged() {
+    let mut decompressor = Decompressor::new();
+    let content = read_fixture_gz();
+    let mut decompressed = b"prefix".to_vec();
+    decompressed.shrink_to_fit();
+    let result = decompressor.gzip_decompress_append(&content, &mut decompressed);
 
-    assert_ne!(ret, 0);
+    assert_eq!(result.unwrap_err(), DecompressionError::InsufficientSpace);
+    assert_eq!(decompressed, b"prefix");
 }
 
 #[test]
-fn test_compressor_zlib_compress_with_correct_args_resized_outbuf_to_return_val_produces_nonempty_nonzero_data() {
-    // This is, give or take, essentially how users *should* use the
-    // lib for zlib compressions
-
-    let compressed_data = {
-        let mut compressor = Compressor::new(CompressionLvl::default());
-        let in_data = read_fixture_content();
-        let mut out_data = Vec::new();
-        let out_max_sz = compressor.zlib_compress_bound(in_data.len());
-        out_data.resize(out_max_sz, 0);
-        let sz = compressor.zlib_compress(&in_data, &mut out_data).unwrap();
-        out_data.resize(sz, 0);
-        out_data
-    };
+fn test_calling_zlib_decompress_append_with_reserved_capacity_decompresses_content() {
+    let mut decompressor = Decompressor::new();
+    let content = read_fixture_zlib();
+    let mut decompressed = Vec::with_capacity(fixture_content_size());
+    let sz = decompressor.zlib_decompress_append(&content, &mut decompressed).unwrap();
 
-    assert_ne!(compressed_data.len(), 0);
-    assert!(compressed_data.iter().any(|byte| *byte != 0x00));
+    assert_eq!(sz, fixture_content_size());
+    assert_eq!(decompressed, read_fixture_content());
 }
 
+#[test]
+fn test_calling_deflate_decompress_append_with_reserved_capacity_decompresses_content() {
+    let mut decompressor = Decompressor::new();
+    let content = read_fixture_deflate();
+    let mut decompressed = Vec::with_capacity(fixture_content_size());
+    let sz = decompressor.deflate_decompress_append(&content, &mut decompressed).unwrap();
 
-// compress gzip
+    assert_eq!(sz, fixture_content_size());
+    assert_eq!(decompressed, read_fixture_content());
+}
 
 #[test]
-fn test_compressor_gzip_compress_bound_returns_nonzero_when_given_nonzero_input() {
-    let returned_bound = {
-        let mut compressor = Compressor::new(CompressionLvl::default());
-        compressor.gzip_compress_bound(1)
-    };
+fn test_compress_uninit_output_can_be_decompressed_for_each_format() {
+    let in_data = read_fixture_content();
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let mut decompressor = Decompressor::new();
 
-    assert_ne!(returned_bound, 0);
+    let mut out = vec![MaybeUninit::uninit(); compressor.deflate_compress_bound(in_data.len())];
+    let sz = compressor.deflate_compress_uninit(&in_data, &mut out).unwrap();
+    let deflate_data: Vec<u8> = out[..sz].iter().map(|b| unsafe { b.assume_init() }).collect();
+    assert_eq!(decompressor.deflate_decompress_to_vec(&deflate_data, 1 << 20).unwrap(), in_data);
+
+    let mut out = vec![MaybeUninit::uninit(); compressor.zlib_compress_bound(in_data.len())];
+    let sz = compressor.zlib_compress_uninit(&in_data, &mut out).unwrap();
+    let zlib_data: Vec<u8> = out[..sz].iter().map(|b| unsafe { b.assume_init() }).collect();
+    assert_eq!(decompressor.zlib_decompress_to_vec(&zlib_data, 1 << 20).unwrap(), in_data);
+
+    let mut out = vec![MaybeUninit::uninit(); compressor.gzip_compress_bound(in_data.len())];
+    let sz = compressor.gzip_compress_uninit(&in_data, &mut out).unwrap();
+    let gz_data: Vec<u8> = out[..sz].iter().map(|b| unsafe { b.assume_init() }).collect();
+    assert_eq!(decompressor.gzip_decompress_to_vec(&gz_data, 1 << 20).unwrap(), in_data);
 }
 
 #[test]
-fn test_compressor_compresss_gzip_with_valid_args_does_not_panic() {
-    let mut compressor = Compressor::new(CompressionLvl::default());
+fn test_calling_deflate_compress_uninit_with_too_small_outbuf_returns_insufficient_space() {
     let in_data = read_fixture_content();
-    let mut out_data = Vec::new();
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let mut out = [MaybeUninit::uninit(); 1];
+    let result = compressor.deflate_compress_uninit(&in_data, &mut out);
+
+    assert_eq!(result.unwrap_err(), CompressionError::InsufficientSpace);
+}
+
+
+
+// multi-member gzip decompression
+
+fn gzip_compress_to_vec(data: &[u8]) -> Vec<u8> {
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let mut out = vec![0; compressor.gzip_compress_bound(data.len())];
+    let sz = compressor.gzip_compress(data, &mut out).unwrap();
+    out.truncate(sz);
+    out
+}
+
+#[test]
+fn test_calling_gzip_decompress_multi_with_single_member_behaves_like_gzip_decompress() {
+    let mut decompressor = Decompressor::new();
+    let content = read_fixture_gz();
+    let mut decompressed = vec![0; fixture_content_size()];
+    let members = decompressor.gzip_decompress_multi(&content, &mut decompressed).unwrap();
+
+    assert_eq!(members, vec![GzipMember { compressed: 0..content.len(), decompressed: 0..fixture_content_size() }]);
+    assert_eq!(decompressed, read_fixture_content());
+}
+
+#[test]
+fn test_calling_gzip_decompress_multi_with_concatenated_members_decompresses_all_members() {
+    let first = gzip_compress_to_vec(b"the first member\n");
+    let second = gzip_compress_to_vec(b"");
+    let third = gzip_compress_to_vec(b"and the third member\n");
+    let concatenated = [first.clone(), second.clone(), third.clone()].concat();
+
+    let mut decompressor = Decompressor::new();
+    let mut decompressed = vec![0; 64];
+    let members = decompressor.gzip_decompress_multi(&concatenated, &mut decompressed).unwrap();
+
+    let first_end = first.len();
+    let second_end = first_end + second.len();
+    assert_eq!(members, vec![
+        GzipMember { compressed: 0..first_end, decompressed: 0..17 },
+        GzipMember { compressed: first_end..second_end, decompressed: 17..17 },
+        GzipMember { compressed: second_end..concatenated.len(), decompressed: 17..38 },
+    ]);
+    assert_eq!(&decompressed[..38], &b"the first member\nand the third member\n"[..]);
+}
+
+#[test]
+fn test_calling_gzip_decompress_multi_with_trailing_garbage_returns_bad_magic() {
+    let mut decompressor = Decompressor::new();
+    let content = with_trailing_garbage(read_fixture_gz());
+    let mut decompressed = vec![0; 2 * fixture_content_size()];
+    let result = decompressor.gzip_decompress_multi(&content, &mut decompressed);
+
+    assert_eq!(result.unwrap_err(), DecompressionError::BadMagic);
+}
+
+#[test]
+fn test_calling_gzip_decompress_multi_with_outbuf_that_only_fits_first_member_returns_insufficient_space() {
+    let mut decompressor = Decompressor::new();
+    let content = [read_fixture_gz(), read_fixture_gz()].concat();
+    let mut decompressed = vec![0; fixture_content_size()];
+    let result = decompressor.gzip_decompress_multi(&content, &mut decompressed);
+
+    assert_eq!(result.unwrap_err(), DecompressionError::InsufficientSpace);
+}
+
+
+
+// *_decompress_to_vec
+
+fn zlib_compress_to_vec(data: &[u8]) -> Vec<u8> {
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let mut out = vec![0; compressor.zlib_compress_bound(data.len())];
+    let sz = compressor.zlib_compress(data, &mut out).unwrap();
+    out.truncate(sz);
+    out
+}
+
+#[test]
+fn test_calling_gzip_decompress_to_vec_with_valid_data_returns_expected_content() {
+    let mut decompressor = Decompressor::new();
+    let decompressed = decompressor.gzip_decompress_to_vec(&read_fixture_gz(), usize::MAX).unwrap();
+
+    assert_eq!(decompressed, read_fixture_content());
+}
+
+#[test]
+fn test_calling_zlib_decompress_to_vec_with_valid_data_returns_expected_content() {
+    let mut decompressor = Decompressor::new();
+    let decompressed = decompressor.zlib_decompress_to_vec(&read_fixture_zlib(), usize::MAX).unwrap();
+
+    assert_eq!(decompressed, read_fixture_content());
+}
+
+#[test]
+fn test_calling_deflate_decompress_to_vec_with_valid_data_returns_expected_content() {
+    let mut decompressor = Decompressor::new();
+    let decompressed = decompressor.deflate_decompress_to_vec(&read_fixture_deflate(), usize::MAX).unwrap();
+
+    assert_eq!(decompressed, read_fixture_content());
+}
+
+#[test]
+fn test_calling_zlib_decompress_to_vec_with_highly_compressible_data_grows_the_output() {
+    let input_data = vec![b'a'; 1 << 20];
+    let compressed = zlib_compress_to_vec(&input_data);
+
+    let mut decompressor = Decompressor::new();
+    let decompressed = decompressor.zlib_decompress_to_vec(&compressed, usize::MAX).unwrap();
+
+    assert_eq!(decompressed, input_data);
+}
+
+#[test]
+fn test_calling_gzip_decompress_to_vec_with_max_out_len_equal_to_content_size_works() {
+    let mut decompressor = Decompressor::new();
+    let decompressed = decompressor.gzip_decompress_to_vec(&read_fixture_gz(), fixture_content_size()).unwrap();
+
+    assert_eq!(decompressed, read_fixture_content());
+}
+
+#[test]
+fn test_calling_gzip_decompress_to_vec_with_too_small_max_out_len_returns_output_limit_exceeded() {
+    let mut decompressor = Decompressor::new();
+    let result = decompressor.gzip_decompress_to_vec(&read_fixture_gz(), fixture_content_size() - 1);
+
+    assert_eq!(result.unwrap_err(), DecompressionError::OutputLimitExceeded);
+}
+
+#[test]
+fn test_calling_zlib_decompress_to_vec_with_too_small_max_out_len_returns_output_limit_exceeded() {
+    let compressed = zlib_compress_to_vec(&vec![0; 1 << 20]);
+
+    let mut decompressor = Decompressor::new();
+    let result = decompressor.zlib_decompress_to_vec(&compressed, 1 << 19);
+
+    assert_eq!(result.unwrap_err(), DecompressionError::OutputLimitExceeded);
+}
+
+#[test]
+fn test_calling_gzip_decompress_to_vec_with_corrupted_crc32_returns_crc_mismatch() {
+    let mut decompressor = Decompressor::new();
+    let result = decompressor.gzip_decompress_to_vec(&read_fixture_gz_with_bad_crc32(), usize::MAX);
+
+    assert_eq!(result.unwrap_err(), DecompressionError::CrcMismatch { expected: 909783073, actual: 909783072 });
+}
+
+
+
+// format detection
+
+#[test]
+fn test_detect_format_on_gzip_fixture_returns_gzip() {
+    assert_eq!(detect_format(&read_fixture_gz()), Some(Format::Gzip));
+}
+
+#[test]
+fn test_detect_format_on_zlib_fixture_returns_zlib() {
+    assert_eq!(detect_format(&read_fixture_zlib()), Some(Format::Zlib));
+}
+
+#[test]
+fn test_detect_format_on_deflate_fixture_returns_deflate() {
+    assert_eq!(detect_format(&read_fixture_deflate()), Some(Format::Deflate));
+}
+
+#[test]
+fn test_detect_format_on_zlib_data_at_all_compression_lvls_returns_zlib() {
+    // the zlib FLG byte (FLEVEL) changes with the compression level
+    for lvl in CompressionLvl::iter() {
+        let mut compressor = Compressor::new(lvl);
+        let mut out = vec![0; compressor.zlib_compress_bound(fixture_content_size())];
+        compressor.zlib_compress(&read_fixture_content(), &mut out).unwrap();
+
+        assert_eq!(detect_format(&out), Some(Format::Zlib));
+    }
+}
+
+#[test]
+fn test_detect_format_on_empty_data_returns_none() {
+    assert_eq!(detect_format(&[]), None);
+}
+
+#[test]
+fn test_detect_format_on_reserved_deflate_block_type_returns_none() {
+    assert_eq!(detect_format(&[0b0000_0110]), None);
+}
+
+#[test]
+fn test_calling_decompress_auto_with_each_format_returns_expected_content_and_format() {
+    let fixtures = vec![
+        (Format::Gzip, read_fixture_gz()),
+        (Format::Zlib, read_fixture_zlib()),
+        (Format::Deflate, read_fixture_deflate()),
+    ];
+
+    for (expected_format, content) in fixtures {
+        let mut decompressor = Decompressor::new();
+        let mut decompressed = vec![0; fixture_content_size()];
+        let (format, sz) = decompressor.decompress_auto(&content, &mut decompressed).unwrap();
+
+        assert_eq!(format, expected_format);
+        assert_eq!(sz, fixture_content_size());
+        assert_eq!(decompressed, read_fixture_content());
+    }
+}
+
+#[test]
+fn test_calling_decompress_auto_with_undetectable_data_returns_bad_data() {
+    let mut decompressor = Decompressor::new();
+    let mut decompressed = vec![0; fixture_content_size()];
+    let result = decompressor.decompress_auto(&[], &mut decompressed);
+
+    assert_eq!(result.unwrap_err(), DecompressionError::BadData);
+}
+
+#[test]
+fn test_calling_decompress_auto_with_deflate_data_that_looks_like_zlib_returns_deflate() {
+    // a non-final stored block holding "A" (whose first two bytes,
+    // 0x78 0x01, are also a valid zlib header), then an empty final
+    // stored block
+    let content = [0x78, 0x01, 0x00, 0xfe, 0xff, b'A', 0x01, 0x00, 0x00, 0xff, 0xff];
+    assert_eq!(detect_format(&content), Some(Format::Zlib));
+
+    let mut decompressor = Decompressor::new();
+    let mut decompressed = vec![0; 1];
+    let (format, sz) = decompressor.decompress_auto(&content, &mut decompressed).unwrap();
+
+    assert_eq!(format, Format::Deflate);
+    assert_eq!(sz, 1);
+    assert_eq!(decompressed, b"A");
+}
+
+#[test]
+fn test_calling_decompress_auto_with_corrupted_zlib_data_returns_zlib_error() {
+    let content = read_fixture_zlib_with_bad_adler32_checksum();
+    let mut decompressor = Decompressor::new();
+    let mut decompressed = vec![0; fixture_content_size()];
+    let expected = decompressor.zlib_decompress(&content, &mut decompressed).unwrap_err();
+
+    let result = decompressor.decompress_auto(&content, &mut decompressed);
+
+    assert_eq!(result.unwrap_err(), expected);
+}
+
+
+
+// gzip headers
+
+#[test]
+fn test_gzip_header_parse_on_gzip_fixture_returns_expected_fields() {
+    let (header, payload_start) = GzipHeader::parse(&read_fixture_gz()).unwrap();
+
+    assert_eq!(header, GzipHeader {
+        mtime: 0x5d6cd9bc,
+        os: gzip::OS_UNIX,
+        ..GzipHeader::default()
+    });
+    assert_eq!(payload_start, 10);
+}
+
+#[test]
+fn test_gzip_header_parse_on_libdeflate_output_returns_default_header() {
+    let compressed = gzip_compress_to_vec(&read_fixture_content());
+    let (header, payload_start) = GzipHeader::parse(&compressed).unwrap();
+
+    assert_eq!(header, GzipHeader::default());
+    assert_eq!(payload_start, 10);
+}
+
+#[test]
+fn test_gzip_header_parse_with_bad_magic_num_returns_bad_magic() {
+    let ret = GzipHeader::parse(&read_fixture_gz_with_bad_magic_num());
+
+    assert_eq!(ret.unwrap_err(), DecompressionError::BadMagic);
+}
+
+#[test]
+fn test_gzip_header_with_all_fields_round_trips_through_to_bytes_and_parse() {
+    let mut header = GzipHeader {
+        text: true,
+        mtime: 1234567890,
+        xfl: 2,
+        os: gzip::OS_UNIX,
+        extra: None,
+        filename: Some(b"hello.txt".to_vec()),
+        comment: Some(b"a comment".to_vec()),
+        header_crc: true,
+    };
+    header.push_extra_subfield(*b"AB", b"some data");
+    header.push_extra_subfield(*b"CD", b"");
+
+    let bytes = header.to_bytes();
+    assert_eq!(bytes.len(), header.encoded_len());
+
+    let (parsed, payload_start) = GzipHeader::parse(&bytes).unwrap();
+    assert_eq!(parsed, header);
+    assert_eq!(payload_start, bytes.len());
+    assert_eq!(parsed.extra_subfields().collect::<Vec<_>>(), vec![(*b"AB", &b"some data"[..]), (*b"CD", &b""[..])]);
+    assert_eq!(parsed.extra_subfield(*b"CD"), Some(&b""[..]));
+    assert_eq!(parsed.extra_subfield(*b"EF"), None);
+}
+
+#[test]
+fn test_gzip_header_parse_with_corrupted_header_crc_returns_header_checksum_mismatch() {
+    let header = GzipHeader {
+        filename: Some(b"hello.txt".to_vec()),
+        header_crc: true,
+        ..GzipHeader::default()
+    };
+    let mut bytes = header.to_bytes();
+    let crc16_start = bytes.len() - 2;
+    bytes[crc16_start] ^= 0xff;
+
+    assert_eq!(GzipHeader::parse(&bytes).unwrap_err(), DecompressionError::HeaderChecksumMismatch);
+}
+
+#[test]
+fn test_gzip_header_parse_with_truncated_filename_returns_truncated() {
+    let header = GzipHeader {
+        filename: Some(b"hello.txt".to_vec()),
+        ..GzipHeader::default()
+    };
+    let bytes = header.to_bytes();
+
+    assert_eq!(GzipHeader::parse(&bytes[..bytes.len() - 1]).unwrap_err(), DecompressionError::Truncated);
+}
+
+#[test]
+fn test_gzip_compress_with_header_then_gzip_decompress_produces_the_same_input_data() {
+    let input_data = read_fixture_content();
+    let header = GzipHeader {
+        mtime: 1234567890,
+        filename: Some(b"hello".to_vec()),
+        header_crc: true,
+        ..GzipHeader::default()
+    };
+
+    let compressed = {
+        let mut compressor = Compressor::new(CompressionLvl::default());
+        let mut out = vec![0; compressor.gzip_compress_with_header_bound(&header, input_data.len())];
+        let sz = compressor.gzip_compress_with_header(&header, &input_data, &mut out).unwrap();
+        out.truncate(sz);
+        out
+    };
+
+    let mut decompressor = Decompressor::new();
+    let mut decompressed = vec![0; input_data.len()];
+    let sz = decompressor.gzip_decompress(&compressed, &mut decompressed).unwrap();
+    assert_eq!(sz, input_data.len());
+    assert_eq!(decompressed, input_data);
+
+    let (parsed_header, sz) = decompressor.gzip_decompress_with_header(&compressed, &mut decompressed).unwrap();
+    assert_eq!(parsed_header, header);
+    assert_eq!(sz, input_data.len());
+}
+
+#[test]
+fn test_gzip_compress_with_header_output_is_readable_by_flate2() {
+    use std::io::Read;
+
+    let input_data = read_fixture_content();
+    let header = GzipHeader {
+        filename: Some(b"hello".to_vec()),
+        comment: Some(b"greetings".to_vec()),
+        ..GzipHeader::default()
+    };
+
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let mut compressed = vec![0; compressor.gzip_compress_with_header_bound(&header, input_data.len())];
+    let sz = compressor.gzip_compress_with_header(&header, &input_data, &mut compressed).unwrap();
+    compressed.truncate(sz);
+
+    let mut decoder = flate2::read::GzDecoder::new(&compressed[..]);
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed).unwrap();
+
+    assert_eq!(decompressed, input_data);
+    assert_eq!(decoder.header().unwrap().filename(), Some(&b"hello"[..]));
+    assert_eq!(decoder.header().unwrap().comment(), Some(&b"greetings"[..]));
+}
+
+#[test]
+fn test_gzip_compress_with_header_with_undersized_outbuf_returns_insufficient_space() {
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let mut out = vec![0; 12];
+    let ret = compressor.gzip_compress_with_header(&GzipHeader::default(), &read_fixture_content(), &mut out);
+
+    assert_eq!(ret.unwrap_err(), CompressionError::InsufficientSpace);
+}
+
+#[test]
+fn test_gzip_decompress_with_header_with_corrupted_crc32_returns_crc_mismatch() {
+    let mut decompressor = Decompressor::new();
+    let mut decompressed = vec![0; fixture_content_size()];
+    let ret = decompressor.gzip_decompress_with_header(&read_fixture_gz_with_bad_crc32(), &mut decompressed);
+
+    assert_eq!(ret.unwrap_err(), DecompressionError::CrcMismatch { expected: 909783073, actual: 909783072 });
+}
+
+
+
+// bgzf
+
+fn bgzf_test_data() -> Vec<u8> {
+    // spans several blocks, with a partially-filled final block
+    (0..(3 * bgzf::MAX_BLOCK_DATA_SIZE + 1234))
+        .map(|i| ((i * 7) % 251) as u8)
+        .collect()
+}
+
+#[test]
+fn test_bgzf_compress_then_bgzf_decompress_produces_the_same_input_data() {
+    let input_data = bgzf_test_data();
+
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let compressed = bgzf::compress(&mut compressor, &input_data).unwrap();
+
+    let mut decompressor = Decompressor::new();
+    let decompressed = bgzf::decompress(&mut decompressor, &compressed).unwrap();
+
+    assert_eq!(decompressed, input_data);
+}
+
+#[test]
+fn test_bgzf_compress_writes_blocks_with_valid_bsize_fields_and_an_eof_marker() {
+    let input_data = bgzf_test_data();
+
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let compressed = bgzf::compress(&mut compressor, &input_data).unwrap();
+
+    assert!(compressed.ends_with(&bgzf::EOF_MARKER));
+
+    let mut pos = 0;
+    let mut n_blocks = 0;
+    while pos < compressed.len() {
+        let bsize = bgzf::block_size(&compressed[pos..]).unwrap();
+        assert!(bsize <= bgzf::MAX_BLOCK_SIZE);
+        pos += bsize;
+        n_blocks += 1;
+    }
+    assert_eq!(pos, compressed.len());
+    assert_eq!(n_blocks, 5);  // 4 data blocks + EOF marker
+}
+
+#[test]
+fn test_bgzf_compress_with_incompressible_data_fits_in_max_block_size() {
+    // xorshift: cheap and incompressible enough to defeat DEFLATE
+    let mut state: u32 = 0x12345678;
+    let input_data: Vec<u8> = (0..bgzf::MAX_BLOCK_DATA_SIZE)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect();
+
+    let mut compressor = Compressor::new(CompressionLvl::best());
+    let mut block = vec![0; bgzf::MAX_BLOCK_SIZE];
+    let sz = bgzf::compress_block(&mut compressor, &input_data, &mut block).unwrap();
+
+    assert_eq!(bgzf::block_size(&block).unwrap(), sz);
+}
+
+#[test]
+fn test_bgzf_compress_of_empty_data_writes_only_the_eof_marker() {
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let compressed = bgzf::compress(&mut compressor, &[]).unwrap();
+
+    assert_eq!(compressed, bgzf::EOF_MARKER.to_vec());
+
+    let mut decompressor = Decompressor::new();
+    assert_eq!(bgzf::decompress(&mut decompressor, &compressed).unwrap(), Vec::<u8>::new());
+}
+
+#[test]
+fn test_bgzf_compress_output_is_readable_as_multi_member_gzip() {
+    use std::io::Read;
+
+    let input_data = bgzf_test_data();
+
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let compressed = bgzf::compress(&mut compressor, &input_data).unwrap();
+
+    let mut decoder = flate2::read::MultiGzDecoder::new(&compressed[..]);
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(decompressed, input_data);
+
+    let mut decompressor = Decompressor::new();
+    let mut decompressed = vec![0; input_data.len()];
+    let members = decompressor.gzip_decompress_multi(&compressed, &mut decompressed).unwrap();
+    assert_eq!(members.len(), 5);
+    assert_eq!(decompressed, input_data);
+}
+
+#[test]
+fn test_bgzf_decompress_without_eof_marker_returns_truncated() {
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let compressed = bgzf::compress(&mut compressor, &bgzf_test_data()).unwrap();
+    let truncated = &compressed[..compressed.len() - bgzf::EOF_MARKER.len()];
+
+    let mut decompressor = Decompressor::new();
+    let ret = bgzf::decompress(&mut decompressor, truncated);
+
+    assert_eq!(ret.unwrap_err(), DecompressionError::Truncated);
+}
+
+#[test]
+fn test_bgzf_decompress_of_plain_gzip_data_returns_bad_data() {
+    let mut data = read_fixture_gz();
+    data.extend_from_slice(&bgzf::EOF_MARKER);
+
+    let mut decompressor = Decompressor::new();
+    let ret = bgzf::decompress(&mut decompressor, &data);
+
+    assert_eq!(ret.unwrap_err(), DecompressionError::BadData);
+}
+
+#[test]
+fn test_bgzf_decompress_block_with_corrupted_bsize_returns_truncated() {
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let mut block = vec![0; bgzf::MAX_BLOCK_SIZE];
+    let sz = bgzf::compress_block(&mut compressor, &read_fixture_content(), &mut block).unwrap();
+    block.truncate(sz);
+    block[16] += 1;  // BSIZE now points past the end of the block
+
+    let mut decompressor = Decompressor::new();
+    let mut decompressed = vec![0; fixture_content_size()];
+    let ret = bgzf::decompress_block(&mut decompressor, &block, &mut decompressed);
+
+    assert_eq!(ret.unwrap_err(), DecompressionError::Truncated);
+}
+
+#[test]
+fn test_bgzf_decompress_block_returns_block_size_and_decompressed_size() {
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let mut block = vec![0; bgzf::MAX_BLOCK_SIZE];
+    let sz = bgzf::compress_block(&mut compressor, &read_fixture_content(), &mut block).unwrap();
+
+    let mut decompressor = Decompressor::new();
+    let mut decompressed = vec![0; fixture_content_size()];
+    let outcome = bgzf::decompress_block(&mut decompressor, &block, &mut decompressed).unwrap();
+
+    assert_eq!(outcome, DecompressOutcome { consumed: sz, produced: fixture_content_size() });
+    assert_eq!(decompressed, read_fixture_content());
+}
+
+
+
+// bgzf random access
+
+fn bgzf_compress_test_data() -> (Vec<u8>, Vec<u8>) {
+    let input_data = bgzf_test_data();
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let compressed = bgzf::compress(&mut compressor, &input_data).unwrap();
+    (input_data, compressed)
+}
+
+#[test]
+fn test_bgzf_virtual_offset_packs_block_offset_and_within_block_offset() {
+    let offset = bgzf::VirtualOffset::new(0x1234_5678_9abc, 0xdef0);
+
+    assert_eq!(offset.block_offset(), 0x1234_5678_9abc);
+    assert_eq!(offset.within_block(), 0xdef0);
+    assert_eq!(u64::from(offset), 0x1234_5678_9abc_def0);
+    assert_eq!(bgzf::VirtualOffset::from(0x1234_5678_9abc_def0), offset);
+}
+
+#[test]
+fn test_bgzf_gzi_index_build_has_an_entry_for_each_block_after_the_first() {
+    let (_, compressed) = bgzf_compress_test_data();
+    let index = bgzf::GziIndex::build(&compressed).unwrap();
+
+    let mut expected_compressed_offset = 0;
+    let mut expected_entries = Vec::new();
+    for i in 0..3 {
+        expected_compressed_offset += bgzf::block_size(&compressed[expected_compressed_offset..]).unwrap();
+        expected_entries.push(bgzf::GziEntry {
+            compressed_offset: expected_compressed_offset as u64,
+            uncompressed_offset: ((i + 1) * bgzf::MAX_BLOCK_DATA_SIZE) as u64,
+        });
+    }
+
+    assert_eq!(index.entries(), &expected_entries[..]);
+}
+
+#[test]
+fn test_bgzf_gzi_index_round_trips_through_gzi_format() {
+    let (_, compressed) = bgzf_compress_test_data();
+    let index = bgzf::GziIndex::build(&compressed).unwrap();
+
+    let bytes = index.to_bytes();
+    assert_eq!(bytes.len(), 8 + 16 * index.entries().len());
+    assert_eq!(bgzf::GziIndex::from_bytes(&bytes).unwrap(), index);
+
+    let mut written = Vec::new();
+    index.write(&mut written).unwrap();
+    assert_eq!(written, bytes);
+    assert_eq!(bgzf::GziIndex::read(&written[..]).unwrap(), index);
+}
+
+#[test]
+fn test_bgzf_gzi_index_from_bytes_with_truncated_data_returns_bad_data() {
+    let (_, compressed) = bgzf_compress_test_data();
+    let bytes = bgzf::GziIndex::build(&compressed).unwrap().to_bytes();
+
+    let ret = bgzf::GziIndex::from_bytes(&bytes[..bytes.len() - 1]);
+
+    assert_eq!(ret.unwrap_err(), DecompressionError::BadData);
+}
+
+#[test]
+fn test_bgzf_gzi_index_from_bytes_with_huge_entry_count_returns_bad_data() {
+    let ret = bgzf::GziIndex::from_bytes(&[0xff; 8]);
+
+    assert_eq!(ret.unwrap_err(), DecompressionError::BadData);
+}
+
+#[test]
+fn test_bgzf_read_range_returns_the_same_data_as_slicing_the_input() {
+    let (input_data, compressed) = bgzf_compress_test_data();
+    let index = bgzf::GziIndex::build(&compressed).unwrap();
+    let mut decompressor = Decompressor::new();
+
+    let ranges = vec![
+        (0, 10),
+        (100, 1000),
+        (bgzf::MAX_BLOCK_DATA_SIZE - 5, 10),  // spans a block boundary
+        (bgzf::MAX_BLOCK_DATA_SIZE, 2 * bgzf::MAX_BLOCK_DATA_SIZE + 1),  // spans several blocks
+        (input_data.len() - 10, 10),
+    ];
+    for (start, len) in ranges {
+        let data = bgzf::read_range(&mut decompressor, &compressed, &index, start as u64, len).unwrap();
+        assert_eq!(data, &input_data[start..start + len]);
+    }
+}
+
+#[test]
+fn test_bgzf_read_range_past_the_end_of_the_data_returns_the_available_data() {
+    let (input_data, compressed) = bgzf_compress_test_data();
+    let index = bgzf::GziIndex::build(&compressed).unwrap();
+    let mut decompressor = Decompressor::new();
+
+    let tail = bgzf::read_range(&mut decompressor, &compressed, &index, input_data.len() as u64 - 10, 100).unwrap();
+    assert_eq!(tail, &input_data[input_data.len() - 10..]);
+
+    let past_end = bgzf::read_range(&mut decompressor, &compressed, &index, input_data.len() as u64 + 10, 100).unwrap();
+    assert!(past_end.is_empty());
+}
+
+#[test]
+fn test_bgzf_read_range_from_a_reader_returns_the_same_data_as_slicing_the_input() {
+    let (input_data, compressed) = bgzf_compress_test_data();
+    let index = bgzf::GziIndex::build(&compressed).unwrap();
+    let mut decompressor = Decompressor::new();
+    let mut reader = std::io::Cursor::new(&compressed);
+
+    let start = 2 * bgzf::MAX_BLOCK_DATA_SIZE + 17;
+    let data = bgzf::read_range_from(&mut decompressor, &mut reader, &index, start as u64, 60000).unwrap();
+    assert_eq!(data, &input_data[start..start + 60000]);
+
+    let data = bgzf::read_range_from(&mut decompressor, &mut reader, &index, 0, 3).unwrap();
+    assert_eq!(data, &input_data[..3]);
+}
+
+#[test]
+fn test_bgzf_read_at_virtual_offset_from_index_returns_the_same_data_as_slicing_the_input() {
+    let (input_data, compressed) = bgzf_compress_test_data();
+    let index = bgzf::GziIndex::build(&compressed).unwrap();
+    let mut decompressor = Decompressor::new();
+    let mut reader = std::io::Cursor::new(&compressed);
+
+    let start = bgzf::MAX_BLOCK_DATA_SIZE + 1;
+    let offset = index.virtual_offset(start as u64).unwrap();
+    assert_eq!(offset.within_block(), 1);
+
+    let data = bgzf::read_at(&mut decompressor, &compressed, offset, 50).unwrap();
+    assert_eq!(data, &input_data[start..start + 50]);
+
+    let data = bgzf::read_at_from(&mut decompressor, &mut reader, offset, 50).unwrap();
+    assert_eq!(data, &input_data[start..start + 50]);
+}
+
+#[test]
+fn test_bgzf_gzi_index_virtual_offset_far_past_the_end_of_the_data_returns_none() {
+    let (input_data, compressed) = bgzf_compress_test_data();
+    let index = bgzf::GziIndex::build(&compressed).unwrap();
+
+    assert_eq!(index.virtual_offset(input_data.len() as u64 + (1 << 20)), None);
+}
+
+
+
+// gzip writer
+
+#[test]
+fn test_gzip_writer_output_decompresses_to_the_written_data_in_one_member_per_block() {
+    use std::io::Write;
+
+    let input_data = parallel_test_data();
+    let block_size = 64 * 1024;
+    let mut writer = gzip::GzipWriter::with_block_size(Vec::new(), CompressionLvl::default(), block_size);
+    for piece in input_data.chunks(1000) {
+        writer.write_all(piece).unwrap();
+    }
+    let compressed = writer.finish().unwrap();
+
+    let mut decompressor = Decompressor::new();
+    let mut decompressed = vec![0; input_data.len()];
+    let members = decompressor.gzip_decompress_multi(&compressed, &mut decompressed).unwrap();
+
+    assert_eq!(decompressed, input_data);
+    assert_eq!(members.len(), input_data.len().div_ceil(block_size));
+}
+
+#[test]
+fn test_gzip_writer_output_is_readable_by_flate2() {
+    use std::io::{Read, Write};
+
+    let input_data = parallel_test_data();
+    let mut writer = gzip::GzipWriter::with_block_size(Vec::new(), CompressionLvl::best(), 100_000);
+    writer.write_all(&input_data).unwrap();
+    let compressed = writer.finish().unwrap();
+
+    let mut decoder = flate2::read::MultiGzDecoder::new(&compressed[..]);
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed).unwrap();
+
+    assert_eq!(decompressed, input_data);
+}
+
+#[test]
+fn test_gzip_writer_with_no_data_writes_an_empty_member() {
+    let writer = gzip::GzipWriter::new(Vec::new(), CompressionLvl::default());
+    let compressed = writer.finish().unwrap();
+
+    let mut decompressor = Decompressor::new();
+    let sz = decompressor.gzip_decompress(&compressed, &mut []).unwrap();
+    assert_eq!(sz, 0);
+}
+
+#[test]
+fn test_gzip_writer_flush_writes_buffered_data_as_a_member() {
+    use std::io::Write;
+
+    let mut writer = gzip::GzipWriter::new(Vec::new(), CompressionLvl::default());
+    writer.write_all(b"hello").unwrap();
+    assert!(writer.get_ref().is_empty());
+
+    writer.flush().unwrap();
+    let mut decompressor = Decompressor::new();
+    let mut decompressed = vec![0; 5];
+    decompressor.gzip_decompress(writer.get_ref(), &mut decompressed).unwrap();
+    assert_eq!(decompressed, b"hello");
+}
+
+#[test]
+fn test_gzip_writer_finishes_when_dropped() {
+    use std::io::Write;
+
+    let input_data = read_fixture_content();
+    let mut compressed = Vec::new();
+    {
+        let mut writer = gzip::GzipWriter::new(&mut compressed, CompressionLvl::default());
+        writer.write_all(&input_data).unwrap();
+    }
+
+    let mut decompressor = Decompressor::new();
+    let decompressed = decompressor.gzip_decompress_to_vec(&compressed, input_data.len()).unwrap();
+    assert_eq!(decompressed, input_data);
+}
+
+#[test]
+fn test_gzip_writer_in_bgzf_mode_writes_the_same_data_as_bgzf_compress() {
+    use std::io::Write;
+
+    let input_data = bgzf_test_data();
+    let mut writer = gzip::GzipWriter::bgzf(Vec::new(), CompressionLvl::default());
+    for piece in input_data.chunks(777) {
+        writer.write_all(piece).unwrap();
+    }
+    let compressed = writer.finish().unwrap();
+
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    assert_eq!(compressed, bgzf::compress(&mut compressor, &input_data).unwrap());
+}
+
+/// A writer that accepts up to `accepted` bytes, then fails its next
+/// `failures` writes without writing anything.
+struct FailingWriter {
+    written: Vec<u8>,
+    accepted: usize,
+    failures: usize,
+}
+
+impl std::io::Write for FailingWriter {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        if self.failures > 0 && self.written.len() >= self.accepted {
+            self.failures -= 1;
+            return Err(std::io::Error::new(std::io::ErrorKind::Other, "write failed"));
+        }
+        let n = if self.failures > 0 {
+            std::cmp::min(data.len(), self.accepted - self.written.len())
+        } else {
+            data.len()
+        };
+        self.written.extend_from_slice(&data[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_gzip_writer_keeps_buffered_data_when_inner_writer_fails() {
+    use std::io::Write;
+
+    let mut writer = gzip::GzipWriter::new(FailingWriter { written: Vec::new(), accepted: 0, failures: 1 }, CompressionLvl::default());
+    writer.write_all(b"hello").unwrap();
+    assert!(writer.flush().is_err());
+    writer.flush().unwrap();
+
+    let mut decompressor = Decompressor::new();
+    let decompressed = decompressor.gzip_decompress_to_vec(&writer.get_ref().written, 5).unwrap();
+    assert_eq!(decompressed, b"hello");
+}
+
+#[test]
+fn test_gzip_writer_does_not_write_again_when_dropped_after_finish_fails() {
+    use std::io::Write;
+
+    let mut inner = FailingWriter { written: Vec::new(), accepted: 0, failures: 1 };
+    let mut writer = gzip::GzipWriter::bgzf(&mut inner, CompressionLvl::default());
+    writer.write_all(b"hello").unwrap();
+
+    assert!(writer.finish().is_err());
+    assert!(inner.written.is_empty());
+}
+
+#[test]
+fn test_gzip_writer_resumes_partially_written_member_when_inner_writer_fails() {
+    use std::io::Write;
+
+    let mut writer = gzip::GzipWriter::with_block_size(FailingWriter { written: Vec::new(), accepted: 4, failures: 1 }, CompressionLvl::default(), 5);
+    writer.write_all(b"hello").unwrap();
+    assert!(writer.write_all(b", world!").is_err());
+    writer.write_all(b", world!").unwrap();
+    let inner = writer.finish().unwrap();
+
+    let mut decompressed = Vec::new();
+    gzip::GzipReader::new(&inner.written[..]).read_to_end(&mut decompressed).unwrap();
+    assert_eq!(decompressed, b"hello, world!");
+}
+
+
+// gzip reader
+
+/// A reader that returns at most a few bytes per `read` call.
+struct TrickleReader<'a>(&'a [u8]);
+
+impl<'a> Read for TrickleReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = std::cmp::min(std::cmp::min(buf.len(), 7), self.0.len());
+        buf[..n].copy_from_slice(&self.0[..n]);
+        self.0 = &self.0[n..];
+        Ok(n)
+    }
+}
+
+fn gzip_reader_error_cause(e: &std::io::Error) -> &DecompressionError {
+    e.get_ref().unwrap().downcast_ref::<DecompressionError>().unwrap()
+}
+
+#[test]
+fn test_gzip_reader_reads_every_member_of_gzip_writer_output() {
+    use std::io::Write;
+
+    let input_data = parallel_test_data();
+    let mut writer = gzip::GzipWriter::with_block_size(Vec::new(), CompressionLvl::default(), 50_000);
+    writer.write_all(&input_data).unwrap();
+    let compressed = writer.finish().unwrap();
+
+    let mut reader = gzip::GzipReader::new(&compressed[..]);
+    let mut decompressed = Vec::new();
+    reader.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(decompressed, input_data);
+
+    let mut reader = gzip::GzipReader::new(TrickleReader(&compressed));
+    let mut decompressed = Vec::new();
+    reader.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(decompressed, input_data);
+}
+
+#[test]
+fn test_gzip_reader_reads_bgzf_data() {
+    let input_data = bgzf_test_data();
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let compressed = bgzf::compress(&mut compressor, &input_data).unwrap();
+
+    let mut reader = gzip::GzipReader::new(&compressed[..]);
+    let mut decompressed = Vec::new();
+    reader.read_to_end(&mut decompressed).unwrap();
+
+    assert_eq!(decompressed, input_data);
+}
+
+#[test]
+fn test_gzip_reader_reads_flate2_output_with_optional_header_fields() {
+    use std::io::Write;
+
+    let input_data = read_fixture_content();
+    let mut encoder = flate2::GzBuilder::new()
+        .filename("hello.txt")
+        .comment("greetings")
+        .write(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&input_data).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let mut reader = gzip::GzipReader::new(&compressed[..]);
+    let mut decompressed = Vec::new();
+    reader.read_to_end(&mut decompressed).unwrap();
+
+    assert_eq!(decompressed, input_data);
+}
+
+#[test]
+fn test_gzip_reader_implements_buf_read() {
+    use std::io::BufRead;
+
+    let compressed = gzip_compress_to_vec(b"first line\nsecond line\n");
+    let reader = gzip::GzipReader::new(&compressed[..]);
+    let lines: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+
+    assert_eq!(lines, vec!["first line", "second line"]);
+}
+
+#[test]
+fn test_gzip_reader_with_member_larger_than_max_member_size_returns_output_limit_exceeded() {
+    let input_data = vec![0; 100_000];
+    let compressed = gzip_compress_to_vec(&input_data);
+
+    let mut reader = gzip::GzipReader::with_max_member_size(&compressed[..], 50_000);
+    let mut decompressed = Vec::new();
+    let err = reader.read_to_end(&mut decompressed).unwrap_err();
+
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(*gzip_reader_error_cause(&err), DecompressionError::OutputLimitExceeded);
+}
+
+#[test]
+fn test_gzip_reader_buffers_at_most_max_member_size_of_input() {
+    let compressed = gzip_compress_to_vec(&parallel_test_data());
+
+    let mut reader = gzip::GzipReader::with_max_member_size(&compressed[..], 1000);
+    let mut decompressed = Vec::new();
+    let err = reader.read_to_end(&mut decompressed).unwrap_err();
+
+    assert_eq!(*gzip_reader_error_cause(&err), DecompressionError::OutputLimitExceeded);
+    assert!(compressed.len() - reader.get_ref().len() <= 1001);
+}
+
+#[test]
+fn test_gzip_reader_with_bad_magic_num_returns_bad_magic() {
+    let compressed = read_fixture_gz_with_bad_magic_num();
+    let mut reader = gzip::GzipReader::new(&compressed[..]);
+    let mut decompressed = Vec::new();
+    let err = reader.read_to_end(&mut decompressed).unwrap_err();
+
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(*gzip_reader_error_cause(&err), DecompressionError::BadMagic);
+}
+
+#[test]
+fn test_gzip_reader_with_truncated_data_returns_truncated() {
+    let compressed = read_fixture_gz();
+    let mut reader = gzip::GzipReader::new(&compressed[..compressed.len() - 3]);
+    let mut decompressed = Vec::new();
+    let err = reader.read_to_end(&mut decompressed).unwrap_err();
+
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    assert_eq!(*gzip_reader_error_cause(&err), DecompressionError::Truncated);
+}
+
+#[test]
+fn test_gzip_reader_with_corrupted_crc32_returns_crc_mismatch() {
+    let compressed = read_fixture_gz_with_bad_crc32();
+    let mut reader = gzip::GzipReader::new(&compressed[..]);
+    let mut decompressed = Vec::new();
+    let err = reader.read_to_end(&mut decompressed).unwrap_err();
+
+    assert_eq!(*gzip_reader_error_cause(&err), DecompressionError::CrcMismatch { expected: 909783073, actual: 909783072 });
+}
+
+
+// parallel compression
+
+fn parallel_test_data() -> Vec<u8> {
+    // a mix of compressible text and noise, spanning many chunks
+    let mut data = Vec::new();
+    let content = read_fixture_content();
+    let mut x: u32 = 1;
+    for _ in 0..8 {
+        data.extend_from_slice(&content);
+        for _ in 0..10_000 {
+            x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            data.push((x >> 24) as u8);
+        }
+    }
+    data
+}
+
+#[test]
+fn test_parallel_gzip_compress_then_gzip_decompress_at_all_compression_lvls_produces_the_same_input_data() {
+    let input_data = parallel_test_data();
+    let mut decompressor = Decompressor::new();
+
+    for lvl in CompressionLvl::iter() {
+        let compressed = ParallelCompressor::new(lvl)
+            .threads(4)
+            .chunk_size(16 * 1024)
+            .gzip_compress(&input_data);
+
+        let mut decompressed = vec![0; input_data.len()];
+        let sz = decompressor.gzip_decompress(&compressed, &mut decompressed).unwrap();
+        assert_eq!(sz, input_data.len(), "lvl = {:?}", lvl);
+        assert_eq!(decompressed, input_data, "lvl = {:?}", lvl);
+    }
+}
+
+#[test]
+fn test_parallel_zlib_compress_then_zlib_decompress_produces_the_same_input_data() {
+    let input_data = parallel_test_data();
+    let mut decompressor = Decompressor::new();
+
+    for lvl in &[CompressionLvl::fastest(), CompressionLvl::default(), CompressionLvl::best()] {
+        let compressed = ParallelCompressor::new(*lvl)
+            .chunk_size(10_000)
+            .zlib_compress(&input_data);
+
+        let decompressed = decompressor.zlib_decompress_to_vec(&compressed, input_data.len()).unwrap();
+        assert_eq!(decompressed, input_data, "lvl = {:?}", lvl);
+    }
+}
+
+#[test]
+fn test_parallel_deflate_compress_then_deflate_decompress_produces_the_same_input_data() {
+    let input_data = parallel_test_data();
+    let compressed = ParallelCompressor::new(CompressionLvl::default())
+        .chunk_size(10_000)
+        .deflate_compress(&input_data);
+
+    let mut decompressor = Decompressor::new();
+    let decompressed = decompressor.deflate_decompress_to_vec(&compressed, input_data.len()).unwrap();
+    assert_eq!(decompressed, input_data);
+}
+
+#[test]
+fn test_parallel_gzip_compress_output_is_a_single_member_readable_by_flate2() {
+    use std::io::Read;
+
+    let input_data = parallel_test_data();
+    let compressed = ParallelCompressor::new(CompressionLvl::best())
+        .chunk_size(32 * 1024)
+        .gzip_compress(&input_data);
+
+    // GzDecoder (unlike MultiGzDecoder) stops after the first member
+    let mut decoder = flate2::read::GzDecoder::new(&compressed[..]);
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed).unwrap();
+
+    assert_eq!(decompressed, input_data);
+}
+
+#[test]
+fn test_parallel_gzip_compress_with_tiny_chunks_produces_the_same_input_data() {
+    let input_data = &parallel_test_data()[..1000];
+    let compressed = ParallelCompressor::new(CompressionLvl::default())
+        .chunk_size(7)
+        .gzip_compress(input_data);
+
+    let mut decompressor = Decompressor::new();
+    let decompressed = decompressor.gzip_decompress_to_vec(&compressed, input_data.len()).unwrap();
+    assert_eq!(decompressed, input_data);
+}
+
+#[test]
+fn test_parallel_gzip_compress_of_empty_data_decompresses_to_empty_data() {
+    let compressed = ParallelCompressor::new(CompressionLvl::default()).gzip_compress(&[]);
+
+    let mut decompressor = Decompressor::new();
+    let mut decompressed = Vec::new();
+    let sz = decompressor.gzip_decompress(&compressed, &mut decompressed).unwrap();
+    assert_eq!(sz, 0);
+}
+
+#[test]
+fn test_parallel_compress_with_a_single_chunk_matches_compressor_output() {
+    let input_data = read_fixture_content();
+    let lvl = CompressionLvl::best();
+    let parallel = ParallelCompressor::new(lvl).chunk_size(input_data.len());
+    let mut compressor = Compressor::new(lvl);
+
+    let mut expected = vec![0; compressor.gzip_compress_bound(input_data.len())];
+    let sz = compressor.gzip_compress(&input_data, &mut expected).unwrap();
+    expected.truncate(sz);
+    assert_eq!(parallel.gzip_compress(&input_data), expected);
+
+    let mut expected = vec![0; compressor.zlib_compress_bound(input_data.len())];
+    let sz = compressor.zlib_compress(&input_data, &mut expected).unwrap();
+    expected.truncate(sz);
+    assert_eq!(parallel.zlib_compress(&input_data), expected);
+}
+
+#[test]
+fn test_parallel_gzip_compress_output_does_not_depend_on_thread_count() {
+    let input_data = parallel_test_data();
+    let compressor = ParallelCompressor::new(CompressionLvl::default()).chunk_size(10_000);
+
+    let single_threaded = compressor.clone().threads(1).gzip_compress(&input_data);
+    let multi_threaded = compressor.threads(8).gzip_compress(&input_data);
+
+    assert_eq!(single_threaded, multi_threaded);
+}
+
+
+// compressor/decompressor pools
+
+#[test]
+fn test_compressor_pool_reuses_returned_compressors() {
+    let pool = CompressorPool::new();
+    drop(pool.get(CompressionLvl::best()));
+    assert_eq!(pool.idle(), 1);
+
+    let _compressor = pool.get(CompressionLvl::best());
+    assert_eq!(pool.idle(), 0);
+}
+
+#[test]
+fn test_compressor_pool_keeps_compressors_for_each_compression_lvl_separately() {
+    let pool = CompressorPool::new();
+    drop(pool.get(CompressionLvl::best()));
+
+    let compressor = pool.get(CompressionLvl::fastest());
+    assert_eq!(compressor.lvl(), CompressionLvl::fastest());
+    assert_eq!(pool.idle(), 1);
+}
+
+#[test]
+fn test_compressor_pool_drops_compressors_beyond_max_idle() {
+    let pool = CompressorPool::with_max_idle(2);
+    let compressors: Vec<_> = (0..3).map(|_| pool.get(CompressionLvl::default())).collect();
+    drop(compressors);
+
+    assert_eq!(pool.idle(), 2);
+    pool.clear();
+    assert_eq!(pool.idle(), 0);
+}
+
+#[test]
+fn test_detached_compressor_is_not_returned_to_pool() {
+    let pool = CompressorPool::new();
+    let compressor = pool.get(CompressionLvl::default()).detach();
+    drop(compressor);
+
+    assert_eq!(pool.idle(), 0);
+}
+
+#[test]
+fn test_pools_can_be_used_from_many_threads_at_once() {
+    let compressors = CompressorPool::with_max_idle(4);
+    let decompressors = DecompressorPool::with_max_idle(4);
+    let in_data = read_fixture_content();
+
+    thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                for lvl in CompressionLvl::iter() {
+                    let gz_data = compressors.gzip_compress_to_vec(lvl, &in_data).unwrap();
+                    assert_eq!(decompressors.gzip_decompress_to_vec(&gz_data, 1 << 20).unwrap(), in_data);
+                }
+            });
+        }
+    });
+
+    assert!(decompressors.idle() <= 4);
+    assert!(decompressors.idle() >= 1);
+}
+
+#[test]
+fn test_pool_one_liners_roundtrip_each_format() {
+    let compressors = CompressorPool::new();
+    let decompressors = DecompressorPool::new();
+    let in_data = read_fixture_content();
+    let lvl = CompressionLvl::default();
+
+    let deflate_data = compressors.deflate_compress_to_vec(lvl, &in_data).unwrap();
+    assert_eq!(decompressors.deflate_decompress_to_vec(&deflate_data, 1 << 20).unwrap(), in_data);
+    let zlib_data = compressors.zlib_compress_to_vec(lvl, &in_data).unwrap();
+    assert_eq!(decompressors.zlib_decompress_to_vec(&zlib_data, 1 << 20).unwrap(), in_data);
+    let gz_data = compressors.gzip_compress_to_vec(lvl, &in_data).unwrap();
+    assert_eq!(decompressors.gzip_decompress_to_vec(&gz_data, 1 << 20).unwrap(), in_data);
+}
+
+#[test]
+fn test_decompressor_pool_drops_decompressors_beyond_max_idle() {
+    let pool = DecompressorPool::with_max_idle(1);
+    let first = pool.get();
+    let second = pool.try_get().unwrap();
+    drop(first);
+    drop(second);
+
+    assert_eq!(pool.idle(), 1);
+}
+
+
+
+// compression
+
+#[test]
+fn test_compression_lvl_new_returns_ok_for_0() {
+    // see issuse #21 - some use-cases require uncompressed blocks
+    CompressionLvl::new(0).unwrap();
+}
+
+#[test]
+fn test_compression_lvl_new_returns_ok_for_7() {
+    CompressionLvl::new(7).unwrap();
+}
+
+#[test]
+fn test_compression_lvl_new_returns_err_for_13() {
+    // If libdeflate changes in the future this test will need to be
+    // scrapped, but it's ok as a smoke test
+    let ret = CompressionLvl::new(13).unwrap_err();
+
+    assert_eq!(ret, CompressionLvlError::InvalidValue);
+}
+
+#[test]
+fn test_compressor_with_fastest_compression_lvl_calls_with_no_panics() {
+    Compressor::new(CompressionLvl::fastest());
+}
+
+#[test]
+fn test_compressor_with_default_compression_lvl_calls_with_no_panics() {
+    Compressor::new(CompressionLvl::default());
+}
+
+#[test]
+fn test_compressor_try_new_returns_ok_for_every_compression_lvl() {
+    for lvl in CompressionLvl::iter() {
+        Compressor::try_new(lvl).unwrap();
+    }
+}
+
+#[test]
+fn test_compressor_from_try_new_compresses_data() {
+    let in_data = read_fixture_content();
+    let mut compressor = Compressor::try_new(CompressionLvl::best()).unwrap();
+    let compressed = compressor.gzip_compress_to_vec(&in_data).unwrap();
+
+    let mut decompressor = Decompressor::try_new().unwrap();
+    assert_eq!(decompressor.gzip_decompress_to_vec(&compressed, 1 << 20).unwrap(), in_data);
+}
+
+#[test]
+fn test_compressor_with_best_compression_lvl_calls_with_no_panics() {
+    Compressor::new(CompressionLvl::best());
+}
+
+#[test]
+fn test_compression_iter_starts_at_compression_lvl_zero() {
+    let val = CompressionLvl::iter().next().unwrap();
+    assert_eq!(val, CompressionLvl::fastest());
+    assert_eq!(val, CompressionLvl::new(0).unwrap());
+}
+
+#[test]
+fn test_compressor_with_compression_lvl_can_be_called_with_all_lvls_with_no_panics() {
+    for lvl in CompressionLvl::iter() {
+        Compressor::new(lvl);
+    }
+}
+
+
+// compress DEFLATE
+
+#[test]
+fn test_compressor_deflate_compress_bound_returns_nonzero_when_given_nonzero_input() {
+    let returned_bound = {
+        let mut compressor = Compressor::new(CompressionLvl::default());
+        compressor.deflate_compress_bound(1)
+    };
+
+    assert_ne!(returned_bound, 0);
+}
+
+#[test]
+fn test_compressor_deflate_compress_with_valid_args_does_not_panic() {
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let in_data = read_fixture_content();
+    let mut out_data = Vec::new();
+    let out_max_sz = compressor.deflate_compress_bound(in_data.len());
+    out_data.resize(out_max_sz, 0);
+
+    compressor.deflate_compress(&in_data, &mut out_data).unwrap();
+}
+
+#[test]
+fn test_compressor_deflate_compress_with_undersized_buf_returns_insufficient_space() {
+    let ret = {
+        let mut compressor = Compressor::new(CompressionLvl::default());
+        let in_data = read_fixture_content();
+        let mut out_data = Vec::new();
+        compressor.deflate_compress(&in_data, &mut out_data)
+    };
+
+    assert_eq!(ret.unwrap_err(), CompressionError::InsufficientSpace);
+}
+
+#[test]
+fn test_compressor_deflate_compress_with_correct_args_returns_a_nonzero_size() {
+    let ret = {
+        let mut compressor = Compressor::new(CompressionLvl::default());
+        let in_data = read_fixture_content();
+        let mut out_data = Vec::new();
+        let out_max_sz = compressor.deflate_compress_bound(in_data.len());
+        out_data.resize(out_max_sz, 0);
+        compressor.deflate_compress(&in_data, &mut out_data).unwrap()
+    };
+
+    assert_ne!(0, ret);
+}
+
+#[test]
+fn test_compressor_deflate_compress_with_correct_args_resized_outbuf_to_return_val_produces_nonempty_nonzero_data() {
+    // This is, give or take, essentially how users *should* use the
+    // lib for raw DEFLATE compressions
+
+    let compressed_data = {
+        let mut compressor = Compressor::new(CompressionLvl::default());
+        let in_data = read_fixture_content();
+        let mut out_data = Vec::new();
+        let out_max_sz = compressor.deflate_compress_bound(in_data.len());
+        out_data.resize(out_max_sz, 0);
+        let sz = compressor.deflate_compress(&in_data, &mut out_data).unwrap();
+        out_data.resize(sz, 0);
+        out_data
+    };
+
+    assert_ne!(compressed_data.len(), 0);
+    assert!(compressed_data.iter().any(|byte| *byte != 0x00));
+}
+
+
+// compress zlib
+
+#[test]
+fn test_compressor_zlib_compress_bound_returns_nonzero_when_given_nonzero_input() {
+    let returned_bound = {
+        let mut compressor = Compressor::new(CompressionLvl::default());
+        compressor.zlib_compress_bound(1)
+    };
+
+    assert_ne!(returned_bound, 0);
+}
+
+#[test]
+fn test_compressor_compresss_zlib_with_valid_args_does_not_panic() {
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let in_data = read_fixture_content();
+    let mut out_data = Vec::new();
+    let out_max_sz = compressor.zlib_compress_bound(in_data.len());
+    out_data.resize(out_max_sz, 0);
+
+    compressor.zlib_compress(&in_data, &mut out_data).unwrap();
+}
+
+#[test]
+fn test_compressor_zlib_compress_with_undersized_outbuf_returns_insufficient_space() {
+    let ret = {
+        let mut compressor = Compressor::new(CompressionLvl::default());
+        let in_data = read_fixture_content();
+        let mut out_data = Vec::new();
+        compressor.zlib_compress(&in_data, &mut out_data)
+    };
+
+    assert_eq!(ret.unwrap_err(), CompressionError::InsufficientSpace);
+}
+
+#[test]
+fn test_compressor_zlib_compress_with_correct_args_returns_nonzero_size() {
+    let ret = {
+        let mut compressor = Compressor::new(CompressionLvl::default());
+        let in_data = read_fixture_content();
+        let mut out_data = Vec::new();
+        let out_max_sz = compressor.zlib_compress_bound(in_data.len());
+        out_data.resize(out_max_sz, 0);
+
+        compressor.zlib_compress(&in_data, &mut out_data).unwrap()
+    };
+
+    assert_ne!(ret, 0);
+}
+
+#[test]
+fn test_compressor_zlib_compress_with_correct_args_resized_outbuf_to_return_val_produces_nonempty_nonzero_data() {
+    // This is, give or take, essentially how users *should* use the
+    // lib for zlib compressions
+
+    let compressed_data = {
+        let mut compressor = Compressor::new(CompressionLvl::default());
+        let in_data = read_fixture_content();
+        let mut out_data = Vec::new();
+        let out_max_sz = compressor.zlib_compress_bound(in_data.len());
+        out_data.resize(out_max_sz, 0);
+        let sz = compressor.zlib_compress(&in_data, &mut out_data).unwrap();
+        out_data.resize(sz, 0);
+        out_data
+    };
+
+    assert_ne!(compressed_data.len(), 0);
+    assert!(compressed_data.iter().any(|byte| *byte != 0x00));
+}
+
+
+// compress gzip
+
+#[test]
+fn test_compressor_gzip_compress_bound_returns_nonzero_when_given_nonzero_input() {
+    let returned_bound = {
+        let mut compressor = Compressor::new(CompressionLvl::default());
+        compressor.gzip_compress_bound(1)
+    };
+
+    assert_ne!(returned_bound, 0);
+}
+
+#[test]
+fn test_compressor_compresss_gzip_with_valid_args_does_not_panic() {
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let in_data = read_fixture_content();
+    let mut out_data = Vec::new();
     let out_max_sz = compressor.gzip_compress_bound(in_data.len());
     out_data.resize(out_max_sz, 0);
 
-    compressor.gzip_compress(&in_data, &mut out_data).unwrap();
+    compressor.gzip_compress(&in_data, &mut out_data).unwrap();
+}
+
+#[test]
+fn test_compressor_gzip_compress_with_undersized_outbuf_returns_insufficient_space() {
+    let ret = {
+        let mut compressor = Compressor::new(CompressionLvl::default());
+        let in_data = read_fixture_content();
+        let mut out_data = Vec::new();
+        compressor.gzip_compress(&in_data, &mut out_data)
+    };
+
+    assert_eq!(ret.unwrap_err(), CompressionError::InsufficientSpace);
+}
+
+#[test]
+fn test_compressor_gzip_compress_with_correct_args_returns_nonzero_size() {
+    let ret = {
+        let mut compressor = Compressor::new(CompressionLvl::default());
+        let in_data = read_fixture_content();
+        let mut out_data = Vec::new();
+        let out_max_sz = compressor.gzip_compress_bound(in_data.len());
+        out_data.resize(out_max_sz, 0);
+
+        compressor.gzip_compress(&in_data, &mut out_data).unwrap()
+    };
+
+    assert_ne!(ret, 0);
+}
+
+#[test]
+fn test_compressor_gzip_compress_with_correct_args_resized_outbuf_to_return_val_produces_nonempty_nonzero_data() {
+    // This is, give or take, essentially how users *should* use the
+    // lib for gzip compressions
+
+    let compressed_data = {
+        let mut compressor = Compressor::new(CompressionLvl::default());
+        let in_data = read_fixture_content();
+        let mut out_data = Vec::new();
+        let out_max_sz = compressor.gzip_compress_bound(in_data.len());
+        out_data.resize(out_max_sz, 0);
+        let sz = compressor.gzip_compress(&in_data, &mut out_data).unwrap();
+        out_data.resize(sz, 0);
+        out_data
+    };
+
+    assert_ne!(compressed_data.len(), 0);
+    assert!(compressed_data.iter().any(|byte| *byte != 0x00));
+}
+
+
+// *_compress_to_vec and *_compress_append
+
+type CompressFn = fn(&mut Compressor, &[u8], &mut [u8]) -> Result<usize, CompressionError>;
+
+fn compress_into_bounded_buf(compress: CompressFn,
+                             bound: fn(&mut Compressor, usize) -> usize,
+                             in_data: &[u8]) -> Vec<u8> {
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let mut out = vec![0; bound(&mut compressor, in_data.len())];
+    let sz = compress(&mut compressor, in_data, &mut out).unwrap();
+    out.truncate(sz);
+    out
+}
+
+#[test]
+fn test_deflate_compress_to_vec_produces_the_same_data_as_deflate_compress() {
+    let in_data = read_fixture_content();
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let compressed = compressor.deflate_compress_to_vec(&in_data).unwrap();
+
+    assert_eq!(compressed, compress_into_bounded_buf(Compressor::deflate_compress, Compressor::deflate_compress_bound, &in_data));
+}
+
+#[test]
+fn test_zlib_compress_to_vec_produces_the_same_data_as_zlib_compress() {
+    let in_data = read_fixture_content();
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let compressed = compressor.zlib_compress_to_vec(&in_data).unwrap();
+
+    assert_eq!(compressed, compress_into_bounded_buf(Compressor::zlib_compress, Compressor::zlib_compress_bound, &in_data));
+}
+
+#[test]
+fn test_gzip_compress_to_vec_produces_the_same_data_as_gzip_compress() {
+    let in_data = read_fixture_content();
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let compressed = compressor.gzip_compress_to_vec(&in_data).unwrap();
+
+    assert_eq!(compressed, compress_into_bounded_buf(Compressor::gzip_compress, Compressor::gzip_compress_bound, &in_data));
+}
+
+#[test]
+fn test_gzip_compress_to_vec_with_empty_input_produces_valid_gzip_data() {
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let compressed = compressor.gzip_compress_to_vec(&[]).unwrap();
+
+    let mut decompressor = Decompressor::new();
+    assert_eq!(decompressor.gzip_decompress_to_vec(&compressed, 0).unwrap(), Vec::<u8>::new());
+}
+
+#[test]
+fn test_gzip_compress_append_appends_compressed_data_after_existing_data() {
+    let in_data = read_fixture_content();
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let mut out = b"prefix".to_vec();
+    let sz = compressor.gzip_compress_append(&in_data, &mut out).unwrap();
+
+    assert_eq!(out.len(), "prefix".len() + sz);
+    assert_eq!(&out[..6], b"prefix");
+    let mut decompressor = Decompressor::new();
+    assert_eq!(decompressor.gzip_decompress_to_vec(&out[6..], 1 << 20).unwrap(), in_data);
+}
+
+#[test]
+fn test_calling_compress_append_repeatedly_concatenates_streams() {
+    let in_data = read_fixture_content();
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let mut out = Vec::new();
+    compressor.gzip_compress_append(&in_data, &mut out).unwrap();
+    compressor.gzip_compress_append(&in_data, &mut out).unwrap();
+
+    let mut decompressor = Decompressor::new();
+    let mut decompressed = vec![0; 2 * in_data.len()];
+    let members = decompressor.gzip_decompress_multi(&out, &mut decompressed).unwrap();
+    assert_eq!(members.len(), 2);
+    assert_eq!(decompressed, [&in_data[..], &in_data[..]].concat());
+}
+
+#[test]
+fn test_deflate_and_zlib_compress_append_output_can_be_decompressed() {
+    let in_data = read_fixture_content();
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let mut decompressor = Decompressor::new();
+
+    let mut deflate_data = Vec::new();
+    compressor.deflate_compress_append(&in_data, &mut deflate_data).unwrap();
+    assert_eq!(decompressor.deflate_decompress_to_vec(&deflate_data, 1 << 20).unwrap(), in_data);
+
+    let mut zlib_data = Vec::new();
+    compressor.zlib_compress_append(&in_data, &mut zlib_data).unwrap();
+    assert_eq!(decompressor.zlib_decompress_to_vec(&zlib_data, 1 << 20).unwrap(), in_data);
+}
+
+
+
+// format-generic API (Format and Codec)
+
+const FORMATS: [Format; 3] = [Format::Gzip, Format::Zlib, Format::Deflate];
+
+#[test]
+fn test_compress_bound_matches_format_specific_bounds() {
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let n = fixture_content_size();
+
+    assert_eq!(compressor.compress_bound(Format::Gzip, n), compressor.gzip_compress_bound(n));
+    assert_eq!(compressor.compress_bound(Format::Zlib, n), compressor.zlib_compress_bound(n));
+    assert_eq!(compressor.compress_bound(Format::Deflate, n), compressor.deflate_compress_bound(n));
+}
+
+#[test]
+fn test_compress_with_format_matches_format_specific_compress() {
+    let in_data = read_fixture_content();
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let mut out = vec![0; compressor.gzip_compress_bound(in_data.len())];
+    let mut expected = vec![0; compressor.gzip_compress_bound(in_data.len())];
+
+    let sz = compressor.compress(Format::Gzip, &in_data, &mut out).unwrap();
+    let expected_sz = compressor.gzip_compress(&in_data, &mut expected).unwrap();
+
+    assert_eq!(&out[..sz], &expected[..expected_sz]);
+}
+
+#[test]
+fn test_compress_then_decompress_with_each_format_returns_original_data() {
+    let in_data = read_fixture_content();
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let mut decompressor = Decompressor::new();
+
+    for format in FORMATS {
+        let mut compressed = vec![0; compressor.compress_bound(format, in_data.len())];
+        let compressed_sz = compressor.compress(format, &in_data, &mut compressed).unwrap();
+        let mut decompressed = vec![0; in_data.len()];
+        let sz = decompressor.decompress(format, &compressed[..compressed_sz], &mut decompressed).unwrap();
+
+        assert_eq!(sz, in_data.len());
+        assert_eq!(decompressed, in_data);
+    }
+}
+
+#[test]
+fn test_decompress_with_the_wrong_format_returns_an_error() {
+    let mut decompressor = Decompressor::new();
+    let mut decompressed = vec![0; fixture_content_size()];
+
+    assert!(decompressor.decompress(Format::Zlib, &read_fixture_gz(), &mut decompressed).is_err());
+}
+
+#[test]
+fn test_compress_to_vec_and_decompress_to_vec_with_each_format_roundtrip_data() {
+    let in_data = read_fixture_content();
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let mut decompressor = Decompressor::new();
+
+    for format in FORMATS {
+        let compressed = compressor.compress_to_vec(&format, &in_data).unwrap();
+
+        assert_eq!(decompressor.decompress_to_vec(&format, &compressed, 1 << 20).unwrap(), in_data);
+    }
 }
 
 #[test]
-fn test_compressor_gzip_compress_with_undersized_outbuf_returns_insufficient_space() {
-    let ret = {
-        let mut compressor = Compressor::new(CompressionLvl::default());
-        let in_data = read_fixture_content();
-        let mut out_data = Vec::new();
-        compressor.gzip_compress(&in_data, &mut out_data)
-    };
+fn test_decompress_to_vec_with_format_returns_output_limit_exceeded_when_limit_is_too_small() {
+    let mut decompressor = Decompressor::new();
+    let result = decompressor.decompress_to_vec(&Format::Gzip, &read_fixture_gz(), fixture_content_size() - 1);
 
-    assert_eq!(ret.unwrap_err(), CompressionError::InsufficientSpace);
+    assert_eq!(result.unwrap_err(), DecompressionError::OutputLimitExceeded);
+}
+
+/// A user-defined container: DEFLATE data prefixed with its
+/// decompressed size.
+struct SizePrefixed;
+
+impl Codec for SizePrefixed {
+    fn compress_bound(&self, compressor: &mut Compressor, n_bytes: usize) -> usize {
+        4 + compressor.deflate_compress_bound(n_bytes)
+    }
+
+    fn compress(&self, compressor: &mut Compressor, in_raw_data: &[u8], out: &mut [u8]) -> CompressionResult<usize> {
+        let sz = compressor.deflate_compress(in_raw_data, &mut out[4..])?;
+        out[..4].copy_from_slice(&(in_raw_data.len() as u32).to_le_bytes());
+        Ok(4 + sz)
+    }
+
+    fn decompress(&self, decompressor: &mut Decompressor, in_data: &[u8], out: &mut [u8]) -> DecompressionResult<usize> {
+        let size = self.decompressed_size_hint(in_data);
+        let out = out.get_mut(..size).ok_or(DecompressionError::InsufficientSpace)?;
+        decompressor.deflate_decompress_exact(&in_data[4..], out)?;
+        Ok(size)
+    }
+
+    fn decompressed_size_hint(&self, in_data: &[u8]) -> usize {
+        u32::from_le_bytes([in_data[0], in_data[1], in_data[2], in_data[3]]) as usize
+    }
 }
 
 #[test]
-fn test_compressor_gzip_compress_with_correct_args_returns_nonzero_size() {
-    let ret = {
-        let mut compressor = Compressor::new(CompressionLvl::default());
-        let in_data = read_fixture_content();
-        let mut out_data = Vec::new();
-        let out_max_sz = compressor.gzip_compress_bound(in_data.len());
-        out_data.resize(out_max_sz, 0);
+fn test_compress_to_vec_and_decompress_to_vec_with_custom_codec_roundtrip_data() {
+    let in_data = read_fixture_content();
+    let compressed = Compressor::new(CompressionLvl::default()).compress_to_vec(&SizePrefixed, &in_data).unwrap();
 
-        compressor.gzip_compress(&in_data, &mut out_data).unwrap()
-    };
+    assert_eq!(&compressed[..4], &(in_data.len() as u32).to_le_bytes());
+    assert_eq!(Decompressor::new().decompress_to_vec(&SizePrefixed, &compressed, 1 << 20).unwrap(), in_data);
+}
 
-    assert_ne!(ret, 0);
+#[test]
+fn test_codecs_can_be_used_as_trait_objects() {
+    let in_data = read_fixture_content();
+    let codecs: Vec<Box<dyn Codec>> = vec![Box::new(Format::Zlib), Box::new(SizePrefixed)];
+
+    for codec in &codecs {
+        let compressed = Compressor::new(CompressionLvl::default()).compress_to_vec(&**codec, &in_data).unwrap();
+
+        assert_eq!(Decompressor::new().decompress_to_vec(&**codec, &compressed, 1 << 20).unwrap(), in_data);
+    }
 }
 
 #[test]
-fn test_compressor_gzip_compress_with_correct_args_resized_outbuf_to_return_val_produces_nonempty_nonzero_data() {
-    // This is, give or take, essentially how users *should* use the
-    // lib for gzip compressions
+fn test_pools_can_roundtrip_data_with_custom_codec() {
+    let in_data = read_fixture_content();
+    let compressors = CompressorPool::new();
+    let decompressors = DecompressorPool::new();
+    let compressed = compressors.compress_to_vec(&SizePrefixed, CompressionLvl::default(), &in_data).unwrap();
 
-    let compressed_data = {
-        let mut compressor = Compressor::new(CompressionLvl::default());
-        let in_data = read_fixture_content();
-        let mut out_data = Vec::new();
-        let out_max_sz = compressor.gzip_compress_bound(in_data.len());
-        out_data.resize(out_max_sz, 0);
-        let sz = compressor.gzip_compress(&in_data, &mut out_data).unwrap();
-        out_data.resize(sz, 0);
-        out_data
-    };
+    assert_eq!(decompressors.decompress_to_vec(&SizePrefixed, &compressed, 1 << 20).unwrap(), in_data);
+    assert_eq!(compressors.idle(), 1);
+    assert_eq!(decompressors.idle(), 1);
+}
 
-    assert_ne!(compressed_data.len(), 0);
-    assert!(compressed_data.iter().any(|byte| *byte != 0x00));
+/// A broken codec that claims to have written more bytes than fit in
+/// the output buffer.
+struct OverreportingCodec;
+
+impl Codec for OverreportingCodec {
+    fn compress_bound(&self, _compressor: &mut Compressor, n_bytes: usize) -> usize {
+        n_bytes
+    }
+
+    fn compress(&self, _compressor: &mut Compressor, _in_raw_data: &[u8], _out: &mut [u8]) -> CompressionResult<usize> {
+        Ok(usize::MAX)
+    }
+
+    fn decompress(&self, _decompressor: &mut Decompressor, _in_data: &[u8], out: &mut [u8]) -> DecompressionResult<usize> {
+        Ok(out.len() + 1)
+    }
+}
+
+#[test]
+#[should_panic(expected = "more bytes than fit in the output buffer")]
+fn test_compress_to_vec_panics_if_codec_overreports_its_output_size() {
+    let _ = Compressor::new(CompressionLvl::default()).compress_to_vec(&OverreportingCodec, b"hello");
+}
+
+#[test]
+#[should_panic(expected = "more bytes than fit in the output buffer")]
+fn test_decompress_to_vec_panics_if_codec_overreports_its_output_size() {
+    let _ = Decompressor::new().decompress_to_vec(&OverreportingCodec, b"hello", 1 << 20);
+}
+
+
+
+// container framing (gzip, zlib, and in-house containers)
+
+#[test]
+fn test_compressor_lvl_returns_construction_lvl() {
+    for lvl in CompressionLvl::iter() {
+        assert_eq!(Compressor::new(lvl).lvl(), lvl);
+    }
+}
+
+#[test]
+fn test_gzip_framing_output_is_identical_to_gzip_compress() {
+    let in_data = read_fixture_content();
+
+    for lvl in CompressionLvl::iter() {
+        let mut compressor = Compressor::new(lvl);
+        let expected = compressor.gzip_compress_to_vec(&in_data).unwrap();
+        let actual = compressor.compress_to_vec(&GzipFraming::new(), &in_data).unwrap();
+
+        assert_eq!(actual, expected, "lvl = {:?}", lvl);
+    }
+}
+
+#[test]
+fn test_zlib_framing_output_is_identical_to_zlib_compress() {
+    let in_data = read_fixture_content();
+
+    for lvl in CompressionLvl::iter() {
+        let mut compressor = Compressor::new(lvl);
+        let expected = compressor.zlib_compress_to_vec(&in_data).unwrap();
+        let actual = compressor.compress_to_vec(&ZlibFraming, &in_data).unwrap();
+
+        assert_eq!(actual, expected, "lvl = {:?}", lvl);
+    }
+}
+
+#[test]
+fn test_gzip_framing_with_header_output_is_identical_to_gzip_compress_with_header() {
+    let in_data = read_fixture_content();
+    let header = GzipHeader { filename: Some(b"hello".to_vec()), mtime: 1234, header_crc: true, ..GzipHeader::default() };
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let mut expected = vec![0; compressor.gzip_compress_with_header_bound(&header, in_data.len())];
+    let sz = compressor.gzip_compress_with_header(&header, &in_data, &mut expected).unwrap();
+    expected.truncate(sz);
+
+    let framing = GzipFraming::with_header(header.clone());
+    assert_eq!(framing.header(), Some(&header));
+    assert_eq!(compressor.compress_to_vec(&framing, &in_data).unwrap(), expected);
+}
+
+#[test]
+fn test_container_compress_bound_is_header_plus_deflate_bound_plus_trailer() {
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let deflate_bound = compressor.deflate_compress_bound(1000);
+
+    assert_eq!(container::compress_bound(&GzipFraming::new(), &mut compressor, 1000), 10 + deflate_bound + 8);
+    assert_eq!(container::compress_bound(&ZlibFraming, &mut compressor, 1000), 2 + deflate_bound + 4);
+    assert_eq!(container::compress_bound(&Ldf1, &mut compressor, 1000), 9 + deflate_bound + 4);
+}
+
+#[test]
+fn test_container_compress_with_too_small_output_fails() {
+    let in_data = read_fixture_content();
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let mut out = vec![0; 11];
+
+    assert_eq!(container::compress(&GzipFraming::new(), &mut compressor, &in_data, &mut out), Err(CompressionError::InsufficientSpace));
+    assert_eq!(container::compress(&GzipFraming::new(), &mut compressor, &in_data, &mut []), Err(CompressionError::InsufficientSpace));
+}
+
+#[test]
+fn test_framings_can_decompress_libdeflate_output() {
+    let in_data = read_fixture_content();
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let mut decompressor = Decompressor::new();
+    let mut out = vec![0; in_data.len()];
+
+    let gz_data = compressor.gzip_compress_to_vec(&in_data).unwrap();
+    let outcome = container::decompress(&GzipFraming::new(), &mut decompressor, &gz_data, &mut out).unwrap();
+    assert_eq!(outcome, DecompressOutcome { consumed: gz_data.len(), produced: in_data.len() });
+    assert_eq!(out, in_data);
+
+    let zlib_data = compressor.zlib_compress_to_vec(&in_data).unwrap();
+    let outcome = container::decompress(&ZlibFraming, &mut decompressor, &zlib_data, &mut out).unwrap();
+    assert_eq!(outcome, DecompressOutcome { consumed: zlib_data.len(), produced: in_data.len() });
+    assert_eq!(out, in_data);
+}
+
+#[test]
+fn test_container_decompress_reports_consumed_bytes_before_trailing_data() {
+    let in_data = read_fixture_content();
+    let mut compressed = Compressor::new(CompressionLvl::default()).compress_to_vec(&Ldf1, &in_data).unwrap();
+    let container_len = compressed.len();
+    compressed.extend_from_slice(b"trailing data");
+    let mut out = vec![MaybeUninit::uninit(); in_data.len()];
+
+    let outcome = container::decompress_uninit(&Ldf1, &mut Decompressor::new(), &compressed, &mut out).unwrap();
+    assert_eq!(outcome, DecompressOutcome { consumed: container_len, produced: in_data.len() });
+}
+
+#[test]
+fn test_gzip_framing_reports_crc_and_size_mismatches() {
+    let in_data = read_fixture_content();
+    let compressed = Compressor::new(CompressionLvl::default()).compress_to_vec(&GzipFraming::new(), &in_data).unwrap();
+    let mut decompressor = Decompressor::new();
+    let mut out = vec![0; in_data.len()];
+    let n = compressed.len();
+
+    let mut bad_crc = compressed.clone();
+    bad_crc[n - 8] ^= 0xff;
+    assert!(matches!(container::decompress(&GzipFraming::new(), &mut decompressor, &bad_crc, &mut out),
+                     Err(DecompressionError::CrcMismatch { .. })));
+
+    let mut bad_size = compressed.clone();
+    bad_size[n - 4] ^= 0xff;
+    assert!(matches!(container::decompress(&GzipFraming::new(), &mut decompressor, &bad_size, &mut out),
+                     Err(DecompressionError::SizeMismatch { .. })));
+
+    assert_eq!(container::decompress(&GzipFraming::new(), &mut decompressor, &compressed[..n - 1], &mut out),
+               Err(DecompressionError::Truncated));
+    assert_eq!(container::decompress(&GzipFraming::new(), &mut decompressor, &compressed[1..], &mut out),
+               Err(DecompressionError::BadMagic));
+}
+
+#[test]
+fn test_zlib_framing_reports_adler32_mismatch() {
+    let in_data = read_fixture_content();
+    let mut compressed = Compressor::new(CompressionLvl::default()).compress_to_vec(&ZlibFraming, &in_data).unwrap();
+    let n = compressed.len();
+    compressed[n - 1] ^= 0xff;
+
+    assert!(matches!(Decompressor::new().decompress_to_vec(&ZlibFraming, &compressed, 1 << 20),
+                     Err(DecompressionError::Adler32Mismatch { .. })));
+}
+
+/// An in-house container: the magic bytes `LDF`, a version byte, the
+/// uncompressed length (u32 LE), the DEFLATE data, and the CRC32 of
+/// the uncompressed data (u32 LE).
+struct Ldf1;
+
+impl Framing for Ldf1 {
+    type Checksum = libdeflater::Crc;
+
+    fn header_len(&self) -> usize {
+        9
+    }
+
+    fn write_header(&self, _lvl: CompressionLvl, raw_len: usize, out: &mut [u8]) {
+        out[..4].copy_from_slice(b"LDF\x01");
+        out[4..8].copy_from_slice(&(raw_len as u32).to_le_bytes());
+        out[8] = 0;
+    }
+
+    fn trailer_len(&self) -> usize {
+        4
+    }
+
+    fn write_trailer(&self, checksum: u32, _raw_len: usize, out: &mut [u8]) {
+        out.copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    fn read_header(&self, in_data: &[u8]) -> DecompressionResult<usize> {
+        let header = in_data.get(..9).ok_or(DecompressionError::Truncated)?;
+        if &header[..3] != b"LDF" {
+            return Err(DecompressionError::BadMagic);
+        }
+        if header[3] != 1 {
+            return Err(DecompressionError::BadData);
+        }
+        Ok(9)
+    }
+
+    fn check_trailer(&self, header: &[u8], trailer: &[u8], checksum: u32, raw_len: usize) -> DecompressionResult<()> {
+        let expected = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        if expected != checksum {
+            return Err(DecompressionError::CrcMismatch { expected, actual: checksum });
+        }
+        let expected = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if expected != raw_len as u32 {
+            return Err(DecompressionError::SizeMismatch { expected, actual: raw_len as u32 });
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_custom_framing_roundtrips_data() {
+    let in_data = read_fixture_content();
+    let compressed = Compressor::new(CompressionLvl::best()).compress_to_vec(&Ldf1, &in_data).unwrap();
+
+    assert_eq!(&compressed[..4], b"LDF\x01");
+    assert_eq!(&compressed[4..8], &(in_data.len() as u32).to_le_bytes());
+    assert_eq!(&compressed[compressed.len() - 4..], &libdeflater::crc32(&in_data).to_le_bytes());
+    assert_eq!(Decompressor::new().decompress_to_vec(&Ldf1, &compressed, 1 << 20).unwrap(), in_data);
+}
+
+#[test]
+fn test_custom_framing_rejects_bad_header_and_trailer() {
+    let in_data = read_fixture_content();
+    let compressed = Compressor::new(CompressionLvl::default()).compress_to_vec(&Ldf1, &in_data).unwrap();
+    let mut decompressor = Decompressor::new();
+    let mut out = vec![0; in_data.len() + 1];
+
+    let mut bad_version = compressed.clone();
+    bad_version[3] = 2;
+    assert_eq!(Ldf1.decompress(&mut decompressor, &bad_version, &mut out), Err(DecompressionError::BadData));
+
+    let mut bad_length = compressed.clone();
+    bad_length[4] ^= 0xff;
+    assert!(matches!(Ldf1.decompress(&mut decompressor, &bad_length, &mut out),
+                     Err(DecompressionError::SizeMismatch { .. })));
+
+    assert_eq!(Ldf1.decompress(&mut decompressor, b"LDF", &mut out), Err(DecompressionError::Truncated));
+}
+
+#[test]
+fn test_pools_can_roundtrip_data_with_custom_framing() {
+    let in_data = read_fixture_content();
+    let compressed = CompressorPool::new().compress_to_vec(&Ldf1, CompressionLvl::fastest(), &in_data).unwrap();
+
+    assert_eq!(DecompressorPool::new().decompress_to_vec(&Ldf1, &compressed, 1 << 20).unwrap(), in_data);
+}
+
+
+
+// custom allocators
+
+/// Allocates with the system allocator while counting the number of
+/// allocations and the number of live bytes.
+#[derive(Default)]
+struct CountingAllocator {
+    allocations: AtomicUsize,
+    live_bytes: AtomicUsize,
+}
+
+unsafe impl Allocator for CountingAllocator {
+    fn allocate(&self, layout: Layout) -> *mut u8 {
+        self.allocations.fetch_add(1, Ordering::SeqCst);
+        self.live_bytes.fetch_add(layout.size(), Ordering::SeqCst);
+        System.allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        self.live_bytes.fetch_sub(layout.size(), Ordering::SeqCst);
+        System.deallocate(ptr, layout)
+    }
+}
+
+/// An allocator that always fails.
+struct FailingAllocator;
+
+unsafe impl Allocator for FailingAllocator {
+    fn allocate(&self, _layout: Layout) -> *mut u8 {
+        std::ptr::null_mut()
+    }
+
+    unsafe fn deallocate(&self, _ptr: *mut u8, _layout: Layout) {
+        unreachable!("nothing was allocated");
+    }
+}
+
+#[test]
+fn test_compressor_with_allocator_allocates_with_it_and_frees_on_drop() {
+    let allocator = Arc::new(CountingAllocator::default());
+    let compressor = Compressor::with_allocator(CompressionLvl::best(), allocator.clone());
+
+    assert!(allocator.allocations.load(Ordering::SeqCst) > 0);
+    assert!(allocator.live_bytes.load(Ordering::SeqCst) > 0);
+    drop(compressor);
+    assert_eq!(allocator.live_bytes.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn test_decompressor_with_allocator_allocates_with_it_and_frees_on_drop() {
+    let allocator = Arc::new(CountingAllocator::default());
+    let decompressor = Decompressor::with_allocator(allocator.clone());
+
+    assert!(allocator.allocations.load(Ordering::SeqCst) > 0);
+    assert!(allocator.live_bytes.load(Ordering::SeqCst) > 0);
+    drop(decompressor);
+    assert_eq!(allocator.live_bytes.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn test_compressor_and_decompressor_with_allocator_can_roundtrip_data() {
+    let in_data = read_fixture_content();
+    let mut compressor = Compressor::with_allocator(CompressionLvl::default(), System);
+    let compressed = compressor.gzip_compress_to_vec(&in_data).unwrap();
+
+    let mut decompressor = Decompressor::with_allocator(System);
+    assert_eq!(decompressor.gzip_decompress_to_vec(&compressed, 1 << 20).unwrap(), in_data);
+}
+
+#[test]
+fn test_compressors_with_allocator_can_be_dropped_on_another_thread() {
+    let allocator = Arc::new(CountingAllocator::default());
+    let compressor = Compressor::with_allocator(CompressionLvl::default(), allocator.clone());
+    thread::spawn(move || drop(compressor)).join().unwrap();
+
+    assert_eq!(allocator.live_bytes.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn test_try_with_allocator_returns_alloc_error_when_allocator_fails() {
+    assert_eq!(Compressor::try_with_allocator(CompressionLvl::default(), FailingAllocator).err(), Some(AllocError));
+    assert_eq!(Decompressor::try_with_allocator(FailingAllocator).err(), Some(AllocError));
+}
+
+#[test]
+fn test_default_allocator_is_used_again_after_a_compressor_with_allocator_is_created() {
+    let allocator = Arc::new(CountingAllocator::default());
+    let _compressor = Compressor::with_allocator(CompressionLvl::default(), allocator.clone());
+    let allocations = allocator.allocations.load(Ordering::SeqCst);
+    let _other = Compressor::new(CompressionLvl::default());
+
+    assert_eq!(allocator.allocations.load(Ordering::SeqCst), allocations);
+}
+
+
+
+// memory usage
+
+#[test]
+fn test_compressor_memory_usage_matches_estimated_memory_for_every_compression_lvl() {
+    for lvl in CompressionLvl::iter() {
+        let compressor = Compressor::new(lvl);
+        let usage = compressor.memory_usage();
+
+        assert!(usage.live > 0);
+        assert_eq!(usage.live, usage.peak);
+        assert_eq!(usage.peak, Compressor::estimated_memory(lvl));
+    }
+}
+
+// the pure-Rust backend's compressor buffers are allocated by
+// `miniz_oxide`, so its estimate doesn't depend on the level
+#[test]
+#[cfg(not(feature = "rust_backend"))]
+fn test_compressor_estimated_memory_is_larger_for_best_compression_lvl() {
+    assert!(Compressor::estimated_memory(CompressionLvl::best()) > Compressor::estimated_memory(CompressionLvl::fastest()));
+}
+
+#[test]
+fn test_decompressor_memory_usage_is_nonzero() {
+    let usage = Decompressor::new().memory_usage();
+
+    assert!(usage.live > 0);
+    assert_eq!(usage.live, usage.peak);
+}
+
+#[test]
+fn test_compressor_with_allocator_memory_usage_matches_bytes_allocated_by_allocator() {
+    let allocator = Arc::new(CountingAllocator::default());
+    let compressor = Compressor::with_allocator(CompressionLvl::default(), allocator.clone());
+
+    assert_eq!(compressor.memory_usage().live, allocator.live_bytes.load(Ordering::SeqCst));
 }
 
 
+
 // compress + decompress (full-cycle tests)
 
 #[test]
+#[allow(clippy::slow_vector_initialization)]
 fn test_zlib_compress_then_zlib_decompress_works_and_produces_the_same_input_data() {
     let input_data = read_fixture_content();
     
@@ -664,6 +2919,7 @@ fn test_zlib_compress_then_zlib_decompress_works_and_produces_the_same_input_dat
 }
 
 #[test]
+#[allow(clippy::slow_vector_initialization)]
 fn test_deflate_compress_then_deflate_decompress_works_and_produces_the_same_input_data() {
     let input_data = read_fixture_content();
     
@@ -691,6 +2947,7 @@ fn test_deflate_compress_then_deflate_decompress_works_and_produces_the_same_inp
 }
 
 #[test]
+#[allow(clippy::slow_vector_initialization)]
 fn test_gzip_compress_then_gzip_decompress_works_and_produces_the_same_input_data() {
     let input_data = read_fixture_content();
     
@@ -760,6 +3017,41 @@ fn test_use_crc32_convenience_method_returns_same_crc32_as_flate2() {
     assert_eq!(flate2_crc32, libdeflate_crc32);
 }
 
+#[test]
+fn test_crc32_combine_of_two_halves_returns_crc32_of_whole() {
+    let input_data = read_fixture_content();
+
+    for &split in &[0, 1, input_data.len() / 2, input_data.len() - 1, input_data.len()] {
+        let (a, b) = input_data.split_at(split);
+        let combined = libdeflater::crc32_combine(libdeflater::crc32(a), libdeflater::crc32(b), b.len() as u64);
+        assert_eq!(combined, libdeflater::crc32(&input_data), "split = {}", split);
+    }
+}
+
+#[test]
+fn test_crc_combine_returns_same_crc32_as_sequential_updates() {
+    let input_data = parallel_test_data();
+    let (a, b) = input_data.split_at(12345);
+
+    let mut crc_a = libdeflater::Crc::new();
+    crc_a.update(a);
+    let mut crc_b = libdeflater::Crc::new();
+    crc_b.update(b);
+    crc_a.combine(&crc_b, b.len() as u64);
+
+    assert_eq!(crc_a.sum(), libdeflater::crc32(&input_data));
+}
+
+#[test]
+fn test_parallel_crc32_of_large_data_returns_same_crc32_as_crc32() {
+    let input_data: Vec<u8> = (0..(5 * parallel::MIN_CHECKSUM_PART_SIZE + 17))
+        .map(|i| (i % 253) as u8)
+        .collect();
+
+    assert_eq!(parallel::crc32(&input_data), libdeflater::crc32(&input_data));
+    assert_eq!(parallel::crc32(&[]), libdeflater::crc32(&[]));
+}
+
 // adler32
 #[test]
 fn test_use_adler32_reader_to_compute_adler32_of_fixture_returns_same_adler32_as_adler32_crate(
@@ -802,4 +3094,41 @@ fn test_use_adler32_convenience_method_returns_same_adler32_as_adler32_crate() {
     let libdeflate_adler32 = libdeflater::adler32(&input_data);
 
     assert_eq!(crate_adler32, libdeflate_adler32);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_adler32_combine_of_two_halves_returns_adler32_of_whole() {
+    let input_data = read_fixture_content();
+
+    for &split in &[0, 1, input_data.len() / 2, input_data.len() - 1, input_data.len()] {
+        let (a, b) = input_data.split_at(split);
+        let combined = libdeflater::adler32_combine(libdeflater::adler32(a), libdeflater::adler32(b), b.len() as u64);
+        assert_eq!(combined, libdeflater::adler32(&input_data), "split = {}", split);
+    }
+}
+
+#[test]
+fn test_adler32_combine_with_long_second_sequence_returns_adler32_of_whole() {
+    // exercises the length modulo 65521 (and sums near the modulus)
+    let a = vec![0xffu8; 100_000];
+    let b = vec![0xfeu8; 200_003];
+    let whole: Vec<u8> = a.iter().chain(b.iter()).copied().collect();
+
+    let mut adler_a = libdeflater::Adler32::new();
+    adler_a.update(&a);
+    let mut adler_b = libdeflater::Adler32::new();
+    adler_b.update(&b);
+    adler_a.combine(&adler_b, b.len() as u64);
+
+    assert_eq!(adler_a.sum(), libdeflater::adler32(&whole));
+}
+
+#[test]
+fn test_parallel_adler32_of_large_data_returns_same_adler32_as_adler32() {
+    let input_data: Vec<u8> = (0..(5 * parallel::MIN_CHECKSUM_PART_SIZE + 17))
+        .map(|i| (i % 253) as u8)
+        .collect();
+
+    assert_eq!(parallel::adler32(&input_data), libdeflater::adler32(&input_data));
+    assert_eq!(parallel::adler32(&[]), libdeflater::adler32(&[]));
+}
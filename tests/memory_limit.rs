@@ -0,0 +1,37 @@
+extern crate libdeflater;
+
+use libdeflater::{AllocError, CompressionError, Compressor, CompressionLvl, DecompressionError, Decompressor, memory_limit, set_memory_limit, total_memory_usage};
+use libdeflater::pool::{CompressorPool, DecompressorPool};
+
+// The memory limit is process-wide, so it is tested in its own test
+// binary (and in a single test) so that it cannot affect other tests.
+
+#[test]
+fn test_memory_limit_makes_allocations_beyond_it_fail_cleanly() {
+    assert_eq!(memory_limit(), None);
+
+    let lvl = CompressionLvl::best();
+    let required = Compressor::estimated_memory(lvl);
+    let decompressor = Decompressor::new();
+    let usage_before = total_memory_usage();
+    assert_eq!(usage_before, decompressor.memory_usage().live);
+
+    set_memory_limit(Some(usage_before + required - 1));
+    assert_eq!(memory_limit(), Some(usage_before + required - 1));
+    assert_eq!(Compressor::try_new(lvl).err(), Some(AllocError));
+    assert_eq!(CompressorPool::new().gzip_compress_to_vec(lvl, b"hello"), Err(CompressionError::AllocationFailed));
+    assert_eq!(total_memory_usage(), usage_before);
+
+    set_memory_limit(Some(usage_before));
+    assert_eq!(DecompressorPool::new().gzip_decompress_to_vec(b"", 5), Err(DecompressionError::AllocationFailed));
+
+    set_memory_limit(Some(usage_before + required));
+    let compressor = Compressor::try_new(lvl).unwrap();
+    assert_eq!(total_memory_usage(), usage_before + required);
+
+    drop(compressor);
+    assert_eq!(total_memory_usage(), usage_before);
+
+    set_memory_limit(None);
+    assert_eq!(memory_limit(), None);
+}